@@ -0,0 +1,55 @@
+//! A benchmark comparing swapping values in place with swapping them in a `SwapBox`.
+//!
+//! Swapping in place copies the whole value in each direction, while swapping a `SwapBox`
+//! only exchanges a pointer. For each size, the time per swap is printed for both, to find
+//! the size at which boxing starts to pay off on this machine:
+//!
+//! ```text
+//! cargo run --release --example big_swap
+//! ```
+//!
+//! The number of swaps for each size can be given as an argument, and defaults to 100000.
+
+extern crate swapper;
+
+use std::env;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use swapper::SwapBox;
+use swapper::SwapperBuilder;
+use swapper::WaitStrategy;
+
+// Time a pair of threads swapping the given values back and forth.
+fn bench<T: Send + 'static>(mut ours: T, mut theirs: T, swaps: u32) -> Duration {
+    // Spin, so the time is spent swapping rather than waking threads.
+    let strategy = WaitStrategy::SpinThenPark(10_000);
+    let (ab, ba) = SwapperBuilder::new().wait_strategies(strategy, strategy).build::<T>();
+    let helper = thread::spawn(move || {
+        for _ in 0..swaps {
+            ba.swap(&mut theirs).unwrap();
+        }
+    });
+    let start = Instant::now();
+    for _ in 0..swaps {
+        ab.swap(&mut ours).unwrap();
+    }
+    let elapsed = start.elapsed();
+    helper.join().unwrap();
+    elapsed / swaps
+}
+
+macro_rules! compare {
+    ($swaps:expr; $($size:expr),*) => {
+        $(
+            let inline = bench([0u8; $size], [1u8; $size], $swaps);
+            let boxed = bench(SwapBox::new([0u8; $size]), SwapBox::new([1u8; $size]), $swaps);
+            println!("{:>6} bytes: {:>10?} inline, {:>10?} boxed", $size, inline, boxed);
+        )*
+    };
+}
+
+fn main() {
+    let swaps = env::args().nth(1).map_or(100_000, |arg| arg.parse().expect("Expected a number"));
+    compare!(swaps; 16, 32, 64, 128, 256, 512, 1024, 4096, 16384);
+}
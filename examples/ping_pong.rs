@@ -0,0 +1,57 @@
+//! A ping-pong benchmark, where several pairs of threads each swap a token back and forth.
+//!
+//! The pairs are allocated together, so without padding their shared state is packed into
+//! neighbouring cache lines, and the pairs slow each other down through false sharing.
+//! Compare the throughput with and without the `cache-padded` feature:
+//!
+//! ```text
+//! cargo run --release --example ping_pong
+//! cargo run --release --example ping_pong --no-default-features
+//! ```
+//!
+//! The number of pairs and swaps per pair can be given as arguments, and default to 4 and 100000.
+
+extern crate swapper;
+
+use std::env;
+use std::thread;
+use std::time::Instant;
+use swapper::SwapperBuilder;
+use swapper::WaitStrategy;
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse().expect("Expected a number"));
+    let pairs = args.next().unwrap_or(4);
+    let swaps = args.next().unwrap_or(100_000);
+    // Spin, so the threads contend on the shared state rather than sleeping.
+    let strategy = WaitStrategy::SpinThenPark(10_000);
+    let pairs: Vec<_> = (0..pairs)
+        .map(|_| SwapperBuilder::new().wait_strategies(strategy, strategy).build::<u64>())
+        .collect();
+    let start = Instant::now();
+    let threads: Vec<_> = pairs
+        .into_iter()
+        .flat_map(|(ab, ba)| vec![ab, ba])
+        .map(|half| {
+            thread::spawn(move || {
+                let mut token = 0;
+                for _ in 0..swaps {
+                    half.swap(&mut token).unwrap();
+                    token += 1;
+                }
+            })
+        })
+        .collect();
+    let count = threads.len() as u64 / 2 * swaps;
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "cache-padded: {}, {} swaps in {:?}, {:.0} swaps/s",
+        cfg!(feature = "cache-padded"),
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,35 @@
+/* C API for the swapper crate, built with the `ffi` feature. */
+
+#ifndef SWAPPER_H
+#define SWAPPER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SWAPPER_OK 0
+#define SWAPPER_DISCONNECTED 1
+#define SWAPPER_WOULD_DEADLOCK 2
+#define SWAPPER_NULL (-1)
+#define SWAPPER_ERROR (-2)
+
+/* One half of a swap pair. */
+typedef struct swapper swapper_t;
+
+/* Create a new pair of swappers, storing the halves in `a` and `b`. */
+int swapper_new(swapper_t** a, swapper_t** b);
+
+/* Swap the pointer stored in `value` with the other half of the pair,
+   blocking until the other half also swaps. The same half must not be used
+   concurrently from two threads, but each half may be used from a different thread. */
+int swapper_swap(swapper_t* swapper, void** value);
+
+/* Free one half of a pair, which no other thread may be swapping with.
+   Freeing a null pointer does nothing. */
+void swapper_free(swapper_t* swapper);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,99 @@
+//! Type-erased swappers, for when the type being swapped is not known statically.
+
+use std::any;
+use std::any::Any;
+use std::any::TypeId;
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// The data offered by an `AnySwapper`.
+trait AnyValue: Any + Send {
+    fn value_type_id(&self) -> TypeId;
+    fn value_type_name(&self) -> &'static str;
+}
+
+impl<T: Any + Send> AnyValue for T {
+    fn value_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn value_type_name(&self) -> &'static str {
+        any::type_name::<T>()
+    }
+}
+
+/// A swapper which can swap data of any type.
+///
+/// Each swap checks that both halves offered data of the same type. If they did not,
+/// then neither is modified and both calls return `SwapError::TypeMismatch`.
+///
+/// ```rust
+/// # use std::any::Any;
+/// # use std::thread;
+/// let (ab, ba) = swapper::any_swapper();
+/// let helper = thread::spawn(move || {
+///     let mut plugin: Box<dyn Any + Send> = Box::new(37);
+///     ab.swap(&mut plugin).unwrap();
+///     assert_eq!(plugin.downcast_ref::<&str>(), Some(&"hello"));
+/// });
+/// let mut plugin: Box<dyn Any + Send> = Box::new("hello");
+/// ba.swap(&mut plugin).unwrap();
+/// assert_eq!(plugin.downcast_ref::<i32>(), Some(&37));
+/// # helper.join().unwrap();
+/// ```
+pub struct AnySwapper {
+    swapper: Swapper<dyn AnyValue>,
+}
+
+impl AnySwapper {
+    /// Swap boxed data of any type with the other half.
+    ///
+    /// The other half must also swap a `Box<dyn Any + Send>`, but the boxes may
+    /// contain data of different types.
+    pub fn swap(&self, our_ref: &mut Box<dyn Any + Send>) -> Result<(), SwapError> {
+        self.swap_as(our_ref)
+    }
+
+    /// Swap data of type `T` with the other half, which must also offer a `T`.
+    pub fn swap_as<T: Any + Send>(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.swapper.rendezvous(our_ref, |our_ptr, their_ptr| {
+            let (ours, theirs) = unsafe { (our_ptr.as_ref(), their_ptr.as_ref()) };
+            if ours.value_type_id() != theirs.value_type_id() {
+                let ours = ours.value_type_name();
+                let theirs = theirs.value_type_name();
+                return (
+                    Err(SwapError::TypeMismatch { ours, theirs }),
+                    Err(SwapError::TypeMismatch { ours: theirs, theirs: ours }),
+                );
+            }
+            // The data has the same type, so can be exchanged byte-wise.
+            let size = mem::size_of::<T>();
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr().cast::<u8>(), their_ptr.as_ptr().cast::<u8>(), size) };
+            (Ok(()), Ok(()))
+        })
+    }
+
+    /// The unique id of this swap pair, shared by both halves.
+    pub fn id(&self) -> u64 {
+        self.swapper.id()
+    }
+}
+
+impl fmt::Debug for AnySwapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnySwapper")
+            .field("swapper", &self.swapper)
+            .finish()
+    }
+}
+
+/// Create a new pair of type-erased swappers.
+pub fn any_swapper() -> (AnySwapper, AnySwapper) {
+    let (swapper_a, swapper_b) = swapper::<dyn AnyValue>();
+    (AnySwapper { swapper: swapper_a }, AnySwapper { swapper: swapper_b })
+}
@@ -0,0 +1,87 @@
+//! Swapping large values by swapping pointers to them.
+
+use std::fmt;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+use Swapper;
+use swapper;
+
+/// A heap-allocated value, which is swapped by swapping its pointer.
+///
+/// A swap exchanges the data of the two halves byte-wise, so swapping a `T` copies
+/// `size_of::<T>()` bytes in each direction while the other half is blocked. A large value
+/// can instead be allocated once in a `SwapBox`, after which each swap exchanges one
+/// pointer, however large the value. For small values, the extra allocation and indirection
+/// is not worth it. Where the crossover lies depends on the machine, and on how long the
+/// halves wait for each other, so it is worth measuring with the `big_swap` example.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::SwapBox;
+/// let (ab, ba) = swapper::big_swapper::<[u8; 1 << 16]>();
+/// let helper = thread::spawn(move || {
+///     let mut frame = SwapBox::new([1; 1 << 16]);
+///     ab.swap(&mut frame).unwrap();
+///     assert_eq!(frame[0], 2);
+/// });
+/// let mut frame = SwapBox::new([2; 1 << 16]);
+/// ba.swap(&mut frame).unwrap();
+/// assert_eq!(frame[0], 1);
+/// # helper.join().unwrap();
+/// ```
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct SwapBox<T: ?Sized>(Box<T>);
+
+/// A swapper for large values, which swaps them by pointer.
+pub type BigSwap<T> = Swapper<SwapBox<T>>;
+
+/// Create a new pair of swappers for large values.
+pub fn big_swapper<T: ?Sized>() -> (BigSwap<T>, BigSwap<T>) {
+    swapper()
+}
+
+impl<T> SwapBox<T> {
+    /// Move a value to the heap.
+    pub fn new(value: T) -> SwapBox<T> {
+        SwapBox(Box::new(value))
+    }
+
+    /// Move the value back from the heap.
+    pub fn into_inner(self) -> T {
+        *self.0
+    }
+}
+
+impl<T: ?Sized> SwapBox<T> {
+    /// Convert this into the underlying box.
+    pub fn into_box(self) -> Box<T> {
+        self.0
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for SwapBox<T> {
+    fn from(boxed: Box<T>) -> SwapBox<T> {
+        SwapBox(boxed)
+    }
+}
+
+impl<T: ?Sized> Deref for SwapBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> DerefMut for SwapBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SwapBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
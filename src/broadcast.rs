@@ -0,0 +1,364 @@
+//! Swapping between one leader and many workers.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::Ordering;
+
+use CancelToken;
+use CollectError;
+use SwapError;
+use Swapper;
+use swapper;
+
+/// The leader's end of a broadcast swap with many workers.
+///
+/// Each worker has an ordinary `Swapper`, and the leader swaps with all of them at once.
+/// For example, the leader can push a new configuration to every worker, and get back
+/// their old configurations for disposal:
+///
+/// ```rust
+/// # use std::thread;
+/// let (mut leader, workers) = swapper::broadcast(3);
+/// let helpers: Vec<_> = workers.into_iter().map(|worker| thread::spawn(move || {
+///     let mut config = String::from("old");
+///     worker.swap(&mut config).unwrap();
+///     assert_eq!(config, "new");
+/// })).collect();
+/// let old = leader.swap_each(vec![String::from("new"); 3]).unwrap();
+/// assert_eq!(old, ["old", "old", "old"]);
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+pub struct BroadcastSwap<T> {
+    leaders: Vec<Swapper<T>>,
+}
+
+/// The coordinator's end of a gather swap with many workers.
+///
+/// Unlike a `BroadcastSwap`, where the leader swaps with each worker as it arrives,
+/// the coordinator waits until every worker has arrived, then swaps with all of them
+/// together. Since every worker is blocked while the swaps happen, the values the
+/// coordinator gets back are a consistent snapshot, all from the same round:
+///
+/// ```rust
+/// # use std::thread;
+/// let (mut coordinator, workers) = swapper::gather(3);
+/// let helpers: Vec<_> = workers.into_iter().enumerate().map(|(index, worker)| thread::spawn(move || {
+///     let mut token = index;
+///     for round in 1..4 {
+///         worker.swap(&mut token).unwrap();
+///         assert_eq!(token, round * 10 + index);
+///     }
+/// })).collect();
+/// for round in 1..4 {
+///     let tokens = (0..3).map(|index| round * 10 + index).collect();
+///     let previous = coordinator.gather(tokens).unwrap();
+///     assert_eq!(previous, (0..3).map(|index| (round - 1) * 10 + index).collect::<Vec<_>>());
+/// }
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+pub struct GatherSwap<T> {
+    leaders: Vec<Swapper<T>>,
+    arrivals: Arc<Arrivals>,
+}
+
+/// A worker's end of a gather swap.
+pub struct GatherWorker<T> {
+    swapper: Swapper<T>,
+    index: usize,
+    arrivals: Arc<Arrivals>,
+}
+
+/// Which workers are waiting to swap, which the coordinator waits on.
+struct Arrivals {
+    state: Mutex<ArrivalState>,
+    condvar: Condvar,
+    // Cancelled on shutdown, to unblock the swaps between the coordinator and the workers.
+    cancel: CancelToken,
+}
+
+struct ArrivalState {
+    arrived: Vec<bool>,
+    dropped: Vec<bool>,
+    shutdown: bool,
+}
+
+/// The error returned when some of the workers in a broadcast swap failed to swap.
+#[derive(Debug, Eq, PartialEq)]
+pub struct BroadcastError<T> {
+    /// For each worker, the value it swapped in, or the leader's value if it failed to swap.
+    pub values: Vec<T>,
+    /// The index of each worker that failed to swap, and the reason it failed.
+    pub failures: Vec<(usize, SwapError)>,
+}
+
+/// Create a broadcast swap with the given number of workers.
+pub fn broadcast<T>(workers: usize) -> (BroadcastSwap<T>, Vec<Swapper<T>>) {
+    let (leaders, workers) = (0..workers).map(|_| swapper()).unzip();
+    (BroadcastSwap { leaders }, workers)
+}
+
+/// Create a gather swap with the given number of workers.
+pub fn gather<T>(workers: usize) -> (GatherSwap<T>, Vec<GatherWorker<T>>) {
+    let (leaders, swappers): (Vec<_>, Vec<_>) = (0..workers).map(|_| swapper()).unzip();
+    let arrivals = Arc::new(Arrivals {
+        state: Mutex::new(ArrivalState {
+            arrived: vec![false; workers],
+            dropped: vec![false; workers],
+            shutdown: false,
+        }),
+        condvar: Condvar::new(),
+        cancel: CancelToken::new(),
+    });
+    let workers = swappers
+        .into_iter()
+        .enumerate()
+        .map(|(index, swapper)| GatherWorker {
+            swapper,
+            index,
+            arrivals: arrivals.clone(),
+        })
+        .collect();
+    (GatherSwap { leaders, arrivals }, workers)
+}
+
+impl<T: Send> BroadcastSwap<T> {
+    /// The number of workers.
+    pub fn len(&self) -> usize {
+        self.leaders.len()
+    }
+
+    /// Is the number of workers zero?
+    pub fn is_empty(&self) -> bool {
+        self.leaders.is_empty()
+    }
+
+    /// Swap each value with the corresponding worker, returning the workers' values.
+    ///
+    /// The values are offered to every worker at once, so the workers can swap in any order.
+    /// This blocks until every worker has swapped, or failed to swap. If any fail, the
+    /// leader keeps its own values for them, and the error reports which workers failed.
+    ///
+    /// # Panics
+    ///
+    /// If the number of values is not the number of workers.
+    pub fn swap_each(&mut self, values: Vec<T>) -> Result<Vec<T>, BroadcastError<T>> {
+        assert_eq!(values.len(), self.leaders.len(), "Expected one value per worker");
+        swap_all(&mut self.leaders, values)
+    }
+
+    /// Shut down the broadcast, so every worker waiting to swap, and every later swap
+    /// by a worker, fails with `SwapError::Shutdown`.
+    ///
+    /// This consumes the leader, since it can no longer swap.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::SwapError;
+    /// let (leader, workers) = swapper::broadcast(2);
+    /// let helpers: Vec<_> = workers.into_iter().map(|worker| thread::spawn(move || worker.swap(&mut 0))).collect();
+    /// leader.shutdown();
+    /// for helper in helpers {
+    ///     assert_eq!(helper.join().unwrap(), Err(SwapError::Shutdown));
+    /// }
+    /// ```
+    pub fn shutdown(self) {
+        // Dropping the leaders disconnects the workers, which then report the shutdown.
+        for leader in &self.leaders {
+            leader.shared.shutdown.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl<T: Send> GatherSwap<T> {
+    /// The number of workers.
+    pub fn len(&self) -> usize {
+        self.leaders.len()
+    }
+
+    /// Is the number of workers zero?
+    pub fn is_empty(&self) -> bool {
+        self.leaders.is_empty()
+    }
+
+    /// Wait for every worker to arrive, then swap each value with the corresponding worker,
+    /// returning the workers' values.
+    ///
+    /// If a worker has been dropped, no values are swapped, since the coordinator could no
+    /// longer get a consistent set of values back. The error then contains the coordinator's
+    /// own values, and reports the dropped workers as `SwapError::Disconnected`. Similarly,
+    /// if the gather is shut down, the error reports every worker as `SwapError::Shutdown`,
+    /// except any which swapped before the shutdown.
+    ///
+    /// # Panics
+    ///
+    /// If the number of values is not the number of workers.
+    pub fn gather(&mut self, values: Vec<T>) -> Result<Vec<T>, BroadcastError<T>> {
+        assert_eq!(values.len(), self.leaders.len(), "Expected one value per worker");
+        let mut state = self.arrivals.lock();
+        loop {
+            if state.shutdown {
+                let failures = (0..values.len()).map(|index| (index, SwapError::Shutdown)).collect();
+                return Err(BroadcastError { values, failures });
+            }
+            if state.dropped.contains(&true) {
+                let failures = (0..state.dropped.len())
+                    .filter(|&index| state.dropped[index])
+                    .map(|index| (index, SwapError::Disconnected))
+                    .collect();
+                return Err(BroadcastError { values, failures });
+            }
+            if !state.arrived.contains(&false) {
+                break;
+            }
+            state = self.arrivals.condvar.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+        // Every worker is now blocked swapping, so reset for the next round before swapping.
+        for arrived in &mut state.arrived {
+            *arrived = false;
+        }
+        drop(state);
+        // Since every worker is blocked, each swap completes at once, unless we are shut down.
+        let mut values = values;
+        let mut failures = Vec::new();
+        for (index, (leader, value)) in self.leaders.iter().zip(&mut values).enumerate() {
+            if let Err(err) = leader.swap_with_cancel(value, &self.arrivals.cancel) {
+                failures.push((index, shutdown_error(err)));
+            }
+        }
+        if failures.is_empty() {
+            Ok(values)
+        } else {
+            Err(BroadcastError { values, failures })
+        }
+    }
+
+    /// Shut down the gather, so the coordinator and every worker waiting to swap,
+    /// and every later swap, fails with `SwapError::Shutdown`.
+    pub fn shutdown(&self) {
+        self.arrivals.shutdown();
+    }
+}
+
+impl<T: Send> GatherWorker<T> {
+    /// Swap with the coordinator, once it has gathered every worker.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.arrive(true);
+        let result = self.swapper.swap_with_cancel(our_ref, &self.arrivals.cancel);
+        if result.is_err() {
+            self.arrive(false);
+        }
+        result.map_err(shutdown_error)
+    }
+
+    /// Shut down the gather, so the coordinator and every worker waiting to swap,
+    /// and every later swap, fails with `SwapError::Shutdown`.
+    ///
+    /// Unlike `GatherSwap::shutdown`, this can unblock a coordinator waiting in `gather`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::SwapError;
+    /// let (mut coordinator, mut workers) = swapper::gather(2);
+    /// let (first, second) = (workers.remove(0), workers.remove(0));
+    /// let helper = thread::spawn(move || first.swap(&mut 1));
+    /// second.shutdown();
+    /// let error = coordinator.gather(vec![3, 4]).unwrap_err();
+    /// assert_eq!(error.failures, [(0, SwapError::Shutdown), (1, SwapError::Shutdown)]);
+    /// assert_eq!(helper.join().unwrap(), Err(SwapError::Shutdown));
+    /// ```
+    pub fn shutdown(&self) {
+        self.arrivals.shutdown();
+    }
+}
+
+impl<T> GatherWorker<T> {
+    /// The index of this worker, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    fn arrive(&self, arrived: bool) {
+        self.arrivals.lock().arrived[self.index] = arrived;
+        self.arrivals.condvar.notify_all();
+    }
+}
+
+impl Arrivals {
+    fn lock(&self) -> MutexGuard<'_, ArrivalState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn shutdown(&self) {
+        self.lock().shutdown = true;
+        self.cancel.cancel();
+        self.condvar.notify_all();
+    }
+}
+
+/// The swaps of a gather are only cancelled when it is shut down.
+fn shutdown_error(err: SwapError) -> SwapError {
+    match err {
+        SwapError::Cancelled => SwapError::Shutdown,
+        err => err,
+    }
+}
+
+impl<T> Drop for GatherWorker<T> {
+    fn drop(&mut self) {
+        self.arrivals.lock().dropped[self.index] = true;
+        self.arrivals.condvar.notify_all();
+    }
+}
+
+/// Swap each value with the corresponding worker, offering them all at once.
+fn swap_all<T: Send>(leaders: &mut [Swapper<T>], values: Vec<T>) -> Result<Vec<T>, BroadcastError<T>> {
+    let pending: Vec<_> = leaders
+        .iter_mut()
+        .zip(values)
+        .map(|(leader, value)| leader.deposit(value))
+        .collect();
+    let mut values = Vec::with_capacity(pending.len());
+    let mut failures = Vec::new();
+    for (index, pending) in pending.into_iter().enumerate() {
+        match pending.collect() {
+            Ok(value) => values.push(value),
+            Err(CollectError(value, err)) => {
+                values.push(value);
+                failures.push((index, err));
+            }
+        }
+    }
+    if failures.is_empty() {
+        Ok(values)
+    } else {
+        Err(BroadcastError { values, failures })
+    }
+}
+
+impl<T> fmt::Debug for BroadcastSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BroadcastSwap")
+            .field("leaders", &self.leaders)
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for GatherSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GatherSwap")
+            .field("leaders", &self.leaders)
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for GatherWorker<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GatherWorker")
+            .field("index", &self.index)
+            .field("swapper", &self.swapper)
+            .finish()
+    }
+}
@@ -0,0 +1,159 @@
+//! Buffered swappers, which can swap without waiting for the other half.
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+
+use PartnerState;
+use SwapError;
+
+/// One half of a buffered swap pair.
+///
+/// The pair shares a one-deep buffer, which always holds one value. A swap exchanges our
+/// value with the one in the buffer, so succeeds immediately if the buffered value was
+/// left by the other half, or is the initial value. If the buffered value is the one we
+/// left last time, the other half has not swapped since, so this blocks until it does.
+///
+/// This is weaker than the rendezvous of `Swapper`, since each half may be one swap
+/// ahead of the other, but it allows both halves to make progress without waiting.
+///
+/// ```rust
+/// # use std::thread;
+/// let (producer, consumer) = swapper::buffered_swapper(Vec::new());
+/// let helper = thread::spawn(move || {
+///     let mut buffer = Vec::new();
+///     for frame in 0..3 {
+///         buffer.clear();
+///         buffer.push(frame);
+///         producer.swap(&mut buffer).unwrap();
+///     }
+/// });
+/// let mut buffer = Vec::new();
+/// let mut frames = Vec::new();
+/// while frames.len() < 3 {
+///     consumer.swap(&mut buffer).unwrap();
+///     frames.extend(buffer.drain(..));
+/// }
+/// assert_eq!(frames, [0, 1, 2]);
+/// # helper.join().unwrap();
+/// ```
+pub struct BufferedSwapper<T> {
+    shared: Arc<Shared<T>>,
+    side: Side,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Side {
+    A,
+    B,
+}
+
+struct Shared<T> {
+    buffer: Mutex<Buffer<T>>,
+    condvar: Condvar,
+}
+
+struct Buffer<T> {
+    value: T,
+    // The half which left the value, or `None` for the initial value.
+    owner: Option<Side>,
+    disconnected: bool,
+}
+
+/// Create a new pair of buffered swappers, with the given initial value in the buffer.
+pub fn buffered_swapper<T>(initial: T) -> (BufferedSwapper<T>, BufferedSwapper<T>) {
+    let shared = Arc::new(Shared {
+        buffer: Mutex::new(Buffer {
+            value: initial,
+            owner: None,
+            disconnected: false,
+        }),
+        condvar: Condvar::new(),
+    });
+    let swapper_a = BufferedSwapper {
+        shared: shared.clone(),
+        side: Side::A,
+    };
+    let swapper_b = BufferedSwapper {
+        shared,
+        side: Side::B,
+    };
+    (swapper_a, swapper_b)
+}
+
+impl<T: Send> BufferedSwapper<T> {
+    /// Swap data with the buffer.
+    ///
+    /// If the buffer holds the value we left last time, this blocks until the other half
+    /// has swapped, or returns `SwapError::Disconnected` if the other half has been dropped.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.swap_until(our_ref, None)
+    }
+
+    /// Swap data with the buffer, giving up if the other half does not swap within the timeout.
+    ///
+    /// On timeout the data is unchanged, and the error reports `PartnerState::NeverArrived`,
+    /// since the other half has not swapped since we last did.
+    pub fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        self.swap_until(our_ref, Some(Instant::now() + timeout))
+    }
+
+    fn swap_until(&self, our_ref: &mut T, deadline: Option<Instant>) -> Result<(), SwapError> {
+        let mut buffer = self.lock();
+        while buffer.owner == Some(self.side) {
+            if buffer.disconnected {
+                return Err(SwapError::Disconnected);
+            }
+            buffer = match deadline {
+                None => self.shared.condvar.wait(buffer).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(SwapError::Timeout {
+                            partner: PartnerState::NeverArrived,
+                        });
+                    }
+                    self.shared.condvar.wait_timeout(buffer, deadline - now).unwrap().0
+                }
+            };
+        }
+        mem::swap(&mut buffer.value, our_ref);
+        buffer.owner = Some(self.side);
+        self.shared.condvar.notify_all();
+        Ok(())
+    }
+}
+
+impl<T> BufferedSwapper<T> {
+    /// Is there a value left by the other half, or the initial value, waiting in the buffer?
+    ///
+    /// If so, the next swap will not block.
+    pub fn is_ready(&self) -> bool {
+        self.lock().owner != Some(self.side)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buffer<T>> {
+        self.shared.buffer.lock().unwrap()
+    }
+}
+
+impl<T> Drop for BufferedSwapper<T> {
+    fn drop(&mut self) {
+        self.lock().disconnected = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for BufferedSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufferedSwapper")
+            .field("side", &self.side)
+            .field("ready", &self.is_ready())
+            .finish()
+    }
+}
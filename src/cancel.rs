@@ -0,0 +1,215 @@
+//! Cancelling swaps which are blocked waiting for the other half.
+
+use std::fmt;
+#[cfg(feature = "tokio-util")]
+use std::future::Future;
+#[cfg(feature = "tokio-util")]
+use std::pin::Pin;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::task;
+use std::task::Wake;
+use std::thread;
+use std::thread::Thread;
+
+use Offer;
+use SwapError;
+use Swapper;
+use wake::TaskWaker;
+
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::CancellationToken;
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::WaitForCancellationFutureOwned;
+
+/// A token which cancels swaps, for shutting down threads blocked in `swap_with_cancel`.
+///
+/// Clones of a token share its state, so cancelling one clone cancels them all.
+/// Once cancelled, a token stays cancelled.
+///
+/// Other cancellation mechanisms can be bridged to this one by cancelling the token
+/// when they fire. With the `tokio-util` feature, a `tokio_util::sync::CancellationToken`
+/// can be converted into a token which is also cancelled when it is.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    waiters: Mutex<Waiters>,
+    // A tokio token which also cancels this one.
+    #[cfg(feature = "tokio-util")]
+    linked: Option<CancellationToken>,
+}
+
+// The wakers of the threads blocked on the token, with ids so they can deregister.
+#[derive(Default)]
+struct Waiters {
+    next: u64,
+    wakers: Vec<(u64, task::Waker)>,
+}
+
+// Deregisters a waker when the swap is over.
+struct Registration<'a> {
+    token: &'a CancelToken,
+    id: u64,
+    // Waiting for the linked tokio token, which wakes the waker when it is cancelled.
+    #[cfg(feature = "tokio-util")]
+    _linked: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+// Unparks a thread blocked in `swap_with_cancel`.
+struct Unparker(Thread);
+
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark()
+    }
+}
+
+impl CancelToken {
+    /// Create a new token, which has not been cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancel the token, unblocking any swaps waiting on it.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let wakers = {
+            let mut waiters = self.inner.waiters.lock().unwrap_or_else(|err| err.into_inner());
+            waiters.wakers.split_off(0)
+        };
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Has the token been cancelled?
+    pub fn is_cancelled(&self) -> bool {
+        #[cfg(feature = "tokio-util")]
+        if self.inner.linked.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return true;
+        }
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Register a waker, which is woken when the token is cancelled.
+    ///
+    /// The waker is registered before checking whether the token has been cancelled,
+    /// so a cancellation after the check is not lost.
+    fn register<'a>(&'a self, waker: task::Waker) -> Registration<'a> {
+        // Polling the linked token's future registers the waker with it, and the woken
+        // thread then finds the token cancelled.
+        #[cfg(feature = "tokio-util")]
+        let linked = self.inner.linked.as_ref().map(|linked| {
+            let mut cancelled = Box::pin(linked.clone().cancelled_owned());
+            let _ = cancelled.as_mut().poll(&mut task::Context::from_waker(&waker));
+            cancelled
+        });
+        let mut waiters = self.inner.waiters.lock().unwrap_or_else(|err| err.into_inner());
+        let id = waiters.next;
+        waiters.next += 1;
+        waiters.wakers.push((id, waker));
+        Registration {
+            token: self,
+            id,
+            #[cfg(feature = "tokio-util")]
+            _linked: linked,
+        }
+    }
+}
+
+impl<'a> Drop for Registration<'a> {
+    fn drop(&mut self) {
+        let mut waiters = self.token.inner.waiters.lock().unwrap_or_else(|err| err.into_inner());
+        waiters.wakers.retain(|&(id, _)| id != self.id);
+    }
+}
+
+/// A token which is cancelled when the tokio token is, or when it is cancelled itself.
+///
+/// ```rust
+/// # extern crate swapper;
+/// # extern crate tokio_util;
+/// # use std::thread;
+/// # use swapper::{CancelToken, SwapError};
+/// # use tokio_util::sync::CancellationToken;
+/// let shutdown = CancellationToken::new();
+/// let token = CancelToken::from(shutdown.clone());
+/// let (ab, ba) = swapper::swapper();
+/// let helper = thread::spawn(move || ab.swap_with_cancel(&mut 1, &token));
+/// // The other half never swaps, so shut the helper down.
+/// shutdown.cancel();
+/// assert_eq!(helper.join().unwrap(), Err(SwapError::Cancelled));
+/// # drop(ba);
+/// ```
+#[cfg(feature = "tokio-util")]
+impl From<CancellationToken> for CancelToken {
+    fn from(linked: CancellationToken) -> CancelToken {
+        CancelToken {
+            inner: Arc::new(Inner {
+                linked: Some(linked),
+                ..Inner::default()
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl<T: Send> Swapper<T> {
+    /// Swap data, giving up if the token is cancelled first.
+    ///
+    /// This lets shutdown logic unblock a thread waiting for a partner which will never come.
+    /// If the token is cancelled before the other half takes the offer, it is retracted,
+    /// and this returns `SwapError::Cancelled`, leaving the data unchanged. If the other
+    /// half has already started swapping, the swap completes.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::{CancelToken, SwapError};
+    /// let (ab, ba) = swapper::swapper();
+    /// let token = CancelToken::new();
+    /// let shutdown = token.clone();
+    /// let helper = thread::spawn(move || {
+    ///     let mut data = 1;
+    ///     assert_eq!(ab.swap_with_cancel(&mut data, &token), Err(SwapError::Cancelled));
+    ///     assert_eq!(data, 1);
+    /// });
+    /// // The other half never swaps, so shut the helper down.
+    /// shutdown.cancel();
+    /// helper.join().unwrap();
+    /// # drop(ba);
+    /// ```
+    pub fn swap_with_cancel(&self, our_ref: &mut T, token: &CancelToken) -> Result<(), SwapError> {
+        if token.is_cancelled() {
+            return Err(SwapError::Cancelled);
+        }
+        let unparker = task::Waker::from(Arc::new(Unparker(thread::current())));
+        let task = Arc::new(TaskWaker::new());
+        task.register(&unparker);
+        let _registration = token.register(unparker);
+        let our_offer = Offer {
+            task: Some(task),
+            cancel: Some(token.clone()),
+            ..Offer::new(NonNull::from(our_ref))
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })
+    }
+}
@@ -0,0 +1,173 @@
+//! Hot-swapping read-mostly data, such as configuration, held by many readers.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use BroadcastError;
+use CollectError;
+use Swapper;
+use swapper;
+
+/// The writer's end of a hot-swappable configuration, held by many readers.
+///
+/// Each reader holds its own copy of the configuration, which it can read without any
+/// synchronization, and calls `ConfigReader::checkpoint` whenever it is safe to change
+/// version, for example between requests. The writer publishes a new version with
+/// `publish`, which each reader picks up at its next checkpoint, trading in its old version.
+/// The writer gets back every old version, so can dispose of them, and every reader always
+/// holds a version of the configuration, never nothing.
+///
+/// ```rust
+/// # use std::thread;
+/// let (mut writer, readers) = swapper::config_swap(3, String::from("v1"));
+/// let helpers: Vec<_> = readers.into_iter().map(|mut reader| thread::spawn(move || {
+///     assert_eq!(*reader, "v1");
+///     while !reader.checkpoint() {
+///         thread::yield_now();
+///     }
+///     assert_eq!(*reader, "v2");
+/// })).collect();
+/// let old = writer.publish(String::from("v2")).unwrap();
+/// assert_eq!(old, ["v1", "v1", "v1"]);
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+pub struct ConfigSwap<T> {
+    writers: Vec<Swapper<T>>,
+    published: Arc<AtomicU64>,
+}
+
+/// A reader's copy of a hot-swappable configuration.
+///
+/// The reader's current version can be read through `Deref`.
+pub struct ConfigReader<T> {
+    swapper: Swapper<T>,
+    config: T,
+    version: u64,
+    published: Arc<AtomicU64>,
+}
+
+/// Create a hot-swappable configuration with the given number of readers,
+/// each of which starts with a copy of the initial version.
+pub fn config_swap<T: Clone>(readers: usize, initial: T) -> (ConfigSwap<T>, Vec<ConfigReader<T>>) {
+    let published = Arc::new(AtomicU64::new(0));
+    let (writers, swappers): (Vec<_>, Vec<_>) = (0..readers).map(|_| swapper()).unzip();
+    let readers = swappers
+        .into_iter()
+        .map(|swapper| ConfigReader {
+            swapper,
+            config: initial.clone(),
+            version: 0,
+            published: published.clone(),
+        })
+        .collect();
+    (ConfigSwap { writers, published }, readers)
+}
+
+impl<T: Clone + Send> ConfigSwap<T> {
+    /// Publish a new version of the configuration to every reader, returning their old versions.
+    ///
+    /// Each reader is given a copy of the new version, and this blocks until every reader
+    /// has picked it up at a checkpoint. If a reader has been dropped, the error contains
+    /// the new version in its place, and reports the reader as `SwapError::Disconnected`,
+    /// but the other readers still get the new version.
+    pub fn publish(&mut self, config: T) -> Result<Vec<T>, BroadcastError<T>> {
+        // Make the copies first, so a panicking `clone` does not leave a version half-published.
+        let copies = vec![config; self.writers.len()];
+        let pending: Vec<_> = self
+            .writers
+            .iter_mut()
+            .zip(copies)
+            .map(|(writer, copy)| writer.deposit(copy))
+            .collect();
+        // Every copy has been deposited, so readers which see the new version can swap without blocking.
+        self.published.fetch_add(1, Ordering::Release);
+        let mut values = Vec::with_capacity(pending.len());
+        let mut failures = Vec::new();
+        for (index, pending) in pending.into_iter().enumerate() {
+            match pending.collect() {
+                Ok(value) => values.push(value),
+                Err(CollectError(value, err)) => {
+                    values.push(value);
+                    failures.push((index, err));
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(values)
+        } else {
+            Err(BroadcastError { values, failures })
+        }
+    }
+}
+
+impl<T> ConfigSwap<T> {
+    /// The number of readers.
+    pub fn len(&self) -> usize {
+        self.writers.len()
+    }
+
+    /// Is the number of readers zero?
+    pub fn is_empty(&self) -> bool {
+        self.writers.is_empty()
+    }
+
+    /// The number of versions published, not counting the initial one.
+    pub fn version(&self) -> u64 {
+        self.published.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Send> ConfigReader<T> {
+    /// Pick up a new version of the configuration, if one has been published,
+    /// returning whether the version changed.
+    ///
+    /// This never blocks, and is cheap if there is no new version, so can be called often.
+    pub fn checkpoint(&mut self) -> bool {
+        let published = self.published.load(Ordering::Acquire);
+        if published == self.version {
+            return false;
+        }
+        // The writer deposited the new version before publishing it, so this does not block.
+        if self.swapper.swap(&mut self.config).is_err() {
+            return false;
+        }
+        self.version = published;
+        true
+    }
+}
+
+impl<T> ConfigReader<T> {
+    /// The number of versions published before the one this reader holds.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<T> Deref for ConfigReader<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.config
+    }
+}
+
+impl<T> fmt::Debug for ConfigSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConfigSwap")
+            .field("readers", &self.writers.len())
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ConfigReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConfigReader")
+            .field("version", &self.version)
+            .field("config", &self.config)
+            .finish()
+    }
+}
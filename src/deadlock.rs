@@ -0,0 +1,160 @@
+//! Detecting deadlocks between swap pairs, for debugging.
+//!
+//! Enabled by the `deadlock-detection` feature. Each half of a pair is attributed to the
+//! thread which last swapped with it, and each thread blocked waiting to swap is recorded
+//! in a global wait graph. If a thread is about to block waiting for a pair whose other
+//! half is attributed to a thread which is, transitively, waiting for this thread,
+//! then the threads in the cycle may never make progress, so the cycle is reported,
+//! by default to standard error, or to the function passed to `set_reporter`.
+//!
+//! Since halves are only attributed to threads when they swap, a cycle can only be
+//! detected once every half in it has swapped at least once. A half which is sent to
+//! another thread stays attributed to the thread which last swapped with it, until it
+//! swaps again, so a cycle through such a half may be a false positive. That is why a
+//! cycle is reported, and the thread keeps waiting, rather than the swap failing.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::panic;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::ThreadId;
+
+#[derive(Default)]
+struct Graph {
+    // The thread which last swapped with each half of each pair.
+    owners: HashMap<(u64, usize), ThreadId>,
+    // The half each thread is blocked waiting to swap with.
+    waiting: HashMap<ThreadId, (u64, usize)>,
+}
+
+static GRAPH: Mutex<Option<Graph>> = Mutex::new(None);
+
+static REPORTER: Mutex<Option<fn(&str)>> = Mutex::new(None);
+
+/// Set the function called with a description of each possible deadlock detected.
+///
+/// The function is called on the thread which is about to wait, with its offer to swap
+/// already made, so it cannot stop the thread from waiting. If it panics, the panic is
+/// caught, and the thread waits anyway.
+///
+/// ```rust
+/// fn report(cycle: &str) {
+///     eprintln!("Possible deadlock: {}", cycle);
+/// }
+/// swapper::deadlock::set_reporter(report);
+/// ```
+pub fn set_reporter(reporter: fn(&str)) {
+    *REPORTER.lock().unwrap_or_else(|err| err.into_inner()) = Some(reporter);
+}
+
+fn report(cycle: &str) {
+    let reporter = *REPORTER.lock().unwrap_or_else(|err| err.into_inner());
+    match reporter {
+        Some(reporter) => {
+            let _ = panic::catch_unwind(|| reporter(cycle));
+        }
+        None => eprintln!("Possible deadlock detected: {}", cycle),
+    }
+}
+
+fn with_graph<R>(f: impl FnOnce(&mut Graph) -> R) -> R {
+    let mut graph = GRAPH.lock().unwrap_or_else(|err| err.into_inner());
+    f(graph.get_or_insert_with(Graph::default))
+}
+
+/// A record that the current thread is waiting, which is removed when dropped.
+pub(crate) struct Waiting;
+
+/// Attribute a half of a pair to the current thread.
+pub(crate) fn used(pair: u64, half: usize) {
+    with_graph(|graph| graph.owners.insert((pair, half), thread::current().id()));
+}
+
+/// Forget a half of a pair, which has been dropped.
+pub(crate) fn dropped(pair: u64, half: usize) {
+    with_graph(|graph| graph.owners.remove(&(pair, half)));
+}
+
+/// Forget that a thread is waiting, since its offer has been taken.
+///
+/// This is done by the thread taking the offer, since the waiting thread may not
+/// run again for a while, and until then it would appear to still be waiting.
+pub(crate) fn woken(thread: Option<ThreadId>) {
+    if let Some(thread) = thread {
+        with_graph(|graph| graph.waiting.remove(&thread));
+    }
+}
+
+/// Record that the current thread is about to wait for the other half of a pair.
+///
+/// If this closes a cycle in the wait graph, report the cycle, once the graph is unlocked.
+/// The offer may already have been taken, in which case the thread which took it may
+/// already have called `woken`, so `offered` is checked while the graph is locked, and if
+/// the offer has gone, the thread is not recorded as waiting.
+pub(crate) fn wait_for<F: FnOnce() -> bool>(pair: u64, half: usize, offered: F) -> Waiting {
+    match find_cycle(pair, half, offered) {
+        Ok(waiting) => waiting,
+        Err(cycle) => {
+            report(&cycle);
+            Waiting
+        }
+    }
+}
+
+/// Record that the current thread is waiting, unless this closes a cycle in the wait graph,
+/// in which case return a description of the cycle instead.
+fn find_cycle<F: FnOnce() -> bool>(pair: u64, half: usize, offered: F) -> Result<Waiting, String> {
+    let us = thread::current().id();
+    with_graph(|graph| {
+        if !offered() {
+            return Ok(Waiting);
+        }
+        let mut cycle = String::new();
+        let ours = (pair, half);
+        let (mut pair, mut half) = ours;
+        let mut thread = us;
+        let mut visited = HashSet::new();
+        // Follow the edges from this thread, which either reach a thread which is not
+        // waiting, come back to this thread, or enter a cycle which does not include this
+        // thread. Such a cycle has already been reported, by the thread which closed it.
+        loop {
+            let _ = write!(cycle, "{:?} waits for pair #{}", thread, pair);
+            visited.insert(thread);
+            match graph.owners.get(&(pair, 1 - half)) {
+                Some(&owner) if owner == us => break,
+                Some(&owner) if visited.contains(&owner) => {
+                    graph.waiting.insert(us, ours);
+                    return Ok(Waiting);
+                }
+                Some(&owner) => match graph.waiting.get(&owner) {
+                    Some(&next) => {
+                        let _ = write!(cycle, ", whose other half is used by ");
+                        thread = owner;
+                        (pair, half) = next;
+                    }
+                    None => {
+                        graph.waiting.insert(us, ours);
+                        return Ok(Waiting);
+                    }
+                },
+                None => {
+                    graph.waiting.insert(us, ours);
+                    return Ok(Waiting);
+                }
+            }
+        }
+        let _ = write!(cycle, ", whose other half is used by {:?}", us);
+        // Record the wait anyway, since the cycle may be a false positive.
+        graph.waiting.insert(us, ours);
+        Err(cycle)
+    })
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let us = thread::current().id();
+        with_graph(|graph| graph.waiting.remove(&us));
+    }
+}
@@ -0,0 +1,311 @@
+//! Deposits, which offer to swap without blocking, and async swaps built on them.
+
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic;
+use std::sync::atomic::Ordering;
+use std::task::Context;
+use std::task::Poll;
+
+use Offer;
+use SwapError;
+use Swapper;
+use observer;
+use wake::TaskWaker;
+
+/// A deposited value, and the offer to swap it.
+struct Deposit<T> {
+    value: UnsafeCell<T>,
+    offer: Offer<T>,
+}
+
+/// A deposit which has not yet been collected.
+///
+/// Dropping a pending deposit retracts it if it has not been taken,
+/// and otherwise drops the value the other half traded in.
+pub struct Pending<'a, T: 'a> {
+    // Borrowed mutably, so the half cannot be used while the deposit is pending, and so
+    // the deposit can be sent to another thread with it.
+    swapper: &'a mut Swapper<T>,
+    raw: ManuallyDrop<RawDeposit<T>>,
+}
+
+/// A deposit which is owned by one half of a pair, but does not borrow it.
+///
+/// The owner must pass the half which made the deposit to each method, and must call `free`.
+pub(crate) struct RawDeposit<T> {
+    // The deposit is owned by this struct, but is accessed through a raw pointer,
+    // since the other half may access it through the pointer in the offer.
+    deposit: NonNull<Deposit<T>>,
+    // Whether the deposit was swapped immediately, so was never offered.
+    swapped: bool,
+}
+
+/// The error returned when a deposit could not be collected, together with the deposited value.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CollectError<T>(pub T, pub SwapError);
+
+impl<T: Send> Swapper<T> {
+    /// Deposit a value, to be swapped with the other half without blocking.
+    ///
+    /// The value is left in the pair, and the current thread can continue with other work.
+    /// When the other half swaps, it takes the deposited value and trades in its own,
+    /// which can be retrieved with `Pending::collect`. This blocks only if the other half has
+    /// not swapped yet. If the other half is already blocked waiting to swap, the swap
+    /// happens immediately.
+    ///
+    /// ```rust
+    /// let (mut ab, ba) = swapper::swapper();
+    /// let pending = ab.deposit(String::from("hello"));
+    /// // The other half can be used on the same thread, since the deposit does not block.
+    /// let mut world = String::from("world");
+    /// ba.swap(&mut world).unwrap();
+    /// assert_eq!(world, "hello");
+    /// assert_eq!(pending.collect().unwrap(), "world");
+    /// ```
+    pub fn deposit(&mut self, value: T) -> Pending<'_, T> {
+        let raw = ManuallyDrop::new(RawDeposit::new(self, value, None));
+        Pending { raw, swapper: self }
+    }
+
+    /// Swap a value asynchronously, returning the value the other half traded in.
+    ///
+    /// This deposits the value, and the future completes once the other half has swapped.
+    /// The future is cancel-safe: if it is dropped before it completes, the deposit is
+    /// retracted, unless the other half has already taken it, in which case the swap
+    /// completes before the future is dropped. Either way, the pair is left idle,
+    /// with no dangling offer. To recover the value rather than dropping it,
+    /// use `SwapFuture::cancel`.
+    ///
+    /// ```rust
+    /// # use std::future::Future;
+    /// # use std::pin::Pin;
+    /// # use std::task::{Context, Poll, Waker};
+    /// let (mut ab, ba) = swapper::swapper();
+    /// let mut swap = ab.swap_async(String::from("hello"));
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert!(Pin::new(&mut swap).poll(&mut cx).is_pending());
+    /// let mut world = String::from("world");
+    /// ba.swap(&mut world).unwrap();
+    /// assert_eq!(world, "hello");
+    /// assert_eq!(Pin::new(&mut swap).poll(&mut cx), Poll::Ready(Ok(String::from("world"))));
+    /// ```
+    pub fn swap_async(&mut self, value: T) -> SwapFuture<'_, T> {
+        let task = Arc::new(TaskWaker::new());
+        let raw = ManuallyDrop::new(RawDeposit::new(self, value, Some(task.clone())));
+        SwapFuture {
+            pending: Some(Pending { raw, swapper: self }),
+            task,
+        }
+    }
+}
+
+impl<T: Send> RawDeposit<T> {
+    /// Deposit a value, waking the given task when it is taken.
+    ///
+    /// The caller must have exclusive access to the half, so it has no other deposit.
+    pub(crate) fn new(swapper: &Swapper<T>, value: T, task: Option<Arc<TaskWaker>>) -> RawDeposit<T> {
+        let deposit = Box::into_raw(Box::new(Deposit {
+            value: UnsafeCell::new(value),
+            offer: Offer {
+                thread: None,
+                task,
+                ..Offer::new(NonNull::dangling())
+            },
+        }));
+        let (data, offer) = unsafe {
+            let data = NonNull::new_unchecked((*deposit).value.get());
+            (*deposit).offer.data = data;
+            (data, NonNull::new_unchecked(ptr::addr_of_mut!((*deposit).offer)))
+        };
+        let mut raw = RawDeposit {
+            deposit: unsafe { NonNull::new_unchecked(deposit) },
+            swapped: false,
+        };
+        loop {
+            // Is the other thread blocked waiting to swap? If so, swap and unblock it.
+            if let Some(their_offer) = swapper.shared.slot.take::<Offer<T>>() {
+                let their_offer = unsafe { their_offer.as_ref() };
+                if their_offer.inspect {
+                    // The other thread is inspecting, so leave our deposit for it instead.
+                    swapper.shared.slot.offer(offer);
+                    let _ = swapper.complete(their_offer);
+                    return raw;
+                }
+                if their_offer.initialized.is_some()
+                    || their_offer.lease.is_some()
+                    || their_offer.lane.is_some()
+                    || their_offer.negotiator.is_some()
+                {
+                    // The other thread's data may be uninitialized, leased, not data at all,
+                    // or waiting to be negotiated for, so cannot be swapped with ours.
+                    their_offer.outcome.set(Err(SwapError::Mismatch));
+                    unsafe { (*deposit).offer.outcome.set(Err(SwapError::Mismatch)) };
+                    let _ = swapper.complete(their_offer);
+                    raw.swapped = true;
+                    return raw;
+                }
+                if let Some(clone) = their_offer.clone {
+                    // The other thread is observing, so give it a copy, and keep our deposit.
+                    unsafe { clone(data, their_offer.data) };
+                } else {
+                    unsafe { ptr::swap_nonoverlapping(data.as_ptr(), their_offer.data.as_ptr(), 1) };
+                    swapper.shared.swapped(swapper.half, their_offer.thread);
+                }
+                let outcome = swapper.complete(their_offer);
+                unsafe { (*deposit).offer.outcome.set(outcome) };
+                raw.swapped = true;
+                return raw;
+            }
+            // Otherwise, leave our offer for the other thread to take.
+            if swapper.shared.slot.offer(offer) {
+                observer::observe(&swapper.shared.observer, |observer| observer.on_offer(swapper.shared.id, swapper.half));
+                return raw;
+            }
+        }
+    }
+}
+
+impl<'a, T> Pending<'a, T> {
+    /// Collect the value the other half traded in for our deposit,
+    /// blocking if the other half has not swapped yet.
+    ///
+    /// If the other half was dropped without swapping, this returns the deposited value.
+    pub fn collect(self) -> Result<T, CollectError<T>> {
+        let result = self.raw.wait(self.swapper);
+        self.into_result(result)
+    }
+
+    fn into_result(self, result: Result<(), SwapError>) -> Result<T, CollectError<T>> {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.raw) }.free(result)
+    }
+}
+
+impl<T> RawDeposit<T> {
+    fn offer(&self) -> &Offer<T> {
+        unsafe { &(*self.deposit.as_ptr()).offer }
+    }
+
+    /// Wait for the deposit to be taken, or retract it if the other half is dropped.
+    pub(crate) fn wait(&self, swapper: &Swapper<T>) -> Result<(), SwapError> {
+        if !self.swapped {
+            if swapper.released_before(NonNull::from(self.offer())) {
+                return Err(SwapError::Disconnected);
+            }
+            if let Err(err) = swapper.wait.wait() {
+                swapper.shared.slot.retract(NonNull::from(self.offer()));
+                return Err(err);
+            }
+        }
+        self.offer().outcome.get()
+    }
+
+    /// Check whether the deposit has been taken, without blocking.
+    ///
+    /// Returns `None` if it has not, and otherwise the outcome, after which the deposit
+    /// must be freed. If the other half is dropped, the deposit is retracted.
+    pub(crate) fn try_wait(&mut self, swapper: &Swapper<T>) -> Option<Result<(), SwapError>> {
+        if !self.swapped {
+            match swapper.wait.try_wait() {
+                Ok(true) => self.swapped = true,
+                Ok(false) => {
+                    // Pairs with the fence in `release`, so if the other half was dropped
+                    // without seeing our deposit, we see that it was dropped.
+                    atomic::fence(Ordering::SeqCst);
+                    if !swapper.shared.is_disconnected() {
+                        return None;
+                    }
+                    if swapper.shared.slot.retract(NonNull::from(self.offer())) {
+                        return Some(Err(SwapError::Disconnected));
+                    }
+                    // The other half took the deposit before it was dropped.
+                    self.swapped = true;
+                    if let Err(err) = swapper.wait.wait() {
+                        return Some(Err(err));
+                    }
+                }
+                Err(err) => {
+                    swapper.shared.slot.retract(NonNull::from(self.offer()));
+                    return Some(Err(err));
+                }
+            }
+        }
+        Some(self.offer().outcome.get())
+    }
+
+    /// Retract the deposit if it has not been taken, and otherwise wait for the swap to complete.
+    pub(crate) fn retract(&self, swapper: &Swapper<T>) -> Result<(), SwapError> {
+        if !self.swapped && swapper.shared.slot.retract(NonNull::from(self.offer())) {
+            return Err(SwapError::WouldBlock);
+        }
+        self.wait(swapper)
+    }
+
+    /// Free the deposit, once it is no longer offered, returning the value it holds.
+    pub(crate) fn free(self, result: Result<(), SwapError>) -> Result<T, CollectError<T>> {
+        let deposit = unsafe { Box::from_raw(self.deposit.as_ptr()) };
+        let value = deposit.value.into_inner();
+        match result {
+            Ok(()) => Ok(value),
+            Err(err) => Err(CollectError(value, err)),
+        }
+    }
+}
+
+impl<'a, T> Drop for Pending<'a, T> {
+    fn drop(&mut self) {
+        // If the deposit has been taken, wait for the swap to complete before freeing it.
+        let result = self.raw.retract(self.swapper);
+        let _ = unsafe { ManuallyDrop::take(&mut self.raw) }.free(result);
+    }
+}
+
+/// An async swap, created by `Swapper::swap_async`.
+///
+/// Dropping the future before it completes cancels the swap, see `Swapper::swap_async`.
+/// The future is `Send` if `T` is, so it can be spawned on a multi-threaded executor.
+pub struct SwapFuture<'a, T: 'a> {
+    // The deposit, until the future completes.
+    pending: Option<Pending<'a, T>>,
+    task: Arc<TaskWaker>,
+}
+
+impl<'a, T> SwapFuture<'a, T> {
+    /// Cancel the swap without waiting for the other half.
+    ///
+    /// If the other half has not taken the deposit, it is retracted, and this returns
+    /// the deposited value with `SwapError::WouldBlock`. If the other half has taken it,
+    /// this waits for the swap to complete, and returns the value it traded in.
+    pub fn cancel(mut self) -> Result<T, CollectError<T>> {
+        let pending = self.pending.take().expect("SwapFuture cancelled after completion");
+        let result = pending.raw.retract(pending.swapper);
+        pending.into_result(result)
+    }
+}
+
+impl<'a, T> Future for SwapFuture<'a, T> {
+    type Output = Result<T, CollectError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut pending = self.pending.take().expect("SwapFuture polled after completion");
+        // Register before checking, so a wake between the check and suspending is not lost.
+        self.task.register(cx.waker());
+        match pending.raw.try_wait(pending.swapper) {
+            Some(result) => Poll::Ready(pending.into_result(result)),
+            None => {
+                self.pending = Some(pending);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// The deposit is only accessed by the other half through the offer, which synchronizes
+// through the slot, so it can be sent with the half which made it.
+unsafe impl<T: Send> Send for RawDeposit<T> {}
@@ -0,0 +1,222 @@
+//! Waiting for a group of pairs to swap.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use Shared;
+use SwapError;
+use Swapper;
+use registry;
+use registry::PairInfo;
+
+// Every epoch waits on the same condition variable, which is notified whenever a watched
+// pair swaps. Swaps are counted before the lock is taken, and epochs only check the counts
+// while holding the lock, so no notifications are lost.
+static SWAPPED: Mutex<()> = Mutex::new(());
+static SWAPPED_CONDVAR: Condvar = Condvar::new();
+
+pub(crate) fn notify() {
+    let _guard = SWAPPED.lock().unwrap();
+    SWAPPED_CONDVAR.notify_all();
+}
+
+/// A group of pairs, which swap in generations.
+///
+/// A coordinator can wait until every registered pair has swapped in the current generation,
+/// for example to synchronize double buffering across a group of workers each frame.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::SwapEpoch;
+/// let epoch = SwapEpoch::new();
+/// let helpers: Vec<_> = (0..3).map(|_| {
+///     let (ab, ba) = swapper::swapper();
+///     epoch.register(&ab);
+///     thread::spawn(move || ab.swap(&mut 1).unwrap());
+///     thread::spawn(move || ba.swap(&mut 2).unwrap())
+/// }).collect();
+/// assert_eq!(epoch.wait(), Ok(1));
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+#[derive(Debug, Default)]
+pub struct SwapEpoch {
+    state: Mutex<EpochState>,
+    // Kept outside the state, so the epoch can be shut down while a thread is waiting.
+    shutdown: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct EpochState {
+    generation: u64,
+    pairs: Vec<Member>,
+}
+
+struct Member {
+    id: u64,
+    // A weak reference, so the epoch does not keep a dropped pair alive. A pair whose halves
+    // have all been dropped can never swap again, so it no longer counts.
+    shared: Weak<Shared>,
+    // The number of swaps the pair had completed at the start of this generation.
+    baseline: u64,
+}
+
+/// The error returned when waiting with a timeout for a generation to complete fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EpochError {
+    /// Some pairs did not swap in time.
+    Timeout(EpochTimeout),
+    /// The epoch was shut down, see `SwapEpoch::shutdown`.
+    Shutdown,
+}
+
+/// The error returned when some pairs did not swap in time.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EpochTimeout {
+    /// The generation that did not complete.
+    pub generation: u64,
+    /// The pairs which have not yet swapped in this generation.
+    pub pending: Vec<PairInfo>,
+}
+
+impl SwapEpoch {
+    /// Create a new epoch, with no pairs, in generation 0.
+    pub fn new() -> SwapEpoch {
+        SwapEpoch::default()
+    }
+
+    /// Register a pair with the epoch, by either of its halves.
+    ///
+    /// The pair must swap at least once in each generation after the current one, until
+    /// both its halves are dropped, after which it is deregistered.
+    pub fn register<T: ?Sized>(&self, swapper: &Swapper<T>) {
+        let shared = &swapper.shared;
+        shared.watched.store(true, Ordering::Release);
+        self.lock().pairs.push(Member {
+            id: shared.id,
+            shared: Arc::downgrade(shared),
+            baseline: shared.swaps.load(Ordering::Acquire),
+        });
+    }
+
+    /// The current generation.
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Block until every registered pair has swapped in the current generation,
+    /// then start the next generation, and return its number.
+    ///
+    /// If the epoch is shut down, this returns `SwapError::Shutdown`.
+    pub fn wait(&self) -> Result<u64, SwapError> {
+        match self.wait_until(None) {
+            Ok(generation) => Ok(generation),
+            Err(EpochError::Shutdown) => Err(SwapError::Shutdown),
+            Err(EpochError::Timeout(_)) => panic!("Epoch without timeout timed out"),
+        }
+    }
+
+    /// Block until every registered pair has swapped in the current generation, or the
+    /// timeout expires. If every pair has swapped, start the next generation,
+    /// and return its number. Otherwise, report the pairs which have not swapped.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<u64, EpochError> {
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    /// Shut down the epoch, so every thread waiting for a generation to complete,
+    /// and every later wait, fails.
+    ///
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use swapper::{SwapEpoch, SwapError};
+    /// let epoch = Arc::new(SwapEpoch::new());
+    /// let (ab, _ba) = swapper::swapper::<u32>();
+    /// epoch.register(&ab);
+    /// let coordinator = epoch.clone();
+    /// let helper = thread::spawn(move || coordinator.wait());
+    /// epoch.shutdown();
+    /// assert_eq!(helper.join().unwrap(), Err(SwapError::Shutdown));
+    /// ```
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        notify();
+    }
+
+    /// Has the epoch been shut down?
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> Result<u64, EpochError> {
+        let mut guard = SWAPPED.lock().unwrap();
+        loop {
+            // The flag is set before notifying, which takes the lock we hold, so this is not missed.
+            if self.is_shutdown() {
+                return Err(EpochError::Shutdown);
+            }
+            // The state is only locked while checking, not while waiting, so pairs can be
+            // registered, and the generation read, by other threads meanwhile.
+            let mut state = self.lock();
+            state.pairs.retain(|member| member.shared.strong_count() > 0);
+            let pending: Vec<Arc<Shared>> = state.pairs.iter().filter_map(Member::pending).collect();
+            if pending.is_empty() {
+                for member in &mut state.pairs {
+                    member.start_generation();
+                }
+                state.generation += 1;
+                return Ok(state.generation);
+            }
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) => Some(timeout),
+                    None => {
+                        return Err(EpochError::Timeout(EpochTimeout {
+                            generation: state.generation,
+                            pending: pending.iter().map(registry::info).collect(),
+                        }))
+                    }
+                },
+            };
+            drop(state);
+            guard = match timeout {
+                None => SWAPPED_CONDVAR.wait(guard).unwrap(),
+                Some(timeout) => SWAPPED_CONDVAR.wait_timeout(guard, timeout).unwrap().0,
+            };
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EpochState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Member {
+    /// The pair, if it is still alive and has not swapped in this generation.
+    fn pending(&self) -> Option<Arc<Shared>> {
+        self.shared.upgrade().filter(|shared| shared.swaps.load(Ordering::Acquire) <= self.baseline)
+    }
+
+    fn start_generation(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            self.baseline = shared.swaps.load(Ordering::Acquire);
+        }
+    }
+}
+
+impl fmt::Debug for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Member")
+            .field("id", &self.id)
+            .field("baseline", &self.baseline)
+            .finish()
+    }
+}
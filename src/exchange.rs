@@ -0,0 +1,80 @@
+//! A trait for anything which swaps data, so code can be generic over how it swaps.
+
+use std::time::Duration;
+
+use BufferedSwapper;
+use SetMember;
+use SwapError;
+use Swapper;
+
+/// A provider of swaps, such as a `Swapper`, a `BufferedSwapper`, or a `SetMember`.
+///
+/// Code which only needs to swap can be generic over the provider, so a library can leave
+/// the choice to its users, and tests can substitute a deterministic provider, such as
+/// `swapper::testing::MockSwapper`.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::{Exchange, SwapError};
+/// // Swap in a new buffer, however the other half is connected.
+/// fn flush<E: Exchange<Vec<u8>>>(half: &E, buffer: &mut Vec<u8>) -> Result<(), SwapError> {
+///     half.swap(buffer)?;
+///     buffer.clear();
+///     Ok(())
+/// }
+/// let (producer, consumer) = swapper::swapper();
+/// let helper = thread::spawn(move || flush(&consumer, &mut vec![]));
+/// let mut buffer = vec![1, 2, 3];
+/// flush(&producer, &mut buffer).unwrap();
+/// # helper.join().unwrap().unwrap();
+/// let (producer, _consumer) = swapper::buffered_swapper(vec![]);
+/// flush(&producer, &mut buffer).unwrap();
+/// ```
+pub trait Exchange<T> {
+    /// Swap data, blocking until the swap completes or fails.
+    fn swap(&self, our_ref: &mut T) -> Result<(), SwapError>;
+
+    /// Swap data, giving up with `SwapError::Timeout` if it does not complete within the timeout.
+    fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError>;
+
+    /// Swap data if it can be done without waiting, otherwise return `SwapError::WouldBlock`.
+    ///
+    /// By default, this is a swap with a zero timeout.
+    fn try_swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        match self.swap_timeout(our_ref, Duration::from_secs(0)) {
+            Err(SwapError::Timeout { .. }) => Err(SwapError::WouldBlock),
+            result => result,
+        }
+    }
+}
+
+impl<T: Send> Exchange<T> for Swapper<T> {
+    fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        Swapper::swap(self, our_ref)
+    }
+
+    fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        Swapper::swap_timeout(self, our_ref, timeout)
+    }
+}
+
+impl<T: Send> Exchange<T> for BufferedSwapper<T> {
+    fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        BufferedSwapper::swap(self, our_ref)
+    }
+
+    fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        BufferedSwapper::swap_timeout(self, our_ref, timeout)
+    }
+}
+
+/// A member of a set swaps with any other member.
+impl<T: Send> Exchange<T> for SetMember<T> {
+    fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.swap_any(our_ref).map(|_| ())
+    }
+
+    fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        self.swap_any_timeout(our_ref, timeout).map(|_| ())
+    }
+}
@@ -0,0 +1,250 @@
+//! Balancing work between a group of workers, by swapping queues of jobs.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use Swapper;
+use swapper;
+
+/// A job, which is given its worker's token, so it can push more jobs.
+type Job = Box<dyn FnOnce(&mut WorkToken) + Send>;
+
+/// A queue of jobs, owned by one worker.
+///
+/// Each worker always holds a token, which may be empty. An idle worker swaps its empty
+/// token for one loaded with jobs by a busy worker.
+#[derive(Default)]
+pub struct WorkToken {
+    jobs: VecDeque<Job>,
+}
+
+/// One worker in a group which balances its work by swapping.
+///
+/// Each worker runs the jobs in its own `WorkToken`, and jobs can push more jobs to it.
+/// When a worker runs out of jobs, it blocks on its own swapper pair, until a busy worker
+/// uses the other half to swap a token loaded with half of its jobs for the idle worker's
+/// empty one. A busy worker only checks for idle workers between jobs, and only takes a
+/// lock when there is one, so while every worker is busy they do not synchronize at all.
+/// Once every worker is idle, there are no jobs left, so every worker is swapped an empty
+/// token, and stops.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use std::thread;
+/// # use swapper::WorkToken;
+/// let total = Arc::new(AtomicUsize::new(0));
+/// let mut workers = swapper::work_swapping(4);
+/// // Seed one worker with a job which spawns a tree of 1023 jobs.
+/// fn job(depth: usize, total: Arc<AtomicUsize>) -> impl FnOnce(&mut WorkToken) + Send {
+///     move |token: &mut WorkToken| {
+///         total.fetch_add(1, Ordering::Relaxed);
+///         if depth > 0 {
+///             token.push(job(depth - 1, total.clone()));
+///             token.push(job(depth - 1, total));
+///         }
+///     }
+/// }
+/// workers[0].token().push(job(9, total.clone()));
+/// let helpers: Vec<_> = workers.into_iter().map(|mut worker| thread::spawn(move || worker.run())).collect();
+/// let ran: usize = helpers.into_iter().map(|helper| helper.join().unwrap()).sum();
+/// assert_eq!(ran, 1023);
+/// assert_eq!(total.load(Ordering::Relaxed), 1023);
+/// ```
+pub struct WorkSwapper {
+    shared: Arc<Shared>,
+    index: usize,
+    token: WorkToken,
+    // The half on which we wait for a busy worker to swap us a loaded token.
+    swapper: Swapper<WorkToken>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // The number of idle workers, so busy workers can check for them without locking.
+    idle: AtomicUsize,
+    // For each worker, the other half of its swapper. Only the worker which removes an
+    // idle worker from the list uses its half, but the lock makes sure two uses of the same
+    // half never overlap.
+    partners: Vec<Mutex<Swapper<WorkToken>>>,
+}
+
+struct State {
+    // The number of workers which have not been dropped.
+    workers: usize,
+    // The indexes of the idle workers, in the order they ran out of jobs.
+    idle: Vec<usize>,
+    // Set once every worker is idle, after which there can be no more jobs.
+    finished: bool,
+}
+
+/// Create a group of workers which balance their work by swapping.
+pub fn work_swapping(workers: usize) -> Vec<WorkSwapper> {
+    let (swappers, partners): (Vec<_>, Vec<_>) = (0..workers).map(|_| swapper()).unzip();
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            workers,
+            idle: Vec::new(),
+            finished: false,
+        }),
+        idle: AtomicUsize::new(0),
+        partners: partners.into_iter().map(Mutex::new).collect(),
+    });
+    swappers
+        .into_iter()
+        .enumerate()
+        .map(|(index, swapper)| WorkSwapper {
+            shared: shared.clone(),
+            index,
+            token: WorkToken::new(),
+            swapper,
+        })
+        .collect()
+}
+
+impl WorkToken {
+    /// Create an empty token.
+    pub fn new() -> WorkToken {
+        WorkToken::default()
+    }
+
+    /// Push a job, to be run by whichever worker holds the token when it is reached.
+    pub fn push<F: FnOnce(&mut WorkToken) + Send + 'static>(&mut self, job: F) {
+        self.jobs.push_back(Box::new(job));
+    }
+
+    /// The number of jobs in the token.
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Is the token empty?
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Take back the newer half of the jobs, rounding up, leaving the older half.
+    fn split(&mut self) -> WorkToken {
+        let keep = self.jobs.len() / 2;
+        WorkToken {
+            jobs: self.jobs.split_off(keep),
+        }
+    }
+}
+
+impl WorkSwapper {
+    /// The index of this worker, from 0 to the number of workers.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// This worker's token, for example to seed it with jobs before running.
+    pub fn token(&mut self) -> &mut WorkToken {
+        &mut self.token
+    }
+
+    /// Run jobs until every worker is idle, returning the number of jobs this worker ran.
+    ///
+    /// Jobs are run from the front of the token, and jobs they push go to the back.
+    /// If a job panics, the panic is propagated, and the jobs left in the worker's token
+    /// are not run unless it runs again. The other workers count it as busy until then,
+    /// or until it is dropped.
+    pub fn run(&mut self) -> usize {
+        let mut ran = 0;
+        loop {
+            while let Some(job) = self.token.jobs.pop_front() {
+                self.share();
+                job(&mut self.token);
+                ran += 1;
+            }
+            if !self.await_work() {
+                return ran;
+            }
+        }
+    }
+
+    // If a worker is idle, swap a token loaded with half of our jobs for its empty one.
+    fn share(&mut self) {
+        if self.token.is_empty() || self.shared.idle.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut state = self.shared.lock();
+        if state.idle.is_empty() {
+            return;
+        }
+        let idle = state.idle.remove(0);
+        self.shared.idle.fetch_sub(1, Ordering::Release);
+        drop(state);
+        // The idle worker is blocked waiting to swap, so this does not block.
+        let mut loaded = self.token.split();
+        let partner = self.shared.partners[idle].lock().unwrap();
+        if partner.swap(&mut loaded).is_err() {
+            self.token.jobs.append(&mut loaded.jobs);
+        }
+    }
+
+    // Wait for a busy worker to give us jobs, returning false if every worker is idle.
+    fn await_work(&mut self) -> bool {
+        let mut state = self.shared.lock();
+        if state.finished || state.idle.len() + 1 >= state.workers {
+            self.shared.finish(state);
+            return false;
+        }
+        state.idle.push(self.index);
+        self.shared.idle.fetch_add(1, Ordering::Release);
+        drop(state);
+        // Once every worker is idle, we are swapped an empty token.
+        self.swapper.swap(&mut self.token).is_ok() && !self.token.is_empty()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    // Every worker is idle, so swap each of them an empty token to stop.
+    fn finish(&self, mut state: MutexGuard<'_, State>) {
+        state.finished = true;
+        let idle = mem::take(&mut state.idle);
+        self.idle.store(0, Ordering::Release);
+        drop(state);
+        for index in idle {
+            let _ = self.partners[index].lock().unwrap().swap(&mut WorkToken::new());
+        }
+    }
+}
+
+impl Drop for WorkSwapper {
+    fn drop(&mut self) {
+        // We are no longer busy, so if every other worker is idle, there is no work left.
+        let mut state = self.shared.lock();
+        state.workers -= 1;
+        if state.workers > 0 && state.idle.len() >= state.workers {
+            self.shared.finish(state);
+        }
+    }
+}
+
+impl fmt::Debug for WorkToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkToken")
+            .field("jobs", &self.jobs.len())
+            .finish()
+    }
+}
+
+impl fmt::Debug for WorkSwapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkSwapper")
+            .field("index", &self.index)
+            .field("token", &self.token)
+            .finish()
+    }
+}
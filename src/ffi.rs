@@ -0,0 +1,104 @@
+//! A C API, for passing swappers to foreign threads.
+//!
+//! Enabled by the `ffi` feature. The API swaps `void*` pointers, and is declared in
+//! `include/swapper.h`:
+//!
+//! ```c
+//! typedef struct swapper swapper_t;
+//! int swapper_new(swapper_t** a, swapper_t** b);
+//! int swapper_swap(swapper_t* swapper, void** value);
+//! void swapper_free(swapper_t* swapper);
+//! ```
+//!
+//! Each half of the pair is freed separately, and the halves can be freed in any order.
+//! A half can be converted to or from a Rust `Swapper<ForeignPtr>` using
+//! `Swapper::from_raw` and `Swapper::into_raw`.
+
+use std::os::raw::c_int;
+use std::os::raw::c_void;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// The swap succeeded.
+pub const SWAPPER_OK: c_int = 0;
+/// The swap failed, because the other half has been freed.
+pub const SWAPPER_DISCONNECTED: c_int = 1;
+/// The swap failed, because the other half is blocked on the current thread.
+pub const SWAPPER_WOULD_DEADLOCK: c_int = 2;
+/// The swap failed, because a null pointer was passed.
+pub const SWAPPER_NULL: c_int = -1;
+/// The swap failed for some other reason.
+pub const SWAPPER_ERROR: c_int = -2;
+
+/// A pointer owned by foreign code.
+///
+/// Foreign code is responsible for ensuring that the data it points to can be
+/// used from whichever thread receives it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ForeignPtr(pub *mut c_void);
+
+unsafe impl Send for ForeignPtr {}
+
+fn error_code(err: SwapError) -> c_int {
+    match err {
+        SwapError::Disconnected => SWAPPER_DISCONNECTED,
+        SwapError::WouldDeadlock => SWAPPER_WOULD_DEADLOCK,
+        _ => SWAPPER_ERROR,
+    }
+}
+
+/// Create a new pair of swappers, storing the halves in `a` and `b`.
+///
+/// # Safety
+///
+/// `a` and `b` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn swapper_new(a: *mut *mut Swapper<ForeignPtr>, b: *mut *mut Swapper<ForeignPtr>) -> c_int {
+    if a.is_null() || b.is_null() {
+        return SWAPPER_NULL;
+    }
+    let (swapper_a, swapper_b) = swapper();
+    *a = swapper_a.into_raw();
+    *b = swapper_b.into_raw();
+    SWAPPER_OK
+}
+
+/// Swap the pointer stored in `value` with the other half of the pair.
+///
+/// # Safety
+///
+/// `swapper` must have been created by `swapper_new` and not yet freed,
+/// and `value` must be valid for reads and writes. The same half must not be used
+/// concurrently from two threads, that is, while one thread is swapping with a half,
+/// no other thread may swap with or free it. Each half may be used from a different
+/// thread, and a half may move between threads between swaps.
+#[no_mangle]
+pub unsafe extern "C" fn swapper_swap(swapper: *mut Swapper<ForeignPtr>, value: *mut *mut c_void) -> c_int {
+    let (swapper, value) = match (swapper.as_ref(), value.as_mut()) {
+        (Some(swapper), Some(value)) => (swapper, value),
+        _ => return SWAPPER_NULL,
+    };
+    let mut ours = ForeignPtr(*value);
+    match swapper.swap(&mut ours) {
+        Ok(()) => {
+            *value = ours.0;
+            SWAPPER_OK
+        }
+        Err(err) => error_code(err),
+    }
+}
+
+/// Free one half of a pair. Freeing a null pointer does nothing.
+///
+/// # Safety
+///
+/// `swapper` must be null, or have been created by `swapper_new` and not yet freed,
+/// and no other thread may be swapping with it.
+#[no_mangle]
+pub unsafe extern "C" fn swapper_free(swapper: *mut Swapper<ForeignPtr>) {
+    if !swapper.is_null() {
+        drop(Swapper::from_raw(swapper));
+    }
+}
@@ -0,0 +1,77 @@
+//! One-way hand-offs, from a giver to a taker.
+
+use std::fmt;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// The giving half of a hand-off pair.
+///
+/// A hand-off is a swap in one direction: the giver blocks until the taker has taken
+/// the value, and the taker blocks until there is a value to take. It is implemented
+/// by swapping an `Option<T>`, so there is no need for a dummy value to swap back.
+///
+/// ```rust
+/// # use std::thread;
+/// let (giver, taker) = swapper::handoff();
+/// let helper = thread::spawn(move || {
+///     for job in 0..3 {
+///         giver.give(job).unwrap();
+///     }
+/// });
+/// assert_eq!(taker.take(), Ok(0));
+/// assert_eq!(taker.take(), Ok(1));
+/// assert_eq!(taker.take(), Ok(2));
+/// # helper.join().unwrap();
+/// ```
+pub struct Giver<T>(Swapper<Option<T>>);
+
+/// The taking half of a hand-off pair.
+pub struct Taker<T>(Swapper<Option<T>>);
+
+/// The error returned when a value could not be given, together with the value.
+#[derive(Debug, Eq, PartialEq)]
+pub struct GiveError<T>(pub T, pub SwapError);
+
+/// Create a new hand-off pair.
+pub fn handoff<T>() -> (Giver<T>, Taker<T>) {
+    let (giver, taker) = swapper();
+    (Giver(giver), Taker(taker))
+}
+
+impl<T: Send> Giver<T> {
+    /// Give a value to the taker, blocking until it has been taken.
+    ///
+    /// If the taker is dropped, this returns the value.
+    pub fn give(&self, value: T) -> Result<(), GiveError<T>> {
+        let mut value = Some(value);
+        match self.0.swap(&mut value) {
+            Ok(()) => Ok(()),
+            // The swap failed, so we still have our value.
+            Err(err) => Err(GiveError(value.expect("Failed hand-off lost value"), err)),
+        }
+    }
+}
+
+impl<T: Send> Taker<T> {
+    /// Take a value from the giver, blocking until one is given.
+    pub fn take(&self) -> Result<T, SwapError> {
+        let mut value = None;
+        self.0.swap(&mut value)?;
+        // The giver always offers a value.
+        Ok(value.expect("Hand-off with no value"))
+    }
+}
+
+impl<T> fmt::Debug for Giver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Giver").field(&self.0).finish()
+    }
+}
+
+impl<T> fmt::Debug for Taker<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Taker").field(&self.0).finish()
+    }
+}
@@ -0,0 +1,64 @@
+//! Recording which threads swapped using a pair, for auditing.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread::ThreadId;
+use std::time::Instant;
+
+/// A swap, as recorded in a pair's history.
+///
+/// After the swap, the data offered by half 0 is held by the thread which used half 1,
+/// and vice versa.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapRecord {
+    /// The generation of the swap, which is the number of swaps the pair had completed before it.
+    pub generation: u64,
+    /// The thread which used each half of the pair to swap, if known.
+    ///
+    /// A half which swapped by leaving a deposit has no thread, since the swap was
+    /// completed by the other half.
+    pub threads: [Option<ThreadId>; 2],
+    /// When the swap completed.
+    pub at: Instant,
+}
+
+/// The most recent swaps of a pair.
+pub(crate) struct History {
+    capacity: usize,
+    records: Mutex<VecDeque<SwapRecord>>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Record a swap, forgetting the oldest one if the history is full.
+    pub(crate) fn record(&self, record: SwapRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The recorded swaps, oldest first.
+    pub(crate) fn records(&self) -> Vec<SwapRecord> {
+        self.lock().iter().cloned().collect()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<SwapRecord>> {
+        self.records.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,84 @@
+//! Inspecting the other half's data, without swapping.
+
+use std::ptr::NonNull;
+use std::thread;
+
+use Offer;
+use SwapError;
+use Swapper;
+
+#[cfg(feature = "deadlock-detection")]
+use deadlock;
+
+impl<T: Send> Swapper<T> {
+    /// Wait for the other half to swap, and call `f` with its data while it is blocked,
+    /// without swapping.
+    ///
+    /// The other half's swap then succeeds, but leaves its data unchanged, and this does not
+    /// count as a swap, so does not start a new generation. This is useful for health checks
+    /// and debugging, which must not change which half holds which data. Unlike `swap_cloned`,
+    /// the data does not need to be `Clone`, but the other half stays blocked while `f` runs.
+    ///
+    /// If the other half is also inspecting, or observing with `swap_cloned`, or swapping data
+    /// which may be uninitialized or leased, or splitting off a lane, this returns
+    /// `SwapError::Mismatch`. If `f` panics, the other half's swap returns `SwapError::Aborted`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || {
+    ///     let mut token = vec![1, 2, 3];
+    ///     ab.swap(&mut token).unwrap();
+    ///     assert_eq!(token, [1, 2, 3]);
+    /// });
+    /// assert_eq!(ba.inspect(|token| token.len()), Ok(3));
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn inspect<F, R>(&self, f: F) -> Result<R, SwapError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::used(self.shared.id, self.half);
+        let our_offer = Offer {
+            inspect: true,
+            ..Offer::new(NonNull::dangling())
+        };
+        loop {
+            // Is the other thread blocked waiting to swap? If so, inspect its data.
+            if let Some(their_offer) = self.shared.slot.take::<Offer<T>>() {
+                let their_offer = unsafe { their_offer.as_ref() };
+                if their_offer.same_thread(&our_offer) {
+                    self.shared.slot.offer(NonNull::from(their_offer));
+                    return Err(SwapError::WouldDeadlock);
+                }
+                if !their_offer.is_plain() {
+                    self.shared.slot.offer(NonNull::from(their_offer));
+                    return Err(SwapError::Mismatch);
+                }
+                // If `f` panics, unblock the other thread rather than leave it waiting forever.
+                let guard = Unblock(self, their_offer);
+                let result = f(unsafe { their_offer.data.as_ref() });
+                drop(guard);
+                return Ok(result);
+            }
+            // Is the other thread not ready yet? If so, wait for it to offer its data instead.
+            if self.shared.slot.offer(NonNull::from(&our_offer)) {
+                self.await_taken(&our_offer)?;
+                our_offer.outcome.get()?;
+            }
+        }
+    }
+}
+
+/// Unblocks the other half when dropped, reporting a panic as an abort.
+struct Unblock<'a, T: 'a>(&'a Swapper<T>, &'a Offer<T>);
+
+impl<'a, T> Drop for Unblock<'a, T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.1.outcome.set(Err(SwapError::Aborted));
+        }
+        let _ = self.0.complete(self.1);
+    }
+}
@@ -0,0 +1,150 @@
+//! Swapping between an interrupt handler and the main loop.
+//!
+//! Like the rest of the crate, this needs `std`, so it cannot yet be used on a bare-metal
+//! target, even though the handler's side only uses atomics. Nor is it integrated with any
+//! interrupt machinery: it never masks interrupts or enters a critical section, and the main
+//! loop only waits for the handler through the `idle` closure it is given, which on a
+//! microcontroller would execute `WFI` or similar.
+
+use std::fmt;
+use std::hint;
+use std::marker::PhantomData;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use SwapError;
+use slot::Slot;
+
+/// A swapper shared between an interrupt service routine and the main loop.
+///
+/// An interrupt handler cannot block, so only the main loop offers to swap, and the handler
+/// takes the offer if there is one. The handler's side, `try_swap_from_isr`, never blocks,
+/// allocates, or parks: it is a single atomic swap, followed by swapping the data. The main
+/// loop's side, `swap_from_main`, waits for the handler by repeatedly calling an idle
+/// function, which on a microcontroller would typically wait for an interrupt.
+///
+/// For example, a DMA interrupt which exchanges a filled buffer for an empty one:
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::IsrSwapper;
+/// let buffers = IsrSwapper::new();
+/// thread::scope(|scope| {
+///     // Standing in for the interrupt handler.
+///     scope.spawn(|| {
+///         let mut filled = vec![1, 2, 3];
+///         while buffers.try_swap_from_isr(&mut filled).is_err() {
+///             // No empty buffer yet, so keep the filled one, and try again next interrupt.
+///         }
+///         assert!(filled.is_empty());
+///     });
+///     let mut empty = Vec::new();
+///     buffers.swap_from_main(&mut empty, thread::yield_now);
+///     assert_eq!(empty, [1, 2, 3]);
+/// });
+/// ```
+///
+/// The handler may run between the main loop checking whether its offer has been taken
+/// and calling the idle function, so the idle function should not sleep indefinitely
+/// if the handler has already run, for example by waiting for the next interrupt.
+pub struct IsrSwapper<T> {
+    slot: Slot,
+    marker: PhantomData<T>,
+}
+
+/// An offer to swap, which lives on the main loop's stack while it waits.
+struct Offer<T> {
+    data: NonNull<T>,
+    // Set by the interrupt handler once it has swapped the data.
+    done: AtomicBool,
+}
+
+impl<T> IsrSwapper<T> {
+    /// Create a new swapper, with no offer from the main loop.
+    pub fn new() -> IsrSwapper<T> {
+        IsrSwapper {
+            slot: Slot::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Is the main loop waiting for the interrupt handler to swap?
+    pub fn is_offered(&self) -> bool {
+        !self.slot.is_empty()
+    }
+}
+
+impl<T: Send> IsrSwapper<T> {
+    /// Swap data from the interrupt handler, if the main loop has offered to swap.
+    ///
+    /// This never blocks. If the main loop is not waiting to swap, it returns
+    /// `SwapError::WouldBlock`, and the data is not modified.
+    pub fn try_swap_from_isr(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let their_offer = match self.slot.take::<Offer<T>>() {
+            Some(their_offer) => unsafe { their_offer.as_ref() },
+            None => return Err(SwapError::WouldBlock),
+        };
+        // The main loop does not access its data until the offer is done.
+        unsafe { ptr::swap_nonoverlapping(our_ref, their_offer.data.as_ptr(), 1) };
+        their_offer.done.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Swap data from the main loop, calling `idle` until the interrupt handler swaps.
+    pub fn swap_from_main<F: FnMut()>(&self, our_ref: &mut T, mut idle: F) {
+        let our_offer = Offer {
+            data: NonNull::from(our_ref),
+            done: AtomicBool::new(false),
+        };
+        // Only one offer can be in the slot, so wait for any other main loop's to be taken.
+        while !self.slot.offer(NonNull::from(&our_offer)) {
+            idle();
+        }
+        let _guard = Guard {
+            swapper: self,
+            offer: &our_offer,
+        };
+        while !our_offer.done.load(Ordering::Acquire) {
+            idle();
+        }
+    }
+}
+
+// Makes sure the offer is not left in the slot if `idle` panics.
+struct Guard<'a, T: 'a> {
+    swapper: &'a IsrSwapper<T>,
+    offer: &'a Offer<T>,
+}
+
+impl<'a, T> Drop for Guard<'a, T> {
+    fn drop(&mut self) {
+        if !self.swapper.slot.retract(NonNull::from(self.offer)) {
+            // The offer has been taken, and the interrupt handler is about to finish swapping.
+            while !self.offer.done.load(Ordering::Acquire) {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+impl<T> Default for IsrSwapper<T> {
+    fn default() -> IsrSwapper<T> {
+        IsrSwapper::new()
+    }
+}
+
+impl<T> fmt::Debug for IsrSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IsrSwapper")
+            .field("offered", &self.is_offered())
+            .finish()
+    }
+}
+
+// The data is only accessed through the swap protocol, which hands it between contexts.
+unsafe impl<T: Send> Sync for IsrSwapper<T> {}
+
+// Be explicit about implementing Send.
+unsafe impl<T: Send> Send for IsrSwapper<T> {}
@@ -0,0 +1,208 @@
+//! Swapping between whichever threads present the same key.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+
+use PartnerState;
+use SwapError;
+
+/// A rendezvous where threads which present equal keys swap with each other.
+///
+/// Unlike a `Swapper`, where the two threads which swap must be paired up in advance,
+/// threads are paired by the key they present, such as a request id or a shard id, so
+/// pairs do not need to be plumbed through the program. The first thread to present a
+/// key waits, and the second swaps with it, after which the key can be used again.
+/// The exchange can be cloned, and each clone pairs threads with the same keys.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::KeyedExchange;
+/// let exchange = KeyedExchange::new();
+/// let helpers: Vec<_> = (0..4).map(|request| {
+///     let exchange = exchange.clone();
+///     thread::spawn(move || {
+///         // Threads handling the same request swap with each other.
+///         let mut token = request;
+///         exchange.exchange(request / 2, &mut token).unwrap();
+///         assert_eq!(token, request ^ 1);
+///     })
+/// }).collect();
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+pub struct KeyedExchange<K, T> {
+    inner: Arc<Inner<K, T>>,
+}
+
+struct Inner<K, T> {
+    state: Mutex<State<K, T>>,
+    condvar: Condvar,
+}
+
+struct State<K, T> {
+    next_ticket: u64,
+    // The thread blocked waiting for a partner, for each key.
+    waiting: HashMap<K, Waiting<T>>,
+    // The tickets of waiting threads which have been swapped with, but not yet woken.
+    swapped: HashSet<u64>,
+    shutdown: bool,
+}
+
+/// A thread blocked waiting to swap.
+struct Waiting<T> {
+    ticket: u64,
+    // The data lives on the stack of the waiting thread, which does not access it
+    // until its ticket is swapped.
+    data: NonNull<T>,
+}
+
+impl<K: Eq + Hash, T> KeyedExchange<K, T> {
+    /// Create a new exchange, with no threads waiting.
+    pub fn new() -> KeyedExchange<K, T> {
+        KeyedExchange {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    next_ticket: 0,
+                    waiting: HashMap::new(),
+                    swapped: HashSet::new(),
+                    shutdown: false,
+                }),
+                condvar: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Shut down the exchange, so every thread waiting for a partner, and every later
+    /// exchange, fails with `SwapError::Shutdown`.
+    pub fn shutdown(&self) {
+        let mut state = self.inner.lock();
+        state.shutdown = true;
+        state.waiting.clear();
+        self.inner.condvar.notify_all();
+    }
+
+    /// Has the exchange been shut down?
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.lock().shutdown
+    }
+
+    /// The number of keys with a thread waiting for a partner.
+    pub fn waiting(&self) -> usize {
+        self.inner.lock().waiting.len()
+    }
+}
+
+impl<K: Eq + Hash, T: Send> KeyedExchange<K, T> {
+    /// Swap data with another thread presenting an equal key.
+    ///
+    /// If a thread is already waiting with the key, this swaps with it, and unblocks it.
+    /// Otherwise it blocks until another thread presents the key.
+    pub fn exchange(&self, key: K, our_ref: &mut T) -> Result<(), SwapError> {
+        self.rendezvous(key, our_ref, None)
+    }
+
+    /// Swap data with another thread presenting an equal key, giving up if none does
+    /// within the timeout.
+    ///
+    /// On timeout the key is free again, and the error reports `PartnerState::NeverArrived`.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use swapper::{KeyedExchange, PartnerState, SwapError};
+    /// let exchange = KeyedExchange::new();
+    /// let timeout = Duration::from_millis(10);
+    /// let partner = PartnerState::NeverArrived;
+    /// assert_eq!(exchange.exchange_timeout("shard-3", &mut 1, timeout), Err(SwapError::Timeout { partner }));
+    /// ```
+    pub fn exchange_timeout(&self, key: K, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        self.rendezvous(key, our_ref, Some(Instant::now() + timeout))
+    }
+
+    fn rendezvous(&self, key: K, our_ref: &mut T, deadline: Option<Instant>) -> Result<(), SwapError> {
+        let mut state = self.inner.lock();
+        if state.shutdown {
+            return Err(SwapError::Shutdown);
+        }
+        // Is a thread waiting with the same key? If so, swap with it, and unblock it.
+        if let Some(theirs) = state.waiting.remove(&key) {
+            // The waiting thread does not access its data until its ticket is swapped.
+            unsafe { ptr::swap_nonoverlapping(our_ref, theirs.data.as_ptr(), 1) };
+            state.swapped.insert(theirs.ticket);
+            self.inner.condvar.notify_all();
+            return Ok(());
+        }
+        // Otherwise, block waiting for a thread with the same key.
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.insert(
+            key,
+            Waiting {
+                ticket,
+                data: NonNull::from(our_ref),
+            },
+        );
+        loop {
+            if state.swapped.remove(&ticket) {
+                return Ok(());
+            }
+            if state.shutdown {
+                return Err(SwapError::Shutdown);
+            }
+            match deadline {
+                None => state = self.inner.condvar.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // We have not been swapped with, so are still waiting with our key.
+                        state.waiting.retain(|_, waiting| waiting.ticket != ticket);
+                        return Err(SwapError::Timeout {
+                            partner: PartnerState::NeverArrived,
+                        });
+                    }
+                    state = self.inner.condvar.wait_timeout(state, deadline - now).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+impl<K, T> Inner<K, T> {
+    fn lock(&self) -> MutexGuard<'_, State<K, T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl<K: Eq + Hash, T> Default for KeyedExchange<K, T> {
+    fn default() -> KeyedExchange<K, T> {
+        KeyedExchange::new()
+    }
+}
+
+impl<K, T> Clone for KeyedExchange<K, T> {
+    fn clone(&self) -> KeyedExchange<K, T> {
+        KeyedExchange {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, T> fmt::Debug for KeyedExchange<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyedExchange")
+            .field("waiting", &self.inner.lock().waiting.len())
+            .finish()
+    }
+}
+
+// The raw pointers are only dereferenced while the lock is held, and the waiting thread is blocked.
+unsafe impl<K: Send, T: Send> Send for KeyedExchange<K, T> {}
+unsafe impl<K: Send, T: Send> Sync for KeyedExchange<K, T> {}
@@ -0,0 +1,74 @@
+//! Lanes, which are extra pairs split off from an existing pair.
+
+use std::any;
+use std::any::Any;
+use std::any::TypeId;
+use std::ptr::NonNull;
+
+use Offer;
+use SwapError;
+use Swapper;
+use swapper;
+
+impl<T: Send> Swapper<T> {
+    /// Split off a new pair, which swaps data of type `U`, between the same two threads.
+    ///
+    /// Both halves must call `split_off` for the same type, and each gets one half of the
+    /// new pair, without having to send it over a separate channel. This is for threads
+    /// which need several independent lanes, for example one for buffers and one for
+    /// statistics. This does not count as a swap, so does not start a new generation.
+    ///
+    /// If the other half swaps in any other way, both get `SwapError::Mismatch`, and if it
+    /// splits off a lane of a different type, both get `SwapError::TypeMismatch`.
+    ///
+    /// Whichever half completes the split hands the other its half of the lane, so the lane's
+    /// type must be `Send`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// let (ab, ba) = swapper::swapper::<Vec<u8>>();
+    /// let helper = thread::spawn(move || {
+    ///     let stats = ba.split_off::<u64>().unwrap();
+    ///     let mut count = 7;
+    ///     stats.swap(&mut count).unwrap();
+    ///     ba.swap(&mut vec![4, 5, 6]).unwrap();
+    /// });
+    /// let stats = ab.split_off::<u64>().unwrap();
+    /// let mut count = 0;
+    /// stats.swap(&mut count).unwrap();
+    /// assert_eq!(count, 7);
+    /// let mut buffer = vec![1, 2, 3];
+    /// ab.swap(&mut buffer).unwrap();
+    /// assert_eq!(buffer, [4, 5, 6]);
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn split_off<U: Any + Send>(&self) -> Result<Swapper<U>, SwapError> {
+        let mut lane: Option<Swapper<U>> = None;
+        let our_offer = Offer {
+            lane: Some((TypeId::of::<U>(), any::type_name::<U>())),
+            ..Offer::new(NonNull::from(&mut lane).cast())
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            // Both offers are for lanes of type `U`, so their data is where to put each half.
+            let (ours, theirs) = swapper::<U>();
+            unsafe {
+                *our_ptr.cast::<Option<Swapper<U>>>().as_ptr() = Some(ours);
+                *their_ptr.cast::<Option<Swapper<U>>>().as_ptr() = Some(theirs);
+            }
+            (Ok(()), Ok(()))
+        })?;
+        // The swap succeeded, so whichever half completed it filled in our half of the lane.
+        Ok(lane.expect("Lane was not split off"))
+    }
+}
+
+/// The errors for offers to split off lanes which don't match.
+pub(crate) fn mismatch(ours: Option<(TypeId, &'static str)>, theirs: Option<(TypeId, &'static str)>) -> (Result<(), SwapError>, Result<(), SwapError>) {
+    match (ours, theirs) {
+        (Some((_, ours)), Some((_, theirs))) => (
+            Err(SwapError::TypeMismatch { ours, theirs }),
+            Err(SwapError::TypeMismatch { ours: theirs, theirs: ours }),
+        ),
+        _ => (Err(SwapError::Mismatch), Err(SwapError::Mismatch)),
+    }
+}
@@ -0,0 +1,163 @@
+//! Leases, which lend data to the other half for a limited time.
+
+use std::cell::Cell;
+use std::fmt;
+use std::ptr;
+use std::ptr::NonNull;
+use std::time::Duration;
+use std::time::Instant;
+
+use Offer;
+use SwapError;
+use Swapper;
+
+/// Data borrowed from the other half of a pair, which must be given back by a deadline.
+///
+/// The lender blocks until the data is given back, which happens automatically when the
+/// loan is dropped, or by calling `give_back`. Once the deadline has passed, `get_mut` gives
+/// the data back rather than returning it, so a holder which keeps checking the loan cannot
+/// hold on to it for longer than the lease. Nothing interrupts a holder which neither checks
+/// nor drops the loan, so it keeps the lender blocked until it does.
+///
+/// If giving the data back fails, for example because the pair is shut down, the swap is
+/// not undone: the lender is left holding the borrower's data, and the borrower's reference
+/// is left holding the lender's data.
+pub struct Loan<'a, T: 'a> {
+    swapper: &'a Swapper<T>,
+    data: &'a mut T,
+    deadline: Instant,
+    returned: Option<Result<(), SwapError>>,
+}
+
+/// Can offers with these leases be swapped? Either neither is a lease, or one lends
+/// and the other borrows.
+pub(crate) fn matches(ours: &Option<Cell<Option<Duration>>>, theirs: &Option<Cell<Option<Duration>>>) -> bool {
+    match (ours, theirs) {
+        (None, None) => true,
+        (Some(ours), Some(theirs)) => ours.get().is_some() != theirs.get().is_some(),
+        _ => false,
+    }
+}
+
+impl<T: Send> Swapper<T> {
+    /// Lend data to the other half, which must call `borrow`, for the given duration.
+    ///
+    /// This swaps our data with the borrower's, then blocks until the borrower gives it back,
+    /// by swapping again. The borrower can only use the data until the deadline, but gives it
+    /// back when it next checks the loan, or drops it, so this may block for longer than the
+    /// duration, see `Loan`. If the other half swaps in any other way, both halves get
+    /// `SwapError::Mismatch`, and the data is unchanged.
+    ///
+    /// If the data is lent but giving it back fails, this returns the error, and `our_ref` is
+    /// left holding the borrower's data, while the borrower holds ours.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || {
+    ///     let mut buffer = Vec::new();
+    ///     let mut loan = ba.borrow(&mut buffer).unwrap();
+    ///     loan.get_mut().unwrap().push(4);
+    /// });
+    /// let mut buffer = vec![1, 2, 3];
+    /// ab.lease(&mut buffer, Duration::from_secs(60)).unwrap();
+    /// assert_eq!(buffer, [1, 2, 3, 4]);
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn lease(&self, our_ref: &mut T, duration: Duration) -> Result<(), SwapError> {
+        let our_offer = Offer {
+            lease: Some(Cell::new(Some(duration))),
+            ..Offer::new(NonNull::from(&mut *our_ref))
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })?;
+        // Wait for the borrower to give the data back.
+        self.swap(our_ref)
+    }
+
+    /// Borrow data from the other half, which must call `lease`.
+    ///
+    /// This swaps our data with the lender's, and returns a loan, which gives the lender's
+    /// data back when dropped, after which `our_ref` holds our data again. If giving it back
+    /// fails, `our_ref` is left holding the lender's data.
+    pub fn borrow<'a>(&'a self, our_ref: &'a mut T) -> Result<Loan<'a, T>, SwapError> {
+        let our_offer = Offer {
+            lease: Some(Cell::new(None)),
+            ..Offer::new(NonNull::from(&mut *our_ref))
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })?;
+        // The lender filled in the duration when the swap completed.
+        let duration = our_offer.lease.and_then(Cell::into_inner).unwrap_or_default();
+        Ok(Loan {
+            swapper: self,
+            data: our_ref,
+            deadline: Instant::now() + duration,
+            returned: None,
+        })
+    }
+}
+
+impl<'a, T> Loan<'a, T> {
+    /// The borrowed data, if the lease has not expired.
+    ///
+    /// If it has expired, this gives the data back to the lender, blocking until it
+    /// has been taken, and returns `None`.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.returned.is_none() && Instant::now() < self.deadline {
+            Some(self.data)
+        } else {
+            let _ = self.swap_back();
+            None
+        }
+    }
+
+    /// Give the data back to the lender before the deadline.
+    ///
+    /// This is what dropping the loan does, but reports whether giving the data back failed.
+    pub fn give_back(mut self) -> Result<(), SwapError> {
+        self.swap_back()
+    }
+
+    fn swap_back(&mut self) -> Result<(), SwapError> {
+        if self.returned.is_none() {
+            let returned = self.swapper.rendezvous(self.data, |our_ptr, their_ptr| {
+                unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+                (Ok(()), Ok(()))
+            });
+            self.returned = Some(returned);
+        }
+        self.returned.unwrap()
+    }
+
+    /// When the data must be given back.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Has the lease expired, or the data been given back?
+    pub fn is_expired(&self) -> bool {
+        self.returned.is_some() || Instant::now() >= self.deadline
+    }
+}
+
+impl<'a, T> Drop for Loan<'a, T> {
+    fn drop(&mut self) {
+        let _ = self.swap_back();
+    }
+}
+
+impl<'a, T> fmt::Debug for Loan<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Loan")
+            .field("swapper", self.swapper)
+            .field("deadline", &self.deadline)
+            .field("returned", &self.returned)
+            .finish()
+    }
+}
@@ -1,4 +1,3 @@
-
 //! Concurrency control for atomic swap of ownership.
 //!
 //! A common pattern for thread pools is that each thread owns a token,
@@ -105,24 +104,349 @@
 //!    }
 //! }
 //! ```
+//!
+//! This pattern is packaged up as `SwapRequest`, which can be embedded in a message type
+//! by implementing `SwapMessage`.
+
+// Building for wasm32 with atomics requires nightly, which also provides the wait intrinsics.
+#![cfg_attr(all(target_arch = "wasm32", target_feature = "atomics"), feature(stdarch_wasm_atomic_wait))]
 
+use std::any::TypeId;
+use std::cell::Cell;
+use std::fmt;
+use std::hint;
+use std::marker::PhantomData;
 use std::mem;
+use std::mem::MaybeUninit;
 use std::ptr;
+use std::ptr::NonNull;
 use std::sync::Arc;
-use std::sync::atomic::AtomicPtr;
+use std::sync::atomic;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
 use std::sync::mpsc::RecvError;
-use std::sync::mpsc::Sender;
 use std::sync::mpsc::SendError;
+use std::thread;
+use std::thread::ThreadId;
+use std::time::Duration;
+use std::time::Instant;
+
+use history::History;
+use observer::Observer;
+use padded::CachePadded;
+use slot::Slot;
+use wake::TaskWaker;
+use wake::Waiter;
+use wake::Waker;
+use watchdog::Watchdog;
+
+#[cfg(feature = "remote")]
+extern crate bincode;
+#[cfg(all(any(feature = "pi", feature = "process"), target_os = "linux"))]
+extern crate libc;
+#[cfg(loom)]
+extern crate loom;
+#[cfg(all(feature = "parking-lot", not(loom)))]
+extern crate parking_lot_core;
+#[cfg(feature = "remote")]
+extern crate serde;
+#[cfg(feature = "tokio-util")]
+extern crate tokio_util;
+
+mod any;
+mod boxed;
+mod broadcast;
+mod buffered;
+mod cancel;
+mod config;
+#[cfg(feature = "deadlock-detection")]
+pub mod deadlock;
+mod deposit;
+mod epoch;
+mod exchange;
+mod executor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod handoff;
+mod history;
+mod inspect;
+mod isr;
+mod keyed;
+mod lane;
+mod lease;
+mod lock;
+mod negotiate;
+mod neighbours;
+mod observer;
+mod oneshot;
+mod padded;
+#[cfg(all(feature = "pi", target_os = "linux"))]
+pub mod pi;
+mod pipeline;
+mod pool;
+#[cfg(all(feature = "process", target_os = "linux"))]
+pub mod process;
+mod pump;
+pub mod raw;
+pub mod registry;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "replay")]
+pub mod replay;
+mod request;
+mod scoped;
+mod set;
+mod shuffle;
+mod slot;
+mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod wake;
+mod watchdog;
+mod weak;
+
+pub use any::AnySwapper;
+pub use any::any_swapper;
+pub use boxed::BigSwap;
+pub use boxed::SwapBox;
+pub use boxed::big_swapper;
+pub use broadcast::BroadcastError;
+pub use broadcast::BroadcastSwap;
+pub use broadcast::GatherSwap;
+pub use broadcast::GatherWorker;
+pub use broadcast::broadcast;
+pub use broadcast::gather;
+pub use buffered::BufferedSwapper;
+pub use buffered::buffered_swapper;
+pub use cancel::CancelToken;
+pub use config::ConfigReader;
+pub use config::ConfigSwap;
+pub use config::config_swap;
+pub use deposit::CollectError;
+pub use deposit::Pending;
+pub use deposit::SwapFuture;
+pub use epoch::EpochError;
+pub use epoch::EpochTimeout;
+pub use epoch::SwapEpoch;
+pub use exchange::Exchange;
+pub use executor::WorkSwapper;
+pub use executor::WorkToken;
+pub use executor::work_swapping;
+pub use handoff::GiveError;
+pub use handoff::Giver;
+pub use handoff::Taker;
+pub use handoff::handoff;
+pub use history::SwapRecord;
+pub use isr::IsrSwapper;
+pub use keyed::KeyedExchange;
+pub use lease::Loan;
+pub use lock::SwapGuard;
+pub use lock::SwapLock;
+pub use lock::swap_lock;
+pub use negotiate::Negotiation;
+pub use negotiate::PendingNegotiation;
+pub use neighbours::HaloExchange;
+pub use neighbours::Neighbours;
+pub use neighbours::halo_exchange;
+pub use neighbours::neighbours;
+pub use observer::SwapObserver;
+pub use observer::set_global_observer;
+pub use oneshot::OneShotSwapper;
+pub use oneshot::ReusableSwapper;
+pub use oneshot::oneshot_swapper;
+pub use oneshot::reusable_swapper;
+pub use pipeline::Pipeline;
+pub use pipeline::PipelineBuilder;
+pub use pipeline::PipelineError;
+pub use pool::PooledSwapper;
+pub use pool::SwapperPool;
+pub use pump::SwapPump;
+pub use request::SwapMessage;
+pub use request::SwapRequest;
+pub use scoped::ScopedSlot;
+pub use scoped::ScopedSwapper;
+pub use set::AnyNode;
+pub use set::PairingPolicy;
+pub use set::PreferSameNode;
+pub use set::SetMember;
+pub use set::SwapperSet;
+pub use shuffle::ShuffleExchange;
+pub use shuffle::shuffle;
+pub use shuffle::shuffle_seeded;
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+pub use wake::set_blocking_allowed;
+pub use watchdog::BlockedSwap;
+pub use weak::SwapperWeak;
 
 /// A concurrency control for swapping ownership between threads.
+///
+/// A swapper can swap repeatedly, see `ReusableSwapper`. For a pair which swaps once,
+/// see `OneShotSwapper`.
+///
+/// A half can be sent to another thread, but not shared between threads, whichever
+/// backend it waits with, since two threads swapping on the same half could take
+/// each other's offer.
+///
+/// ```rust,compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<swapper::Swapper<u32>>();
+/// ```
+pub struct Swapper<T: ?Sized> {
+    shared: Arc<Shared>,
+    wait: Waiter,
+    notify: Waker,
+    strategy: WaitStrategy,
+    // Which half of the pair this is, 0 or 1.
+    half: usize,
+    marker: PhantomData<T>,
+}
 
-pub struct Swapper<T> {
-    contents: Arc<AtomicPtr<T>>,
-    wait: Receiver<()>,
-    notify: Sender<()>,
+/// The state shared by both halves of a swap pair.
+///
+/// The slot is type-erased so that the debug registry can inspect pairs of any type.
+struct Shared {
+    id: u64,
+    name: Option<String>,
+    // The slot is written by both halves on every swap, so is kept on its own cache line.
+    slot: CachePadded<Slot>,
+    // The number of swaps completed by the pair.
+    swaps: AtomicU64,
+    // Whether the pair is registered with a `SwapEpoch`, which is notified of each swap.
+    watched: AtomicBool,
+    // The number of halves, strong or weak, which have not been dropped.
+    halves: AtomicUsize,
+    // Set when the pair is shut down, so swaps which find it disconnected report why.
+    shutdown: AtomicBool,
+    // Called when a swap has been blocked for too long.
+    watchdog: Option<Watchdog>,
+    // The most recent swaps, if the pair is tracking them.
+    history: Option<History>,
+    // Called back at each step of the protocol.
+    observer: Option<Observer>,
+}
+
+impl Shared {
+    /// Has one of the halves been dropped?
+    fn is_disconnected(&self) -> bool {
+        self.halves.load(Ordering::Acquire) < 2
+    }
+
+    /// What the other half was doing when a timed-out offer was retracted.
+    fn partner_state(&self) -> PartnerState {
+        if self.is_disconnected() {
+            PartnerState::Disconnected
+        } else if !self.slot.is_empty() {
+            // Our offer has been retracted, so this must be an offer from the other half.
+            PartnerState::ArrivedDuringRetraction
+        } else {
+            PartnerState::NeverArrived
+        }
+    }
+
+    /// Record that a swap has completed.
+    ///
+    /// This is called by the thread that completes the swap using the given half, before it
+    /// unblocks the thread which offered to swap, if any.
+    fn swapped(&self, half: usize, offerer: Option<ThreadId>) {
+        #[cfg(feature = "replay")]
+        replay::record(self, half, offerer);
+        if let Some(ref history) = self.history {
+            let mut threads = [None, None];
+            threads[half] = Some(thread::current().id());
+            threads[1 - half] = offerer;
+            history.record(SwapRecord {
+                generation: self.swaps.load(Ordering::Acquire),
+                threads,
+                at: Instant::now(),
+            });
+        }
+        observer::observe(&self.observer, |observer| {
+            observer.on_swap(self.id, half, self.swaps.load(Ordering::Acquire))
+        });
+        self.swaps.fetch_add(1, Ordering::AcqRel);
+        if self.watched.load(Ordering::Acquire) {
+            epoch::notify();
+        }
+    }
+}
+
+/// An offer to swap, which lives on the stack of the offering thread while it is blocked,
+/// or on the heap for a deposit or a prepared two-phase swap.
+struct Offer<T: ?Sized> {
+    data: NonNull<T>,
+    // The thread blocked waiting for the offer to be taken, if any.
+    thread: Option<ThreadId>,
+    // The generation the offer was made in, for a sequenced swap.
+    generation: Option<u64>,
+    // For an observing offer, copies the taker's data into ours, which is uninitialized.
+    clone: Option<unsafe fn(NonNull<T>, NonNull<T>)>,
+    // Set by the thread that takes the offer, before it unblocks the offering thread.
+    outcome: Cell<Result<(), SwapError>>,
+    // The async task waiting for the offer to be taken, if any.
+    task: Option<Arc<TaskWaker>>,
+    // When the offering thread gives up waiting and retracts the offer, if ever.
+    deadline: Option<Instant>,
+    // For an initializing offer, whether the data is initialized, which the taker updates.
+    initialized: Option<Cell<bool>>,
+    // A token which can cancel the offer while the offering thread waits.
+    cancel: Option<CancelToken>,
+    // For a lease, the duration of the loan, which the taker fills in for a borrower.
+    lease: Option<Cell<Option<Duration>>>,
+    // For an inspecting offer, which has no data, so the taker offers its data instead.
+    inspect: bool,
+    // For an offer to split off a new lane, the type the lane swaps. The data is then
+    // where to put our half of the lane.
+    lane: Option<(TypeId, &'static str)>,
+    // For a prepared two-phase swap, the offer of the half which joined it, which the
+    // joining half fills in before waking the preparing half to negotiate.
+    negotiator: Option<Cell<Option<NonNull<Offer<T>>>>>,
+}
+
+impl<T: ?Sized> Offer<T> {
+    /// An offer to swap data, from a thread which blocks until it is taken.
+    fn new(data: NonNull<T>) -> Offer<T> {
+        Offer {
+            data,
+            thread: Some(thread::current().id()),
+            generation: None,
+            clone: None,
+            outcome: Cell::new(Ok(())),
+            task: None,
+            deadline: None,
+            initialized: None,
+            cancel: None,
+            lease: None,
+            inspect: false,
+            lane: None,
+            negotiator: None,
+        }
+    }
+
+    /// Is this an offer of data for an ordinary swap, rather than to observe, inspect,
+    /// initialize, lease, split off a lane, or negotiate?
+    fn is_plain(&self) -> bool {
+        self.clone.is_none()
+            && self.initialized.is_none()
+            && self.lease.is_none()
+            && !self.inspect
+            && self.lane.is_none()
+            && self.negotiator.is_none()
+    }
+
+    /// Was this offer made by the same blocked thread as another?
+    ///
+    /// Under loom, every modelled thread runs on the same OS thread, so thread ids cannot
+    /// tell them apart, and no two offers are treated as coming from the same thread.
+    fn same_thread(&self, other: &Offer<T>) -> bool {
+        cfg!(not(loom)) && self.thread == other.thread
+    }
+}
+
+/// Copy the data from `src` into the uninitialized `dst`.
+unsafe fn clone_into<T: Clone>(src: NonNull<T>, dst: NonNull<T>) {
+    dst.as_ptr().write(src.as_ref().clone());
 }
 
 impl<T: Send> Swapper<T> {
@@ -130,61 +454,858 @@ impl<T: Send> Swapper<T> {
     ///
     /// If the other half of the swap pair is blocked waiting to swap, then it swaps ownership
     /// of the data, then unblocks the other thread. Otherwise it blocks waiting to swap.
+    ///
+    /// If both halves of the pair are used on the same thread, then the second call returns
+    /// `SwapError::WouldDeadlock` rather than waiting for an offer from its own thread.
     pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.rendezvous(our_ref, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })
+    }
+
+    /// Swap data, as the given generation of a sequenced protocol.
+    ///
+    /// Each successful swap starts a new generation, so in a protocol where each half swaps
+    /// exactly once per round, both halves should pass `current_gen()`. If the generation
+    /// is not the current one, this fails immediately. If both halves make sequenced swaps
+    /// with different generations, then neither is modified. In either case the error is
+    /// `SwapError::Sequence`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::SwapError;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || ba.swap_seq(&mut 1, 0).unwrap());
+    /// ab.swap_seq(&mut 2, 0).unwrap();
+    /// # helper.join().unwrap();
+    /// assert_eq!(ab.current_gen(), 1);
+    /// assert_eq!(ab.swap_seq(&mut 2, 0), Err(SwapError::Sequence { ours: 0, theirs: 1 }));
+    /// ```
+    pub fn swap_seq(&self, our_ref: &mut T, generation: u64) -> Result<(), SwapError> {
+        let current = self.current_gen();
+        if generation != current {
+            return Err(SwapError::Sequence {
+                ours: generation,
+                theirs: current,
+            });
+        }
+        let our_offer = Offer {
+            generation: Some(generation),
+            ..Offer::new(NonNull::from(our_ref))
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })
+    }
+
+    /// Swap data, giving up if the other half does not swap within the timeout.
+    ///
+    /// On timeout the offer is retracted, and the error reports what the other half
+    /// was doing, see `PartnerState`.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use swapper::{PartnerState, SwapError};
+    /// let (ab, ba) = swapper::swapper();
+    /// let timeout = Duration::from_millis(10);
+    /// let partner = PartnerState::NeverArrived;
+    /// assert_eq!(ab.swap_timeout(&mut 1, timeout), Err(SwapError::Timeout { partner }));
+    /// # drop(ba);
+    /// ```
+    pub fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        let our_offer = Offer {
+            deadline: Some(Instant::now() + timeout),
+            ..Offer::new(NonNull::from(our_ref))
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), their_ptr.as_ptr(), 1) };
+            (Ok(()), Ok(()))
+        })
+    }
+
+    /// Trade a value for the other half's, or for a replacement made by `fallback` if the
+    /// swap fails, for example because the other half has been dropped.
+    ///
+    /// On failure, our value is dropped, and `fallback` is called with the error.
+    ///
+    /// ```rust
+    /// let (ab, ba) = swapper::swapper();
+    /// drop(ba);
+    /// let value = ab.swap_or_else(String::from("ours"), |_| String::from("replacement"));
+    /// assert_eq!(value, "replacement");
+    /// ```
+    pub fn swap_or_else<F: FnOnce(SwapError) -> T>(&self, mut value: T, fallback: F) -> T {
+        match self.swap(&mut value) {
+            Ok(()) => value,
+            Err(err) => fallback(err),
+        }
+    }
+}
+
+impl<T: Default + Send> Swapper<T> {
+    /// Trade a value for the other half's, or for the default if the swap fails,
+    /// for example because the other half has been dropped.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || ba.swap_or_default(vec![1, 2, 3]));
+    /// assert_eq!(ab.swap_or_default(vec![4]), [1, 2, 3]);
+    /// assert_eq!(helper.join().unwrap(), [4]);
+    /// assert_eq!(ab.swap_or_default(vec![5]), []);
+    /// ```
+    pub fn swap_or_default(&self, value: T) -> T {
+        self.swap_or_else(value, |_| T::default())
+    }
+}
+
+impl<T: Clone + Send> Swapper<T> {
+    /// Take a copy of the other half's data, without swapping.
+    ///
+    /// This waits for the other half to swap, and copies its data while it is blocked.
+    /// The other half's swap then succeeds, but leaves its data unchanged. This does not
+    /// count as a swap, so does not start a new generation. If both halves call
+    /// `swap_cloned`, the second returns `SwapError::Mismatch`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || {
+    ///     let mut token = String::from("hello");
+    ///     ab.swap(&mut token).unwrap();
+    ///     assert_eq!(token, "hello");
+    /// });
+    /// assert_eq!(ba.swap_cloned().unwrap(), "hello");
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn swap_cloned(&self) -> Result<T, SwapError> {
+        let mut copy = MaybeUninit::<T>::uninit();
+        let our_offer = Offer {
+            clone: Some(clone_into::<T>),
+            ..Offer::new(NonNull::from(&mut copy).cast())
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            unsafe { clone_into(their_ptr, our_ptr) };
+            (Ok(()), Ok(()))
+        })?;
+        // The copy has been initialized, either by us or by the other half.
+        Ok(unsafe { copy.assume_init() })
+    }
+}
+
+impl<T: Send> Swapper<T> {
+    /// Swap data which may be uninitialized, returning whether ours is now initialized.
+    ///
+    /// Both halves must call `swap_init`, otherwise both calls return `SwapError::Mismatch`.
+    /// The data and whether it is initialized are swapped, so if one half offers a value
+    /// and the other an uninitialized slot, the value is moved into the slot, without
+    /// needing a default value to send back. This allows a value to be built in place
+    /// on one thread, and handed to another.
+    ///
+    /// ```rust
+    /// # use std::mem::MaybeUninit;
+    /// # use std::thread;
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || {
+    ///     let mut frame = MaybeUninit::new([7u8; 1024]);
+    ///     assert!(!unsafe { ab.swap_init(&mut frame, true) }.unwrap());
+    /// });
+    /// let mut frame = MaybeUninit::uninit();
+    /// assert!(unsafe { ba.swap_init(&mut frame, false) }.unwrap());
+    /// assert_eq!(unsafe { frame.assume_init() }[0], 7);
+    /// # helper.join().unwrap();
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// If `initialized` is true, then the data must be initialized.
+    pub unsafe fn swap_init(&self, our_ref: &mut MaybeUninit<T>, initialized: bool) -> Result<bool, SwapError> {
+        let our_offer = Offer {
+            initialized: Some(Cell::new(initialized)),
+            ..Offer::new(NonNull::from(our_ref).cast())
+        };
+        self.rendezvous_offer(&our_offer, |our_ptr, their_ptr| {
+            // The data may be uninitialized, so swap it without reading it as a `T`.
+            ptr::swap_nonoverlapping(our_ptr.as_ptr().cast::<MaybeUninit<T>>(), their_ptr.as_ptr().cast(), 1);
+            (Ok(()), Ok(()))
+        })?;
+        Ok(our_offer.initialized.is_some_and(Cell::into_inner))
+    }
+}
+
+impl<T: ?Sized + Send> Swapper<T> {
+    /// Swap data which may be unsized, such as slices or trait objects.
+    ///
+    /// The data is exchanged byte-wise, so both halves must offer data with the same
+    /// size and metadata (for example slices of the same length). If they do not, then
+    /// neither is modified and both calls return `SwapError::Mismatch`.
+    ///
+    /// Trait objects are compared by their vtables, which may be duplicated by the compiler,
+    /// so trait objects of the same type may be reported as mismatched.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// let mut ours = vec![0; 8];
+    /// let mut theirs = vec![1; 8];
+    /// let (ab, ba) = swapper::swapper::<[u8]>();
+    /// thread::scope(|scope| {
+    ///     let ours = &mut ours;
+    ///     scope.spawn(move || ab.swap_unsized(&mut ours[..4]).unwrap());
+    ///     ba.swap_unsized(&mut theirs[4..]).unwrap();
+    /// });
+    /// assert_eq!(ours, [1, 1, 1, 1, 0, 0, 0, 0]);
+    /// assert_eq!(theirs, [1, 1, 1, 1, 0, 0, 0, 0]);
+    /// ```
+    pub fn swap_unsized(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.rendezvous(our_ref, |our_ptr, their_ptr| {
+            // Only the address of the data may differ, not its metadata.
+            let our_ptr = our_ptr.as_ptr();
+            if !ptr::eq(their_ptr.as_ptr().with_addr(our_ptr.addr()), our_ptr) {
+                return (Err(SwapError::Mismatch), Err(SwapError::Mismatch));
+            }
+            let size = mem::size_of_val(unsafe { &*our_ptr });
+            unsafe { ptr::swap_nonoverlapping(our_ptr.cast::<u8>(), their_ptr.as_ptr().cast::<u8>(), size) };
+            (Ok(()), Ok(()))
+        })
+    }
+}
+
+impl<T: ?Sized> Swapper<T> {
+    /// Wait for both halves of the pair to be ready, then exchange their data.
+    ///
+    /// The exchange is performed by whichever thread arrives second, while the other
+    /// is blocked. It returns the outcome for the thread performing the exchange,
+    /// and the outcome for the blocked thread.
+    fn rendezvous<F>(&self, our_ref: &mut T, exchange: F) -> Result<(), SwapError>
+    where
+        F: FnOnce(NonNull<T>, NonNull<T>) -> (Result<(), SwapError>, Result<(), SwapError>),
+    {
+        self.rendezvous_offer(&Offer::new(NonNull::from(our_ref)), exchange)
+    }
+
+    /// Rendezvous with a given offer, which may be sequenced or observing.
+    fn rendezvous_offer<F>(&self, our_offer: &Offer<T>, exchange: F) -> Result<(), SwapError>
+    where
+        F: FnOnce(NonNull<T>, NonNull<T>) -> (Result<(), SwapError>, Result<(), SwapError>),
+    {
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::used(self.shared.id, self.half);
+        match self.rendezvous_loop(our_offer, exchange) {
+            // The other half was dropped because the pair was shut down.
+            Err(SwapError::Disconnected) if self.shared.shutdown.load(Ordering::Acquire) => Err(SwapError::Shutdown),
+            result => result,
+        }
+    }
+
+    fn rendezvous_loop<F>(&self, our_offer: &Offer<T>, exchange: F) -> Result<(), SwapError>
+    where
+        F: FnOnce(NonNull<T>, NonNull<T>) -> (Result<(), SwapError>, Result<(), SwapError>),
+    {
         loop {
             // Is the other thead blocked waiting to swap? If so, swap and unblock it.
-            let their_ptr = self.contents.swap(ptr::null_mut(), Ordering::AcqRel);
-            if let Some(their_ref) = unsafe { their_ptr.as_mut() } {
+            if let Some(their_offer) = self.shared.slot.take::<Offer<T>>() {
+                let their_offer = unsafe { their_offer.as_ref() };
+                if their_offer.same_thread(our_offer) {
+                    // The offer was made by this thread, which cannot both wait for the swap
+                    // and complete it, so put the offer back and report the deadlock.
+                    self.shared.slot.offer(NonNull::from(their_offer));
+                    return Err(SwapError::WouldDeadlock);
+                }
+                if their_offer.inspect {
+                    if !our_offer.is_plain() {
+                        // There is no data for the other half to inspect.
+                        self.shared.slot.offer(NonNull::from(their_offer));
+                        return Err(SwapError::Mismatch);
+                    }
+                    // The other half is inspecting, so offer our data in place of its offer,
+                    // and wake it to take ours. Both halves are in the swap, so the slot is ours.
+                    self.shared.slot.offer(NonNull::from(our_offer));
+                    self.complete(their_offer)?;
+                    return match self.await_taken(our_offer) {
+                        Ok(()) => our_offer.outcome.get(),
+                        Err(err) => Err(err),
+                    };
+                }
+                if let Some(ref negotiator) = their_offer.negotiator {
+                    if !our_offer.is_plain() {
+                        // Only data for an ordinary swap can be negotiated for.
+                        self.shared.slot.offer(NonNull::from(their_offer));
+                        return Err(SwapError::Mismatch);
+                    }
+                    // The other half prepared a two-phase swap, so join it, and wait for
+                    // the other half to commit or abort.
+                    negotiator.set(Some(NonNull::from(our_offer)));
+                    self.complete(their_offer)?;
+                    return match self.await_taken(our_offer) {
+                        Ok(()) => our_offer.outcome.get(),
+                        Err(err) => Err(err),
+                    };
+                }
+                if let Some(clone) = their_offer.clone {
+                    if !our_offer.is_plain() {
+                        // Both halves are observing, or our data may be uninitialized, leased,
+                        // or not data at all, so there is no data to copy.
+                        self.shared.slot.offer(NonNull::from(their_offer));
+                        return Err(SwapError::Mismatch);
+                    }
+                    // The other thread is observing, so give it a copy of our data, and keep ours.
+                    unsafe { clone(our_offer.data, their_offer.data) };
+                    self.complete(their_offer)?;
+                    return Ok(());
+                }
                 // The safety of this implementation depends on the other thread being blocked
                 // while this swap happens.
-                mem::swap(our_ref, their_ref);
+                let (our_outcome, their_outcome) = match (our_offer.generation, their_offer.generation) {
+                    (Some(ours), Some(theirs)) if ours != theirs => (
+                        Err(SwapError::Sequence { ours, theirs }),
+                        Err(SwapError::Sequence { ours: theirs, theirs: ours }),
+                    ),
+                    // Data which may be uninitialized can only be swapped with other such data.
+                    _ if our_offer.initialized.is_some() != their_offer.initialized.is_some() => {
+                        (Err(SwapError::Mismatch), Err(SwapError::Mismatch))
+                    }
+                    // A lease must be between a lender and a borrower.
+                    _ if !lease::matches(&our_offer.lease, &their_offer.lease) => {
+                        (Err(SwapError::Mismatch), Err(SwapError::Mismatch))
+                    }
+                    // Lanes can only be split off by both halves, for the same type.
+                    _ if our_offer.lane != their_offer.lane => lane::mismatch(our_offer.lane, their_offer.lane),
+                    _ => exchange(our_offer.data, their_offer.data),
+                };
+                if let (Some(ours), Some(theirs)) = (&our_offer.initialized, &their_offer.initialized) {
+                    if our_outcome.is_ok() {
+                        ours.swap(theirs);
+                    }
+                }
+                if let (Some(ours), Some(theirs)) = (&our_offer.lease, &their_offer.lease) {
+                    if our_outcome.is_ok() {
+                        ours.set(ours.get().or(theirs.get()));
+                        theirs.set(ours.get());
+                    }
+                }
+                if our_outcome.is_ok() && our_offer.clone.is_none() && our_offer.lane.is_none() {
+                    self.shared.swapped(self.half, their_offer.thread);
+                }
+                their_offer.outcome.set(their_outcome);
                 // We have swapped ownership, so its now safe to unblock the other thread.
-                try!(self.notify.send(()));
-                return Ok(());
+                self.complete(their_offer)?;
+                return our_outcome;
             }
             // Is the other thead not ready for a swap yet? If so, block waiting to swap.
-            let their_ptr = self.contents.compare_and_swap(ptr::null_mut(), our_ref, Ordering::AcqRel);
-            if their_ptr.is_null() {
-                try!(self.wait.recv());
-                return Ok(());
+            if self.shared.slot.offer(NonNull::from(our_offer)) {
+                observer::observe(&self.shared.observer, |observer| observer.on_offer(self.shared.id, self.half));
+                return match self.await_taken(our_offer) {
+                    Ok(()) => our_offer.outcome.get(),
+                    Err(err) => Err(err),
+                };
+            }
+        }
+    }
+
+    /// Unblock the other half, once we have completed its offer.
+    ///
+    /// The other half may free its offer as soon as it is unblocked, so anything needed to wake
+    /// an async task is copied out of the offer first.
+    fn complete(&self, their_offer: &Offer<T>) -> Result<(), SwapError> {
+        complete(&self.notify, their_offer)
+    }
+
+    /// Wait for our offer to be taken and completed, using this half's wait strategy.
+    ///
+    /// If this returns an error, the offer has been retracted.
+    fn await_taken(&self, our_offer: &Offer<T>) -> Result<(), SwapError> {
+        let offer = NonNull::from(our_offer);
+        let budget = match self.strategy {
+            WaitStrategy::Park => 0,
+            WaitStrategy::Spin(budget) | WaitStrategy::SpinThenPark(budget) => budget,
+        };
+        for _ in 0..budget {
+            match self.wait.try_wait() {
+                Ok(true) => return Ok(()),
+                Ok(false) => hint::spin_loop(),
+                Err(err) => {
+                    self.shared.slot.retract(offer);
+                    return Err(err);
+                }
+            }
+        }
+        if let WaitStrategy::Spin(_) = self.strategy {
+            if self.shared.slot.retract(offer) {
+                return Err(SwapError::WouldBlock);
+            }
+            // The offer has already been taken, so the other half is about to wake us.
+            while !self.wait.try_wait()? {
+                hint::spin_loop();
+            }
+            return Ok(());
+        }
+        #[cfg(feature = "deadlock-detection")]
+        let _waiting = deadlock::wait_for(self.shared.id, self.half, || !self.shared.slot.is_empty());
+        let _watched = self.shared.watchdog.as_ref().map(|watchdog| watchdog::watch(&self.shared, self.half, watchdog));
+        observer::observe(&self.shared.observer, |observer| observer.on_park(self.shared.id, self.half));
+        let parked = Instant::now();
+        let result = self.await_parked(our_offer, offer);
+        observer::observe(&self.shared.observer, |observer| observer.on_wake(self.shared.id, self.half, parked.elapsed()));
+        result
+    }
+
+    /// Block until our offer is taken and completed, or retracted.
+    fn await_parked(&self, our_offer: &Offer<T>, offer: NonNull<Offer<T>>) -> Result<(), SwapError> {
+        if let Some(ref token) = our_offer.cancel {
+            return self.await_cancellable(offer, token);
+        }
+        if self.released_before(offer) {
+            return Err(SwapError::Disconnected);
+        }
+        if let Some(deadline) = our_offer.deadline {
+            match self.wait.wait_until(deadline) {
+                Ok(true) => return Ok(()),
+                Ok(false) if self.shared.slot.retract(offer) => {
+                    return Err(SwapError::Timeout {
+                        partner: self.shared.partner_state(),
+                    });
+                }
+                // The offer was taken before we could retract it, so the other half is about to wake us.
+                Ok(false) => (),
+                Err(err) => {
+                    self.shared.slot.retract(offer);
+                    return Err(err);
+                }
+            }
+        }
+        match self.wait.wait() {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                // The other thread has dropped its swapper without taking our offer,
+                // so retract it rather than leave a dangling pointer in the slot.
+                self.shared.slot.retract(offer);
+                Err(err)
             }
         }
     }
+
+    /// Was the other half released before it could see our offer? If so, retract it.
+    ///
+    /// A dropped half also drops its `Waker`, so our `Waiter` would report the disconnection,
+    /// but a half released into a `SwapperPool` keeps its `Waker` to be reused.
+    fn released_before(&self, offer: NonNull<Offer<T>>) -> bool {
+        // Pairs with the fence in `release`, so either we see the other half has been released,
+        // or it sees our offer.
+        atomic::fence(Ordering::SeqCst);
+        self.shared.is_disconnected() && self.shared.slot.retract(offer)
+    }
+
+    /// Wait for our offer to be taken, or for the token to be cancelled.
+    ///
+    /// The thread parks, and is unparked either by the token, or by the other half
+    /// through the offer's task waker.
+    fn await_cancellable(&self, offer: NonNull<Offer<T>>, token: &CancelToken) -> Result<(), SwapError> {
+        // Pairs with the fence in `release`, so either we see the other half has been dropped,
+        // or it sees our offer.
+        atomic::fence(Ordering::SeqCst);
+        loop {
+            match self.wait.try_wait() {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                Err(err) => {
+                    self.shared.slot.retract(offer);
+                    return Err(err);
+                }
+            }
+            if token.is_cancelled() || self.shared.is_disconnected() {
+                if self.shared.slot.retract(offer) {
+                    return Err(if token.is_cancelled() {
+                        SwapError::Cancelled
+                    } else {
+                        SwapError::Disconnected
+                    });
+                }
+                // The offer has already been taken, so the other half is about to wake us.
+                return self.wait.wait();
+            }
+            thread::park();
+        }
+    }
+}
+
+impl<T: ?Sized> Swapper<T> {
+    /// The unique id of this swap pair, shared by both halves.
+    pub fn id(&self) -> u64 {
+        self.shared.id
+    }
+
+    /// How this half waits for the other half to swap.
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.strategy
+    }
+
+    /// Set how this half waits for the other half to swap, without affecting the other half.
+    pub fn set_wait_strategy(&mut self, strategy: WaitStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// The name given to this swap pair by `SwapperBuilder::name`, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.shared.name.as_deref()
+    }
+
+    /// The most recent swaps of the pair, oldest first, if it was built to track them
+    /// with `SwapperBuilder::history`.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::SwapperBuilder;
+    /// let (ab, ba) = SwapperBuilder::new().history(8).build();
+    /// let helper = thread::spawn(move || {
+    ///     ba.swap(&mut 1).unwrap();
+    ///     thread::current().id()
+    /// });
+    /// ab.swap(&mut 2).unwrap();
+    /// let helper = helper.join().unwrap();
+    /// let history = ab.history();
+    /// assert_eq!(history.len(), 1);
+    /// // Our data is now held by the thread which used the other half.
+    /// assert_eq!(history[0].threads[1], Some(helper));
+    /// ```
+    pub fn history(&self) -> Vec<SwapRecord> {
+        self.shared.history.as_ref().map(History::records).unwrap_or_default()
+    }
+
+    /// The current generation of the pair, which is the number of swaps it has completed.
+    pub fn current_gen(&self) -> u64 {
+        self.shared.swaps.load(Ordering::Acquire)
+    }
+
+    /// Convert this half of the pair into a raw pointer, for example to pass to foreign code.
+    ///
+    /// The swapper can be recovered with `Swapper::from_raw`.
+    pub fn into_raw(self) -> *mut Swapper<T> {
+        Box::into_raw(Box::new(self))
+    }
+
+    /// Recover a swapper from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by `Swapper::into_raw` at the same type,
+    /// and must not have already been recovered.
+    pub unsafe fn from_raw(raw: *mut Swapper<T>) -> Swapper<T> {
+        *Box::from_raw(raw)
+    }
+
+    /// Inspect the state of the pair, as seen from this half.
+    ///
+    /// This only reads the shared state, so it does not perturb a concurrent swap
+    /// by the other half. The result may be out of date as soon as it is returned.
+    pub fn state(&self) -> SwapState {
+        if self.shared.is_disconnected() {
+            SwapState::Disconnected
+        } else if self.shared.slot.is_empty() {
+            SwapState::Idle
+        } else {
+            // We can't be swapping ourselves, so the offer must be from the other half.
+            SwapState::PartnerWaiting
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Swapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Swapper")
+            .field("id", &self.shared.id)
+            .field("name", &self.shared.name)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// How one half of a pair waits for the other half to swap.
+///
+/// Each half has its own strategy, so for example a real-time thread which must not block
+/// can spin, while the thread it swaps with parks:
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::{SwapError, SwapperBuilder, WaitStrategy};
+/// let (realtime, helper) = SwapperBuilder::new()
+///     .wait_strategies(WaitStrategy::Spin(1000), WaitStrategy::Park)
+///     .build();
+/// // No one is waiting to swap, so the real-time thread gives up.
+/// assert_eq!(realtime.swap(&mut 1), Err(SwapError::WouldBlock));
+/// let helper = thread::spawn(move || helper.swap(&mut 2).unwrap());
+/// while realtime.swap(&mut 1).is_err() {}
+/// # helper.join().unwrap();
+/// ```
+///
+/// Spinning avoids the system calls made by parking, but waking a parked partner
+/// may still make one.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum WaitStrategy {
+    /// Block the thread until the other half swaps.
+    #[default]
+    Park,
+    /// Spin for up to the given number of iterations. If the other half has not swapped
+    /// by then, retract the offer and return `SwapError::WouldBlock`.
+    Spin(u32),
+    /// Spin for up to the given number of iterations, then block.
+    SpinThenPark(u32),
+}
+
+/// The state of a swap pair, as seen from one of its halves.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SwapState {
+    /// The other half is not waiting to swap.
+    Idle,
+    /// The other half is blocked waiting for us to swap.
+    PartnerWaiting,
+    /// The other half has been dropped, so any swap will fail.
+    Disconnected,
+}
+
+impl<T: ?Sized> Drop for Swapper<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::dropped(self.shared.id, self.half);
+        release::<T>(&self.shared, &self.notify);
+    }
+}
+
+/// Complete an offer from the other half, and unblock it.
+///
+/// The other half may free its offer as soon as it is unblocked, so anything needed to wake
+/// an async task is copied out of the offer first.
+fn complete<T: ?Sized>(notify: &Waker, their_offer: &Offer<T>) -> Result<(), SwapError> {
+    #[cfg(feature = "deadlock-detection")]
+    deadlock::woken(their_offer.thread);
+    let task = their_offer.task.clone();
+    notify.wake()?;
+    if let Some(task) = task {
+        task.wake();
+    }
+    Ok(())
+}
+
+/// Release a half of a pair which is being dropped, or returned to a `SwapperPool`.
+///
+/// The half cannot be swapping, so any offer in the slot is from the other half, and can
+/// never be taken, so it is completed with `SwapError::Disconnected`. A thread blocked on
+/// its `Waiter` would notice the disconnection anyway, but an async task would not.
+pub(crate) fn release<T: ?Sized>(shared: &Shared, notify: &Waker) {
+    shared.halves.fetch_sub(1, Ordering::SeqCst);
+    // Pairs with the fence after making an offer which can be cancelled or polled,
+    // so either we see the offer, or the other half sees it has been disconnected.
+    atomic::fence(Ordering::SeqCst);
+    if let Some(their_offer) = shared.slot.take::<Offer<T>>() {
+        let their_offer = unsafe { their_offer.as_ref() };
+        their_offer.outcome.set(Err(SwapError::Disconnected));
+        let _ = complete(notify, their_offer);
+    }
 }
 
 // Be explicit about implementing Send.
-unsafe impl<T: Send> Send for Swapper<T> {}
+unsafe impl<T: ?Sized + Send> Send for Swapper<T> {}
 
 /// Create a new pair of swappers.
-pub fn swapper<T>() -> (Swapper<T>, Swapper<T>) {
-    let contents = Arc::new(AtomicPtr::new(ptr::null_mut()));
-    let (notify_a, wait_a) = mpsc::channel();
-    let (notify_b, wait_b) = mpsc::channel();
-    let swapper_a = Swapper {
-        contents: contents.clone(),
-        notify: notify_b,
-        wait: wait_a,
-    };
-    let swapper_b = Swapper {
-        contents: contents,
-        notify: notify_a,
-        wait: wait_b,
-    };
-    (swapper_a, swapper_b)
-}
-
-/// The error returned when a thread attempts to swap with a thread that has dropped its swapper.
+pub fn swapper<T: ?Sized>() -> (Swapper<T>, Swapper<T>) {
+    SwapperBuilder::new().build()
+}
+
+/// A builder for configuring a pair of swappers.
+///
+/// ```rust
+/// # use swapper::SwapperBuilder;
+/// let (ab, ba) = SwapperBuilder::new().name("gpu-upload").register(true).build::<Vec<u8>>();
+/// assert_eq!(ab.name(), Some("gpu-upload"));
+/// assert_eq!(ab.id(), ba.id());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SwapperBuilder {
+    name: Option<String>,
+    register: bool,
+    strategies: (WaitStrategy, WaitStrategy),
+    watchdog: Option<Watchdog>,
+    history: Option<usize>,
+    observer: Option<Observer>,
+}
+
+impl SwapperBuilder {
+    /// Create a builder for an anonymous, unregistered pair.
+    pub fn new() -> SwapperBuilder {
+        SwapperBuilder::default()
+    }
+
+    /// Label the pair, so it can be identified when debugging.
+    pub fn name<S: Into<String>>(mut self, name: S) -> SwapperBuilder {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Add the pair to the global debug registry, see the `registry` module.
+    pub fn register(mut self, register: bool) -> SwapperBuilder {
+        self.register = register;
+        self
+    }
+
+    /// Set how each half waits for the other, see `WaitStrategy`.
+    pub fn wait_strategies(mut self, a: WaitStrategy, b: WaitStrategy) -> SwapperBuilder {
+        self.strategies = (a, b);
+        self
+    }
+
+    /// Call `callback`, from a shared timer thread, when a swap has been blocked for longer
+    /// than `threshold`, for example to log a stuck pipeline.
+    ///
+    /// The callback is called at most once for each blocked swap, with the pair's id and name,
+    /// while the swap is still blocked. It should not take long, since it delays the callbacks
+    /// of other pairs. If it panics, the panic is caught, so the watchdog keeps running.
+    ///
+    /// ```rust
+    /// # use std::sync::mpsc;
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use swapper::SwapperBuilder;
+    /// let (sender, receiver) = mpsc::channel();
+    /// let (ab, ba) = SwapperBuilder::new()
+    ///     .name("frames")
+    ///     .on_blocked_longer_than(Duration::from_millis(10), move |blocked| {
+    ///         let _ = sender.send(blocked.name.clone());
+    ///     })
+    ///     .build();
+    /// let helper = thread::spawn(move || ab.swap(&mut 1).unwrap());
+    /// assert_eq!(receiver.recv().unwrap().as_deref(), Some("frames"));
+    /// ba.swap(&mut 2).unwrap();
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn on_blocked_longer_than<F>(mut self, threshold: Duration, callback: F) -> SwapperBuilder
+    where
+        F: Fn(&BlockedSwap) + Send + Sync + 'static,
+    {
+        self.watchdog = Some(Watchdog::new(threshold, callback));
+        self
+    }
+
+    /// Keep a history of the pair's most recent swaps, recording which threads swapped,
+    /// for auditing which thread last held which data, see `Swapper::history`.
+    ///
+    /// At most `capacity` swaps are kept, and older ones are forgotten. Tracking takes
+    /// a lock for each swap, so pairs do not track their history unless asked to.
+    pub fn history(mut self, capacity: usize) -> SwapperBuilder {
+        self.history = Some(capacity);
+        self
+    }
+
+    /// Attach an observer to the pair, which is called back at each step of the protocol,
+    /// see `SwapObserver`.
+    pub fn observer(mut self, observer: Arc<dyn SwapObserver>) -> SwapperBuilder {
+        self.observer = Some(Observer(observer));
+        self
+    }
+
+    /// Create a new pair of swappers.
+    pub fn build<T: ?Sized>(self) -> (Swapper<T>, Swapper<T>) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let shared = Arc::new(Shared {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: self.name,
+            slot: CachePadded::new(Slot::new()),
+            swaps: AtomicU64::new(0),
+            watched: AtomicBool::new(false),
+            halves: AtomicUsize::new(2),
+            shutdown: AtomicBool::new(false),
+            watchdog: self.watchdog,
+            history: self.history.map(History::new),
+            observer: self.observer,
+        });
+        if self.register {
+            registry::register(&shared);
+        }
+        let (notify_a, wait_a) = wake::channel();
+        let (notify_b, wait_b) = wake::channel();
+        let swapper_a = Swapper {
+            shared: shared.clone(),
+            notify: notify_b,
+            wait: wait_a,
+            strategy: self.strategies.0,
+            half: 0,
+            marker: PhantomData,
+        };
+        let swapper_b = Swapper {
+            shared,
+            notify: notify_a,
+            wait: wait_b,
+            strategy: self.strategies.1,
+            half: 1,
+            marker: PhantomData,
+        };
+        (swapper_a, swapper_b)
+    }
+}
+
+/// The error returned when a swap cannot be completed.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub struct SwapError(());
+pub enum SwapError {
+    /// The other half of the swap pair has been dropped.
+    Disconnected,
+    /// The other half of the swap pair is blocked on the current thread, so can never swap.
+    WouldDeadlock,
+    /// The two halves of the swap pair offered data of different sizes or types.
+    Mismatch,
+    /// The other half did not swap within this half's spin budget, see `WaitStrategy::Spin`.
+    WouldBlock,
+    /// The two halves of an `AnySwapper` pair offered data of different types.
+    TypeMismatch {
+        /// The name of the type we offered.
+        ours: &'static str,
+        /// The name of the type the other half offered.
+        theirs: &'static str,
+    },
+    /// A sequenced swap was made with a different generation from the other half,
+    /// or from the pair's current generation.
+    Sequence {
+        /// The generation we swapped with.
+        ours: u64,
+        /// The generation the other half swapped with, or the current generation.
+        theirs: u64,
+    },
+    /// The other half aborted a two-phase swap, see `Swapper::prepare`.
+    Aborted,
+    /// The swap was cancelled by a `CancelToken`, see `Swapper::swap_with_cancel`.
+    Cancelled,
+    /// The other half did not swap before the timeout, see `Swapper::swap_timeout`.
+    Timeout {
+        /// What the other half was doing when the offer was retracted.
+        partner: PartnerState,
+    },
+    /// The group was shut down while waiting to swap, or before arriving,
+    /// see `SwapperSet::shutdown` and `BroadcastSwap::shutdown`.
+    Shutdown,
+}
+
+/// What the other half of a pair was doing when a swap timed out.
+///
+/// This helps pick a retry policy: if the other half never arrived, it may be worth
+/// backing off, but if it arrived just as we gave up, retrying will succeed at once.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PartnerState {
+    /// The other half did not try to swap before the timeout.
+    NeverArrived,
+    /// The other half tried to swap just as the offer was being retracted,
+    /// and is now blocked waiting for us to swap.
+    ArrivedDuringRetraction,
+    /// The other half was dropped as the offer was being retracted.
+    Disconnected,
+}
 
 impl From<RecvError> for SwapError {
     fn from(_: RecvError) -> SwapError {
-        SwapError(())
+        SwapError::Disconnected
     }
 }
 
 impl From<SendError<()>> for SwapError {
     fn from(_: SendError<()>) -> SwapError {
-        SwapError(())
+        SwapError::Disconnected
     }
 }
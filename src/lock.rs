@@ -0,0 +1,112 @@
+//! Locks whose guards can trade contents with the other half of a pair.
+
+use std::fmt;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// One half of a pair of locks, each protecting its own value.
+///
+/// Each half can only access its own value, but while holding its guard it can
+/// trade the value with the other half, which must also be holding its guard.
+///
+/// ```rust
+/// # use std::thread;
+/// let (ab, ba) = swapper::swap_lock(String::from("hello"), String::from("world"));
+/// let helper = thread::spawn(move || {
+///     let mut guard = ba.lock();
+///     guard.push('!');
+///     guard.swap_with_partner().unwrap();
+///     assert_eq!(*guard, "hello");
+/// });
+/// let mut guard = ab.lock();
+/// guard.swap_with_partner().unwrap();
+/// assert_eq!(*guard, "world!");
+/// # drop(guard);
+/// # helper.join().unwrap();
+/// ```
+pub struct SwapLock<T> {
+    value: Mutex<T>,
+    swapper: Swapper<T>,
+}
+
+/// A guard giving access to the value of one half of a `SwapLock` pair.
+pub struct SwapGuard<'a, T: 'a> {
+    guard: MutexGuard<'a, T>,
+    swapper: &'a Swapper<T>,
+}
+
+/// Create a new pair of swap locks, with the given initial values.
+pub fn swap_lock<T>(a: T, b: T) -> (SwapLock<T>, SwapLock<T>) {
+    let (swapper_a, swapper_b) = swapper();
+    let lock_a = SwapLock {
+        value: Mutex::new(a),
+        swapper: swapper_a,
+    };
+    let lock_b = SwapLock {
+        value: Mutex::new(b),
+        swapper: swapper_b,
+    };
+    (lock_a, lock_b)
+}
+
+impl<T> SwapLock<T> {
+    /// Lock this half's value, blocking if it is already locked.
+    pub fn lock(&self) -> SwapGuard<'_, T> {
+        SwapGuard {
+            // The value is only accessed through the guard, which does not panic while holding it.
+            guard: self.value.lock().unwrap_or_else(|err| err.into_inner()),
+            swapper: &self.swapper,
+        }
+    }
+
+    /// Consume this half, returning its value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<'a, T: Send> SwapGuard<'a, T> {
+    /// Trade the value with the other half, blocking until it also calls `swap_with_partner`.
+    pub fn swap_with_partner(&mut self) -> Result<(), SwapError> {
+        self.swapper.swap(&mut self.guard)
+    }
+}
+
+impl<'a, T> Deref for SwapGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T> DerefMut for SwapGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> fmt::Debug for SwapLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapLock")
+            .field("swapper", &self.swapper)
+            .finish()
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for SwapGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapGuard")
+            .field("value", &*self.guard)
+            .finish()
+    }
+}
+
+// The swapper is only used while the value is locked.
+unsafe impl<T: Send> Sync for SwapLock<T> {}
@@ -0,0 +1,259 @@
+//! Two-phase swaps, where one half inspects both values before committing.
+
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::ptr::NonNull;
+
+use Offer;
+use SwapError;
+use Swapper;
+use observer;
+
+#[cfg(feature = "deadlock-detection")]
+use deadlock;
+
+/// A two-phase swap which has been prepared, created by `Swapper::prepare`.
+///
+/// Dropping it withdraws from the swap: if the other half has not arrived, the offer is
+/// retracted, and otherwise the swap is aborted, or if the other half is negotiating,
+/// this waits for it to decide.
+pub struct PendingNegotiation<'a, T: 'a> {
+    swapper: &'a Swapper<T>,
+    // Our offer is owned by this struct, but is accessed through a raw pointer,
+    // since the other half may access it through the pointer in the slot.
+    offer: NonNull<Offer<T>>,
+    state: State<T>,
+    marker: PhantomData<&'a mut T>,
+}
+
+enum State<T> {
+    // Our offer is in the slot, or has been taken by the other half, which has not woken us yet.
+    Offered,
+    // We took the other half's offer, so we negotiate with it.
+    Negotiating(NonNull<Offer<T>>),
+    // The other half prepared first, so we joined its swap, and it negotiates.
+    Joined,
+    // The swap has been negotiated, or has failed.
+    Done,
+}
+
+/// A two-phase swap in progress, which must be committed or aborted.
+///
+/// The other half is blocked until this is resolved. Dropping it without committing aborts.
+pub struct Negotiation<'a, T: 'a> {
+    swapper: &'a Swapper<T>,
+    ours: NonNull<T>,
+    theirs: NonNull<Offer<T>>,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<T: Send> Swapper<T> {
+    /// Start a two-phase swap, for protocols which validate the other half's value
+    /// before giving up their own.
+    ///
+    /// This publishes our offer without blocking, and returns a `PendingNegotiation`.
+    /// Calling `wait` on it blocks until the other half arrives, then returns a
+    /// `Negotiation` to the half which negotiates. The negotiation can read both values,
+    /// and must `commit` or `abort`. The other half stays blocked until then, and
+    /// gets `Ok(None)` if the swap was committed, or `SwapError::Aborted` if it was
+    /// aborted, in which case neither value is modified.
+    ///
+    /// If both halves prepare, the half which prepared first negotiates. If the other half
+    /// calls `swap` instead, this half negotiates, whichever arrives first. A half calling
+    /// `swap_cloned`, `swap_init`, `lease`, `borrow`, `inspect`, `split_off` or `deposit`
+    /// cannot negotiate, so gets `SwapError::Mismatch`, and so does this half if it has
+    /// already prepared.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::{SwapError, Swapper};
+    /// fn trade(half: &Swapper<u32>, mut bid: u32) -> u32 {
+    ///     let pending = half.prepare(&mut bid).unwrap();
+    ///     match pending.wait() {
+    ///         // Only trade for a bid of at least 50.
+    ///         Ok(Some(negotiation)) if *negotiation.theirs() < 50 => negotiation.abort().unwrap(),
+    ///         Ok(Some(negotiation)) => negotiation.commit().unwrap(),
+    ///         Ok(None) | Err(SwapError::Aborted) => (),
+    ///         Err(err) => panic!("{:?}", err),
+    ///     };
+    ///     bid
+    /// }
+    /// let (ab, ba) = swapper::swapper();
+    /// let helper = thread::spawn(move || assert_eq!(trade(&ba, 30), 30));
+    /// // Neither bid is at least 50, so whichever half prepared first aborts.
+    /// assert_eq!(trade(&ab, 40), 40);
+    /// # helper.join().unwrap();
+    /// ```
+    pub fn prepare<'a>(&'a self, our_ref: &'a mut T) -> Result<PendingNegotiation<'a, T>, SwapError> {
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::used(self.shared.id, self.half);
+        let offer = Box::new(Offer {
+            negotiator: Some(Cell::new(None)),
+            ..Offer::new(NonNull::from(our_ref))
+        });
+        let mut pending = PendingNegotiation {
+            swapper: self,
+            offer: unsafe { NonNull::new_unchecked(Box::into_raw(offer)) },
+            state: State::Done,
+            marker: PhantomData,
+        };
+        loop {
+            // Has the other half already offered? If so, join its swap or negotiate with it.
+            if let Some(their_offer) = self.shared.slot.take::<Offer<T>>() {
+                let their_ref = unsafe { their_offer.as_ref() };
+                if let Some(ref negotiator) = their_ref.negotiator {
+                    // The other half prepared first, so it negotiates.
+                    negotiator.set(Some(pending.offer));
+                    self.complete(their_ref)?;
+                    pending.state = State::Joined;
+                } else if their_ref.is_plain() {
+                    pending.state = State::Negotiating(their_offer);
+                } else {
+                    self.shared.slot.offer(their_offer);
+                    return Err(SwapError::Mismatch);
+                }
+                return Ok(pending);
+            }
+            // Is the other half not ready yet? If so, leave our offer for it.
+            if self.shared.slot.offer(pending.offer) {
+                observer::observe(&self.shared.observer, |observer| observer.on_offer(self.shared.id, self.half));
+                pending.state = State::Offered;
+                return Ok(pending);
+            }
+        }
+    }
+}
+
+impl<'a, T> PendingNegotiation<'a, T> {
+    /// Wait for the other half, returning a negotiation if this half negotiates.
+    ///
+    /// Otherwise the other half negotiates, and this waits for it to decide, returning
+    /// `Ok(None)` if it committed, or `SwapError::Aborted` if it aborted.
+    pub fn wait(mut self) -> Result<Option<Negotiation<'a, T>>, SwapError> {
+        let ours = self.ours();
+        Ok(self.negotiate()?.map(|theirs| Negotiation {
+            swapper: self.swapper,
+            ours,
+            theirs,
+            marker: PhantomData,
+        }))
+    }
+
+    fn ours(&self) -> NonNull<T> {
+        unsafe { self.offer.as_ref().data }
+    }
+
+    // Wait until the swap can be negotiated, returning the offer to negotiate with if this
+    // half negotiates. Afterwards, the swap has either been handed to the caller, or has
+    // been decided.
+    fn negotiate(&mut self) -> Result<Option<NonNull<Offer<T>>>, SwapError> {
+        let swapper = self.swapper;
+        let our_offer = unsafe { self.offer.as_ref() };
+        match mem::replace(&mut self.state, State::Done) {
+            State::Offered => {
+                if swapper.released_before(self.offer) {
+                    return Err(SwapError::Disconnected);
+                }
+                if let Err(err) = swapper.wait.wait() {
+                    swapper.shared.slot.retract(self.offer);
+                    return Err(err);
+                }
+                // The offer is only completed without being joined if the swap failed.
+                match our_offer.negotiator.as_ref().and_then(Cell::get) {
+                    Some(theirs) => Ok(Some(theirs)),
+                    None => our_offer.outcome.get().and(Err(SwapError::Mismatch)),
+                }
+            }
+            State::Negotiating(theirs) => Ok(Some(theirs)),
+            State::Joined => {
+                swapper.wait.wait()?;
+                our_offer.outcome.get().map(|()| None)
+            }
+            State::Done => Err(SwapError::Mismatch),
+        }
+    }
+}
+
+impl<'a, T> Drop for PendingNegotiation<'a, T> {
+    fn drop(&mut self) {
+        if let State::Offered = self.state {
+            if self.swapper.shared.slot.retract(self.offer) {
+                self.state = State::Done;
+            }
+        }
+        if !matches!(self.state, State::Done) {
+            if let Ok(Some(theirs)) = self.negotiate() {
+                // Dropping the negotiation aborts it.
+                drop(Negotiation {
+                    swapper: self.swapper,
+                    ours: self.ours(),
+                    theirs,
+                    marker: PhantomData,
+                });
+            }
+        }
+        // Our offer is no longer in the slot, and the other half is no longer using it.
+        drop(unsafe { Box::from_raw(self.offer.as_ptr()) });
+    }
+}
+
+impl<'a, T> fmt::Debug for PendingNegotiation<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingNegotiation")
+            .field("swapper", self.swapper)
+            .finish()
+    }
+}
+
+impl<'a, T> Negotiation<'a, T> {
+    /// Our value.
+    pub fn ours(&self) -> &T {
+        unsafe { self.ours.as_ref() }
+    }
+
+    /// The other half's value, which it cannot access until this is resolved.
+    pub fn theirs(&self) -> &T {
+        unsafe { self.theirs.as_ref().data.as_ref() }
+    }
+
+    /// Swap the values, and unblock the other half.
+    pub fn commit(self) -> Result<(), SwapError> {
+        let their_offer = unsafe { self.theirs.as_ref() };
+        unsafe { ptr::swap_nonoverlapping(self.ours.as_ptr(), their_offer.data.as_ptr(), 1) };
+        self.swapper.shared.swapped(self.swapper.half, their_offer.thread);
+        self.resolve(Ok(()))
+    }
+
+    /// Leave both values untouched, and unblock the other half with `SwapError::Aborted`.
+    pub fn abort(self) -> Result<(), SwapError> {
+        self.resolve(Err(SwapError::Aborted))
+    }
+
+    fn resolve(self, outcome: Result<(), SwapError>) -> Result<(), SwapError> {
+        let this = ManuallyDrop::new(self);
+        let their_offer = unsafe { this.theirs.as_ref() };
+        their_offer.outcome.set(outcome);
+        this.swapper.complete(their_offer)
+    }
+}
+
+impl<'a, T> Drop for Negotiation<'a, T> {
+    fn drop(&mut self) {
+        let their_offer = unsafe { self.theirs.as_ref() };
+        their_offer.outcome.set(Err(SwapError::Aborted));
+        let _ = self.swapper.complete(their_offer);
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for Negotiation<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Negotiation")
+            .field("ours", self.ours())
+            .field("theirs", self.theirs())
+            .finish()
+    }
+}
@@ -0,0 +1,244 @@
+//! Swapping between neighbouring tasks in a row, for example in a parallel stencil.
+
+use std::fmt;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// One task's swappers with its left and right neighbours in a row of tasks.
+///
+/// A swapper has no lifetime of its own, and borrows the data it swaps only for the
+/// duration of each swap, so swapper pairs can be moved into scoped tasks which swap
+/// borrowed data, such as `std::thread::scope` or `rayon::scope`. For example, a
+/// one-dimensional stencil where each task exchanges its edge cells with its neighbours:
+///
+/// ```rust
+/// # use std::thread;
+/// let mut cells = [0, 1, 2, 3, 4, 5, 6, 7];
+/// thread::scope(|scope| {
+///     for (chunk, neighbours) in cells.chunks_mut(2).zip(swapper::neighbours(4)) {
+///         scope.spawn(move || {
+///             // Send our edges to our neighbours, and get back theirs.
+///             let (mut left, mut right) = (chunk[0], chunk[1]);
+///             neighbours.exchange(&mut left, &mut right).unwrap();
+///             chunk[0] += left;
+///             chunk[1] += right;
+///         });
+///     }
+/// });
+/// assert_eq!(cells, [0, 3, 3, 7, 7, 11, 11, 14]);
+/// ```
+///
+/// Each swap blocks until the neighbour swaps, so every task in the row must be running
+/// at the same time. Under rayon, this means the thread pool must have at least as many
+/// threads as tasks, otherwise a blocked task can occupy the thread its neighbour needs:
+///
+/// ```rust
+/// # extern crate rayon;
+/// let mut cells = [0, 1, 2, 3, 4, 5, 6, 7];
+/// let tasks = 4;
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(tasks).build().unwrap();
+/// pool.scope(|scope| {
+///     for (chunk, neighbours) in cells.chunks_mut(2).zip(swapper::neighbours(tasks)) {
+///         scope.spawn(move |_| {
+///             let (mut left, mut right) = (chunk[0], chunk[1]);
+///             neighbours.exchange(&mut left, &mut right).unwrap();
+///             chunk[0] += left;
+///             chunk[1] += right;
+///         });
+///     }
+/// });
+/// assert_eq!(cells, [0, 3, 3, 7, 7, 11, 11, 14]);
+/// ```
+pub struct Neighbours<T> {
+    index: usize,
+    left: Option<Swapper<T>>,
+    right: Option<Swapper<T>>,
+}
+
+/// One task's halos in a row of tasks, each of which owns a chunk of a larger array.
+///
+/// In a stencil computation, each task needs the cells at the edges of its neighbours'
+/// chunks, called its halos. Each iteration, `exchange_halos` sends the edges of our chunk
+/// to our neighbours, and receives their edges in return. The halos are kept in buffers
+/// which are swapped with the neighbours, so after the first iteration nothing is allocated.
+///
+/// ```rust
+/// # use std::thread;
+/// let mut cells = vec![0, 1, 2, 3, 4, 5, 6, 7, 8];
+/// thread::scope(|scope| {
+///     for (chunk, mut halos) in cells.chunks_mut(3).zip(swapper::halo_exchange(3, 1)) {
+///         scope.spawn(move || {
+///             for _ in 0..2 {
+///                 halos.exchange_halos(chunk).unwrap();
+///                 // Replace each cell by the sum of its neighbours, treating missing cells as 0.
+///                 let left = halos.left_halo().first().copied().unwrap_or(0);
+///                 let right = halos.right_halo().first().copied().unwrap_or(0);
+///                 let mut padded = vec![left];
+///                 padded.extend_from_slice(chunk);
+///                 padded.push(right);
+///                 for (cell, window) in chunk.iter_mut().zip(padded.windows(3)) {
+///                     *cell = window[0] + window[2];
+///                 }
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(cells, [2, 5, 8, 12, 16, 20, 24, 19, 14]);
+/// ```
+pub struct HaloExchange<T> {
+    neighbours: Neighbours<Vec<T>>,
+    width: usize,
+    left: Vec<T>,
+    right: Vec<T>,
+}
+
+/// Create the swappers for a row of tasks, in order from left to right.
+///
+/// Each task can swap with its neighbours. The leftmost task has no left neighbour,
+/// and the rightmost has no right neighbour.
+pub fn neighbours<T>(tasks: usize) -> Vec<Neighbours<T>> {
+    let mut result = Vec::with_capacity(tasks);
+    let mut left = None;
+    for index in 0..tasks {
+        let (ours, theirs) = if index + 1 < tasks {
+            let (ours, theirs) = swapper();
+            (Some(ours), Some(theirs))
+        } else {
+            (None, None)
+        };
+        result.push(Neighbours {
+            index,
+            left: left.take(),
+            right: ours,
+        });
+        left = theirs;
+    }
+    result
+}
+
+/// Create the halo exchanges for a row of tasks, in order from left to right,
+/// where each halo is `width` cells wide.
+pub fn halo_exchange<T>(tasks: usize, width: usize) -> Vec<HaloExchange<T>> {
+    neighbours(tasks)
+        .into_iter()
+        .map(|neighbours| HaloExchange {
+            neighbours,
+            width,
+            left: Vec::with_capacity(width),
+            right: Vec::with_capacity(width),
+        })
+        .collect()
+}
+
+impl<T: Send> Neighbours<T> {
+    /// Swap with the left neighbour. If there is no left neighbour, this does nothing.
+    pub fn swap_left(&self, data: &mut T) -> Result<(), SwapError> {
+        match self.left {
+            Some(ref left) => left.swap(data),
+            None => Ok(()),
+        }
+    }
+
+    /// Swap with the right neighbour. If there is no right neighbour, this does nothing.
+    pub fn swap_right(&self, data: &mut T) -> Result<(), SwapError> {
+        match self.right {
+            Some(ref right) => right.swap(data),
+            None => Ok(()),
+        }
+    }
+
+    /// Swap with both neighbours.
+    ///
+    /// Tasks at even positions swap right then left, and tasks at odd positions
+    /// swap left then right, so that a row of tasks all calling `exchange` does not deadlock.
+    pub fn exchange(&self, left: &mut T, right: &mut T) -> Result<(), SwapError> {
+        if self.index.is_multiple_of(2) {
+            self.swap_right(right)?;
+            self.swap_left(left)
+        } else {
+            self.swap_left(left)?;
+            self.swap_right(right)
+        }
+    }
+}
+
+impl<T> Neighbours<T> {
+    /// The position of this task in the row.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Does this task have a left neighbour?
+    pub fn has_left(&self) -> bool {
+        self.left.is_some()
+    }
+
+    /// Does this task have a right neighbour?
+    pub fn has_right(&self) -> bool {
+        self.right.is_some()
+    }
+}
+
+impl<T: Clone + Send> HaloExchange<T> {
+    /// Send the edges of our chunk to our neighbours, and receive the edges of theirs.
+    ///
+    /// Afterwards, `left_halo` is the last `width` cells of the left neighbour's chunk,
+    /// and `right_halo` is the first `width` cells of the right neighbour's chunk.
+    /// If the chunks are narrower than `width`, so are the halos. A task without
+    /// a left or right neighbour has an empty halo on that side.
+    pub fn exchange_halos(&mut self, chunk: &[T]) -> Result<(), SwapError> {
+        let width = self.width.min(chunk.len());
+        self.left.clear();
+        self.right.clear();
+        if self.neighbours.has_left() {
+            self.left.extend_from_slice(&chunk[..width]);
+        }
+        if self.neighbours.has_right() {
+            self.right.extend_from_slice(&chunk[chunk.len() - width..]);
+        }
+        self.neighbours.exchange(&mut self.left, &mut self.right)
+    }
+}
+
+impl<T> HaloExchange<T> {
+    /// The edge of the left neighbour's chunk, from the last exchange.
+    pub fn left_halo(&self) -> &[T] {
+        &self.left
+    }
+
+    /// The edge of the right neighbour's chunk, from the last exchange.
+    pub fn right_halo(&self) -> &[T] {
+        &self.right
+    }
+
+    /// The width of the halos.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The position of this task in the row.
+    pub fn index(&self) -> usize {
+        self.neighbours.index()
+    }
+}
+
+impl<T> fmt::Debug for HaloExchange<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HaloExchange")
+            .field("index", &self.index())
+            .field("width", &self.width)
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for Neighbours<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Neighbours")
+            .field("index", &self.index)
+            .field("left", &self.left)
+            .field("right", &self.right)
+            .finish()
+    }
+}
@@ -0,0 +1,97 @@
+//! Observing the steps of the swap protocol, for metrics and profiling.
+
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Callbacks for each step of the swap protocol, for integrating with metrics or profiling.
+///
+/// An observer can be attached to a pair with `SwapperBuilder::observer`, or to every pair
+/// with `set_global_observer`. Each callback is given the id of the pair, and which half of
+/// the pair, 0 or 1, took the step. The callbacks are made on the thread taking the step,
+/// in the middle of the swap, so they should be quick, and must not swap using the pair.
+/// If a callback panics, the panic is caught, so the swap carries on.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use std::thread;
+/// # use swapper::{SwapObserver, SwapperBuilder};
+/// #[derive(Default)]
+/// struct Counts {
+///     offers: AtomicUsize,
+///     swaps: AtomicUsize,
+/// }
+/// impl SwapObserver for Counts {
+///     fn on_offer(&self, _pair: u64, _half: usize) {
+///         self.offers.fetch_add(1, Ordering::Relaxed);
+///     }
+///     fn on_swap(&self, _pair: u64, _half: usize, _generation: u64) {
+///         self.swaps.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+/// let counts = Arc::new(Counts::default());
+/// let (ab, ba) = SwapperBuilder::new().observer(counts.clone()).build();
+/// let helper = thread::spawn(move || ba.swap(&mut 1).unwrap());
+/// ab.swap(&mut 2).unwrap();
+/// # helper.join().unwrap();
+/// // One half offered, and the other completed the swap.
+/// assert_eq!(counts.offers.load(Ordering::Relaxed), 1);
+/// assert_eq!(counts.swaps.load(Ordering::Relaxed), 1);
+/// ```
+pub trait SwapObserver: Send + Sync {
+    /// A half found the other half not ready, so left an offer of its data for it to take.
+    fn on_offer(&self, _pair: u64, _half: usize) {}
+
+    /// A half which offered its data is about to block, after spinning if its wait strategy spins.
+    fn on_park(&self, _pair: u64, _half: usize) {}
+
+    /// A blocked half has stopped waiting, because it was woken or gave up, after being
+    /// parked for the given time.
+    fn on_wake(&self, _pair: u64, _half: usize, _parked: Duration) {}
+
+    /// A half completed a swap, with the given generation, before waking the other half.
+    fn on_swap(&self, _pair: u64, _half: usize, _generation: u64) {}
+}
+
+/// An observer attached to a pair.
+#[derive(Clone)]
+pub(crate) struct Observer(pub(crate) Arc<dyn SwapObserver>);
+
+static OBSERVING: AtomicBool = AtomicBool::new(false);
+static GLOBAL: RwLock<Option<Arc<dyn SwapObserver>>> = RwLock::new(None);
+
+/// Attach an observer to every pair, or remove it with `None`.
+///
+/// This is in addition to any observer attached to a pair.
+pub fn set_global_observer(observer: Option<Arc<dyn SwapObserver>>) {
+    let mut global = GLOBAL.write().unwrap_or_else(|err| err.into_inner());
+    OBSERVING.store(observer.is_some(), Ordering::Release);
+    *global = observer;
+}
+
+/// Call back the pair's observer, if any, then the global observer, if any.
+pub(crate) fn observe<F: Fn(&dyn SwapObserver)>(observer: &Option<Observer>, f: F) {
+    // The callbacks are made in the middle of the swap, where unwinding could leave the
+    // other half blocked, or report a swap which happened as failed. The panic has already
+    // been reported by the panic hook.
+    if let Some(Observer(ref observer)) = *observer {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| f(&**observer)));
+    }
+    if OBSERVING.load(Ordering::Acquire) {
+        if let Some(ref global) = *GLOBAL.read().unwrap_or_else(|err| err.into_inner()) {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| f(&**global)));
+        }
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Observer").finish()
+    }
+}
@@ -0,0 +1,79 @@
+//! Swappers which swap once, and swappers which swap repeatedly.
+
+use std::fmt;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// One half of a swap pair which can only swap once.
+///
+/// Swapping consumes the half, so swapping twice is a compile-time error:
+///
+/// ```rust,compile_fail
+/// let (ab, ba) = swapper::oneshot_swapper::<u8>();
+/// ab.swap(&mut 1).unwrap();
+/// ab.swap(&mut 2).unwrap();
+/// ```
+///
+/// This suits handing a value over in a single exchange, for example replying to a request,
+/// where the half is sent to the other thread and swapped there.
+///
+/// ```rust
+/// # use std::thread;
+/// let (ab, ba) = swapper::oneshot_swapper();
+/// let helper = thread::spawn(move || {
+///     let mut reply = String::from("pong");
+///     ba.swap(&mut reply).unwrap();
+///     assert_eq!(reply, "ping");
+/// });
+/// let mut request = String::from("ping");
+/// ab.swap(&mut request).unwrap();
+/// assert_eq!(request, "pong");
+/// # helper.join().unwrap();
+/// ```
+pub struct OneShotSwapper<T>(Swapper<T>);
+
+/// One half of a swap pair which can swap repeatedly.
+///
+/// This is the same type as `Swapper`, named to make the intent clear. Each half may call
+/// `swap` any number of times, and each call rendezvous with exactly one call by the other
+/// half, so the `n`th swap of one half exchanges data with the `n`th swap of the other.
+/// Each completed swap starts a new generation of the pair, see `Swapper::current_gen`.
+/// A half is not `Sync`, so it is only ever in one swap at a time.
+/// Once either half is dropped, every later swap by the other half, and any swap it is
+/// blocked in, returns `SwapError::Disconnected`.
+pub type ReusableSwapper<T> = Swapper<T>;
+
+/// Create a new pair of swappers which can each swap once.
+pub fn oneshot_swapper<T>() -> (OneShotSwapper<T>, OneShotSwapper<T>) {
+    let (ab, ba) = swapper();
+    (OneShotSwapper(ab), OneShotSwapper(ba))
+}
+
+/// Create a new pair of swappers which can swap repeatedly.
+pub fn reusable_swapper<T>() -> (ReusableSwapper<T>, ReusableSwapper<T>) {
+    swapper()
+}
+
+impl<T: Send> OneShotSwapper<T> {
+    /// Swap data, consuming this half.
+    ///
+    /// This blocks until the other half swaps, as `Swapper::swap` does.
+    pub fn swap(self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.0.swap(our_ref)
+    }
+}
+
+impl<T> OneShotSwapper<T> {
+    /// The unique id of this swap pair, shared by both halves.
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+}
+
+impl<T> fmt::Debug for OneShotSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OneShotSwapper").field(&self.0).finish()
+    }
+}
@@ -0,0 +1,44 @@
+//! Padding hot atomics to a cache line, so they do not falsely share it with other data.
+//!
+//! The offer slot is written by both halves of a pair on every swap. If it shares a cache
+//! line with fields which are only read, or with the shared state of a neighbouring pair,
+//! as happens when many pairs are allocated together, for example by a pool, then every
+//! swap invalidates that line for the threads reading it. With the `cache-padded` feature,
+//! which is enabled by default, such atomics are aligned and padded to a cache line each.
+//! Without it, they are not padded, which saves memory on small targets.
+
+use std::ops::Deref;
+
+/// A value which, with the `cache-padded` feature, is alone on its cache line.
+///
+/// Some processors prefetch cache lines in adjacent pairs, so on those the padding covers
+/// two 64-byte lines, as in `crossbeam_utils::CachePadded`.
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64")
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64"))
+    ),
+    repr(align(64))
+)]
+pub(crate) struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub(crate) fn new(value: T) -> CachePadded<T> {
+        CachePadded(value)
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
@@ -0,0 +1,290 @@
+//! Swapping with priority inheritance, using priority-inheritance futexes.
+//!
+//! Enabled by the `pi` feature, on Linux. When a high-priority thread is blocked waiting
+//! for a low-priority thread to swap, the low-priority thread may be preempted by threads
+//! of medium priority, so the high-priority thread waits for all of them too. To avoid
+//! this priority inversion, each half of a `PiSwapper` pair is bound to a thread, which
+//! holds a priority-inheritance futex while it is not waiting to swap. A thread waiting
+//! to swap blocks on the other half's futex, so the kernel donates its priority to the
+//! thread which must complete the swap.
+//!
+//! ```rust
+//! # use std::thread;
+//! let (ab, ba) = swapper::pi::pi_swapper();
+//! let helper = thread::spawn(move || {
+//!     // Bind the half to this thread, so that waiting threads can donate their priority to it.
+//!     let ba = ba.bind();
+//!     let mut hello = String::from("hello");
+//!     ba.swap(&mut hello).unwrap();
+//!     assert_eq!(hello, "world");
+//! });
+//! let ab = ab.bind();
+//! let mut world = String::from("world");
+//! ab.swap(&mut world).unwrap();
+//! assert_eq!(world, "hello");
+//! # helper.join().unwrap();
+//! ```
+//!
+//! A half is bound to a thread by `bind`, and stays bound until the returned `PiBinding`
+//! is dropped. The futex can only be released by the thread holding it, so the binding
+//! cannot be sent to another thread, and the half cannot be moved or dropped while bound.
+//! A half which is not bound is bound for the duration of each swap. If the other half is
+//! not bound, a waiting thread cannot donate its priority, so it polls.
+
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use libc;
+
+use SwapError;
+use slot::Slot;
+
+struct Shared {
+    slot: Slot,
+    // The priority-inheritance futex of each half, held by the thread bound to it.
+    locks: [AtomicU32; 2],
+    // Set by each half when it has been handed the other half's futex after a swap.
+    acked: [AtomicBool; 2],
+    closed: [AtomicBool; 2],
+}
+
+/// An offer to swap, which lives on the stack of the offering thread while it is blocked.
+struct Offer<T> {
+    data: NonNull<T>,
+    // Set by the thread that takes the offer, once it has swapped the data.
+    done: AtomicBool,
+}
+
+/// One half of a swap pair, which waits with priority inheritance.
+pub struct PiSwapper<T> {
+    shared: Arc<Shared>,
+    ours: usize,
+    theirs: usize,
+    marker: PhantomData<T>,
+}
+
+/// A half of a swap pair bound to the current thread, which unbinds it when dropped.
+///
+/// A bound half cannot be sent to another thread, so this is a compile-time error:
+///
+/// ```rust,compile_fail
+/// # use std::thread;
+/// let (ab, _ba) = swapper::pi::pi_swapper::<u8>();
+/// let binding = ab.bind();
+/// thread::scope(|scope| {
+///     scope.spawn(move || drop(binding));
+/// });
+/// ```
+pub struct PiBinding<'a, T> {
+    swapper: &'a PiSwapper<T>,
+    // Whether this binding bound the half, rather than it already being bound to this thread.
+    bound: bool,
+    // The futex must be released by the thread which holds it, so the binding is not `Send`.
+    marker: PhantomData<*const ()>,
+}
+
+/// Create a new pair of priority-inheritance swappers, which are not yet bound to threads.
+pub fn pi_swapper<T>() -> (PiSwapper<T>, PiSwapper<T>) {
+    let shared = Arc::new(Shared {
+        slot: Slot::new(),
+        locks: [AtomicU32::new(0), AtomicU32::new(0)],
+        acked: [AtomicBool::new(false), AtomicBool::new(false)],
+        closed: [AtomicBool::new(false), AtomicBool::new(false)],
+    });
+    let swapper_a = PiSwapper {
+        shared: shared.clone(),
+        ours: 0,
+        theirs: 1,
+        marker: PhantomData,
+    };
+    let swapper_b = PiSwapper {
+        shared,
+        ours: 1,
+        theirs: 0,
+        marker: PhantomData,
+    };
+    (swapper_a, swapper_b)
+}
+
+impl<T: Send> PiSwapper<T> {
+    /// Swap data.
+    ///
+    /// If the other half of the swap pair is blocked waiting to swap, then it swaps ownership
+    /// of the data, then unblocks the other thread. Otherwise it blocks waiting to swap,
+    /// donating its priority to the thread bound to the other half.
+    ///
+    /// The half is bound to the current thread for the duration of the swap.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.bind().swap(our_ref)
+    }
+
+    // Swap data, while this half is bound to the current thread.
+    fn swap_bound(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let shared = &*self.shared;
+        // Once our data may have been offered, it is only accessed through this pointer.
+        let our_offer = Offer {
+            data: NonNull::from(our_ref),
+            done: AtomicBool::new(false),
+        };
+        loop {
+            if shared.closed[self.theirs].load(Ordering::Acquire) {
+                return Err(SwapError::Disconnected);
+            }
+            // Is the other thead blocked waiting to swap? If so, swap and unblock it.
+            if let Some(their_offer) = shared.slot.take::<Offer<T>>() {
+                let their_offer = unsafe { their_offer.as_ref() };
+                unsafe { ptr::swap_nonoverlapping(our_offer.data.as_ptr(), their_offer.data.as_ptr(), 1) };
+                shared.acked[self.theirs].store(false, Ordering::Release);
+                their_offer.done.store(true, Ordering::Release);
+                // The other thread is blocked on our futex, so hand it over,
+                // and take it back once the other thread has released it.
+                unlock_pi(&shared.locks[self.ours]);
+                while !shared.acked[self.theirs].load(Ordering::Acquire) {
+                    if shared.closed[self.theirs].load(Ordering::Acquire) {
+                        break;
+                    }
+                    thread::yield_now();
+                }
+                lock_pi(&shared.locks[self.ours]);
+                return Ok(());
+            }
+            // Is the other thead not ready for a swap yet? If so, block waiting to swap.
+            if shared.slot.offer(NonNull::from(&our_offer)) {
+                return self.wait(&our_offer);
+            }
+        }
+    }
+
+    // Wait for our offer to be taken, by blocking on the other half's futex.
+    fn wait(&self, our_offer: &Offer<T>) -> Result<(), SwapError> {
+        let shared = &*self.shared;
+        loop {
+            lock_pi(&shared.locks[self.theirs]);
+            shared.acked[self.ours].store(true, Ordering::Release);
+            unlock_pi(&shared.locks[self.theirs]);
+            if our_offer.done.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            // The other half released its futex without taking our offer, so either it
+            // has been dropped, or it is not bound to a thread.
+            if shared.closed[self.theirs].load(Ordering::Acquire) && shared.slot.retract(NonNull::from(our_offer)) {
+                return Err(SwapError::Disconnected);
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+impl<T> PiSwapper<T> {
+    /// Bind this half to the current thread, until the binding is dropped.
+    ///
+    /// If it is bound to another thread, this blocks until that thread unbinds it.
+    /// If it is already bound to this thread, it stays bound until the outer binding is dropped.
+    pub fn bind(&self) -> PiBinding<'_, T> {
+        let bound = !self.is_bound();
+        if bound {
+            lock_pi(&self.shared.locks[self.ours]);
+        }
+        PiBinding {
+            swapper: self,
+            bound,
+            marker: PhantomData,
+        }
+    }
+
+    /// Is this half bound to the current thread?
+    pub fn is_bound(&self) -> bool {
+        self.shared.locks[self.ours].load(Ordering::Acquire) & libc::FUTEX_TID_MASK == gettid()
+    }
+}
+
+impl<'a, T: Send> PiBinding<'a, T> {
+    /// Swap data, as `PiSwapper::swap`.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.swapper.swap_bound(our_ref)
+    }
+}
+
+impl<'a, T> Drop for PiBinding<'a, T> {
+    fn drop(&mut self) {
+        if self.bound {
+            unlock_pi(&self.swapper.shared.locks[self.swapper.ours]);
+        }
+    }
+}
+
+impl<T> Drop for PiSwapper<T> {
+    fn drop(&mut self) {
+        // A half cannot be dropped while bound, so the other half is not waiting on our futex.
+        self.shared.closed[self.ours].store(true, Ordering::Release);
+    }
+}
+
+impl<T> fmt::Debug for PiSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PiSwapper")
+            .field("side", &self.ours)
+            .field("bound", &self.is_bound())
+            .finish()
+    }
+}
+
+impl<'a, T> fmt::Debug for PiBinding<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PiBinding")
+            .field("swapper", &self.swapper)
+            .finish()
+    }
+}
+
+// Be explicit about implementing Send. A half is only bound while borrowed by a `PiBinding`,
+// which is not `Send`, so it can only be sent while unbound.
+unsafe impl<T: Send> Send for PiSwapper<T> {}
+
+fn gettid() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+// Acquire a priority-inheritance futex, donating our priority to its holder while blocked.
+fn lock_pi(word: &AtomicU32) {
+    if word.compare_exchange(0, gettid(), Ordering::Acquire, Ordering::Relaxed).is_ok() {
+        return;
+    }
+    loop {
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                word.as_ptr(),
+                libc::FUTEX_LOCK_PI | libc::FUTEX_PRIVATE_FLAG,
+                0,
+                ptr::null::<libc::timespec>(),
+            )
+        };
+        if result == 0 {
+            return;
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+            _ => panic!("Failed to lock futex: {}", err),
+        }
+    }
+}
+
+// Release a priority-inheritance futex, handing it to the highest-priority waiter, if any.
+fn unlock_pi(word: &AtomicU32) {
+    if word.compare_exchange(gettid(), 0, Ordering::Release, Ordering::Relaxed).is_ok() {
+        return;
+    }
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_UNLOCK_PI | libc::FUTEX_PRIVATE_FLAG);
+    }
+}
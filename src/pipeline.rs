@@ -0,0 +1,178 @@
+//! Pipelines of worker threads, which pass buffers along by swapping with their neighbours.
+
+use std::fmt;
+use std::mem;
+use std::panic;
+use std::thread;
+use std::thread::JoinHandle;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// A pipeline of stages, each running on its own thread, which pass buffers along.
+///
+/// Each stage owns an input buffer and an output buffer. It swaps its input buffer with its
+/// predecessor's output buffer, runs its body to fill its output buffer from its input buffer,
+/// then swaps its output buffer with its successor's input buffer. So buffers circulate
+/// through the pipeline, rather than being allocated for each item. The output buffer a body
+/// is given is whichever buffer was swapped back in, so the body should overwrite it.
+///
+/// Buffers are pushed into the first stage, and pulled from the last stage.
+///
+/// ```rust
+/// # use swapper::Pipeline;
+/// let pipeline = Pipeline::builder()
+///     .stage(|input: &mut Vec<u32>, output: &mut Vec<u32>| {
+///         output.clear();
+///         output.extend(input.iter().map(|x| x * 2));
+///         Ok::<(), String>(())
+///     })
+///     .stage(|input: &mut Vec<u32>, output: &mut Vec<u32>| {
+///         output.clear();
+///         output.extend(input.iter().map(|x| x + 1));
+///         Ok(())
+///     })
+///     .build();
+/// let mut buffer = vec![1, 2, 3];
+/// pipeline.push(&mut buffer).unwrap();
+/// pipeline.pull(&mut buffer).unwrap();
+/// assert_eq!(buffer, [3, 5, 7]);
+/// pipeline.finish().unwrap();
+/// ```
+///
+/// Pushing blocks until the first stage is ready for another buffer, and pulling blocks
+/// until the last stage has filled one, so a single thread which alternates pushing and
+/// pulling only keeps one buffer in flight. Pushing one buffer per stage before starting
+/// to pull keeps every stage busy.
+///
+/// When a stage's body returns an error, the stage stops, and so do the other stages, since
+/// they can no longer swap with it. Pushing and pulling then return `SwapError::Disconnected`,
+/// and `finish` returns the error. Dropping the pipeline stops the stages without waiting.
+pub struct Pipeline<T, E> {
+    input: Swapper<T>,
+    output: Swapper<T>,
+    workers: Vec<JoinHandle<Result<(), E>>>,
+}
+
+/// A builder for a `Pipeline`, which adds stages in order.
+pub struct PipelineBuilder<T, E> {
+    stages: Vec<Stage<T, E>>,
+}
+
+// The body of a stage, which fills its output buffer from its input buffer.
+type Stage<T, E> = Box<dyn FnMut(&mut T, &mut T) -> Result<(), E> + Send>;
+
+/// The error returned by the first stage of a pipeline which failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PipelineError<E> {
+    /// The index of the failed stage, starting from 0.
+    pub stage: usize,
+    /// The error returned by its body.
+    pub error: E,
+}
+
+impl<T, E> Pipeline<T, E> {
+    /// Create a builder for a pipeline, with no stages.
+    pub fn builder() -> PipelineBuilder<T, E> {
+        PipelineBuilder { stages: Vec::new() }
+    }
+
+    /// The number of stages.
+    pub fn stages(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl<T: Send, E> Pipeline<T, E> {
+    /// Swap a full buffer into the first stage, getting back an empty one.
+    pub fn push(&self, buffer: &mut T) -> Result<(), SwapError> {
+        self.input.swap(buffer)
+    }
+
+    /// Swap an empty buffer into the last stage, getting back a full one.
+    pub fn pull(&self, buffer: &mut T) -> Result<(), SwapError> {
+        self.output.swap(buffer)
+    }
+
+    /// Stop the pipeline, and wait for its stages to finish.
+    ///
+    /// Any buffers still in the pipeline are dropped. Returns the error of the first stage
+    /// which failed, if any. If a stage panicked, the panic is propagated.
+    pub fn finish(self) -> Result<(), PipelineError<E>> {
+        drop(self.input);
+        drop(self.output);
+        let mut result = Ok(());
+        for (stage, worker) in self.workers.into_iter().enumerate() {
+            match worker.join() {
+                Ok(Ok(())) => (),
+                Ok(Err(error)) => {
+                    if result.is_ok() {
+                        result = Err(PipelineError { stage, error });
+                    }
+                }
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        result
+    }
+}
+
+impl<T, E> PipelineBuilder<T, E> {
+    /// Add a stage, after the existing ones.
+    ///
+    /// The body is called with the stage's input buffer, which the previous stage filled,
+    /// and its output buffer, which it should fill for the next stage.
+    pub fn stage<F>(mut self, body: F) -> PipelineBuilder<T, E>
+    where
+        F: FnMut(&mut T, &mut T) -> Result<(), E> + Send + 'static,
+    {
+        self.stages.push(Box::new(body));
+        self
+    }
+}
+
+impl<T: Default + Send + 'static, E: Send + 'static> PipelineBuilder<T, E> {
+    /// Start a thread for each stage, with default buffers.
+    pub fn build(self) -> Pipeline<T, E> {
+        let (input, mut left) = swapper();
+        let mut workers = Vec::with_capacity(self.stages.len());
+        for mut body in self.stages {
+            let (right, next) = swapper();
+            let left = mem::replace(&mut left, next);
+            workers.push(thread::spawn(move || {
+                let mut input = T::default();
+                let mut output = T::default();
+                // A swap only fails once a neighbour has stopped, in which case so do we.
+                while left.swap(&mut input).is_ok() {
+                    body(&mut input, &mut output)?;
+                    if right.swap(&mut output).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            }));
+        }
+        Pipeline {
+            input,
+            output: left,
+            workers,
+        }
+    }
+}
+
+impl<T, E> fmt::Debug for Pipeline<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.stages())
+            .finish()
+    }
+}
+
+impl<T, E> fmt::Debug for PipelineBuilder<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipelineBuilder")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
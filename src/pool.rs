@@ -0,0 +1,178 @@
+//! Pools of swap pairs, which are recycled rather than allocated for each use.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::Ordering;
+
+use Swapper;
+use epoch;
+use release;
+use swapper;
+
+#[cfg(feature = "deadlock-detection")]
+use deadlock;
+
+/// A pool of swap pairs, for programs which use many short-lived pairs.
+///
+/// Creating a pair allocates its shared state, and the channels each half waits on.
+/// A pool allocates pairs up front, and hands them out with `acquire`. Each half is
+/// returned to the pool when it is dropped, and once both halves have been returned,
+/// the pair is reset, and can be acquired again.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::SwapperPool;
+/// let pool = SwapperPool::with_capacity(4);
+/// for round in 0..10 {
+///     let (ab, ba) = pool.acquire();
+///     let helper = thread::spawn(move || {
+///         let mut reply = round + 1;
+///         ba.swap(&mut reply).unwrap();
+///         assert_eq!(reply, round);
+///     });
+///     let mut request = round;
+///     ab.swap(&mut request).unwrap();
+///     assert_eq!(request, round + 1);
+///     # helper.join().unwrap();
+/// }
+/// ```
+///
+/// A half acquired from a pool behaves like any other half: once the other half has
+/// been returned, swaps fail with `SwapError::Disconnected`. A pair registered with a
+/// `SwapEpoch` is not reused, but freed and replaced once both halves have been returned.
+pub struct SwapperPool<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+/// A half of a swap pair acquired from a `SwapperPool`, which is returned to it when dropped.
+///
+/// This dereferences to a `Swapper`, so it swaps in the same way.
+pub struct PooledSwapper<T> {
+    swapper: ManuallyDrop<Swapper<T>>,
+    pool: Arc<Mutex<Inner<T>>>,
+}
+
+struct Inner<T> {
+    capacity: usize,
+    // Pairs which are ready to be acquired.
+    free: Vec<(Swapper<T>, Swapper<T>)>,
+    // Halves which have been returned, by pair id, waiting for the other half.
+    returned: HashMap<u64, Swapper<T>>,
+}
+
+impl<T> SwapperPool<T> {
+    /// Create a pool, allocating the given number of pairs.
+    ///
+    /// This is also the number of pairs the pool keeps for reuse.
+    /// If more are in use at once, the extra pairs are allocated, and freed once returned.
+    pub fn with_capacity(capacity: usize) -> SwapperPool<T> {
+        SwapperPool {
+            inner: Arc::new(Mutex::new(Inner {
+                capacity,
+                free: (0..capacity).map(|_| swapper()).collect(),
+                returned: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Acquire a pair from the pool, allocating a new one if there are none free.
+    pub fn acquire(&self) -> (PooledSwapper<T>, PooledSwapper<T>) {
+        let (a, b) = lock(&self.inner).free.pop().unwrap_or_else(swapper);
+        (self.pooled(a), self.pooled(b))
+    }
+
+    /// The number of pairs which are free to be acquired.
+    pub fn free(&self) -> usize {
+        lock(&self.inner).free.len()
+    }
+
+    fn pooled(&self, swapper: Swapper<T>) -> PooledSwapper<T> {
+        PooledSwapper {
+            swapper: ManuallyDrop::new(swapper),
+            pool: self.inner.clone(),
+        }
+    }
+}
+
+fn lock<T>(inner: &Mutex<Inner<T>>) -> MutexGuard<'_, Inner<T>> {
+    inner.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl<T> Deref for PooledSwapper<T> {
+    type Target = Swapper<T>;
+
+    fn deref(&self) -> &Swapper<T> {
+        &self.swapper
+    }
+}
+
+impl<T> Drop for PooledSwapper<T> {
+    fn drop(&mut self) {
+        let swapper = unsafe { ManuallyDrop::take(&mut self.swapper) };
+        // Release the half as if it had been dropped, but keep its channels for reuse.
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::dropped(swapper.shared.id, swapper.half);
+        release::<T>(&swapper.shared, &swapper.notify);
+        let mut pool = lock(&self.pool);
+        let other = match pool.returned.remove(&swapper.shared.id) {
+            Some(other) => other,
+            None => {
+                pool.returned.insert(swapper.shared.id, swapper);
+                return;
+            }
+        };
+        // Both halves have been returned, so the pair is no longer in use. Any offer was
+        // retracted or completed before its half was returned. The halves were released,
+        // so are counted again, whether they are reused or dropped.
+        swapper.shared.halves.store(2, Ordering::SeqCst);
+        // A pair registered with an epoch is not recycled, since the epoch would keep waiting
+        // for it to swap. It is replaced, and freeing it deregisters it, so waiting epochs
+        // are told.
+        let watched = swapper.shared.watched.load(Ordering::Acquire);
+        if !watched && pool.free.len() < pool.capacity {
+            swapper.shared.swaps.store(0, Ordering::SeqCst);
+            if let Some(ref history) = swapper.shared.history {
+                history.clear();
+            }
+            let pair = if swapper.half == 0 { (swapper, other) } else { (other, swapper) };
+            pool.free.push(pair);
+            return;
+        }
+        if watched && pool.free.len() < pool.capacity {
+            pool.free.push(::swapper());
+        }
+        drop(pool);
+        drop(swapper);
+        drop(other);
+        if watched {
+            epoch::notify();
+        }
+    }
+}
+
+impl<T> fmt::Debug for SwapperPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapperPool")
+            .field("free", &self.free())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for PooledSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PooledSwapper").field(&*self.swapper).finish()
+    }
+}
+
+impl<T> Clone for SwapperPool<T> {
+    fn clone(&self) -> SwapperPool<T> {
+        SwapperPool {
+            inner: self.inner.clone(),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Swapping between processes, using shared memory.
+//!
+//! Enabled by the `process` feature, on Linux. The two halves of the pair share a slot in a
+//! memory-mapped file, and block using futexes, so they can be in different processes.
+//! Since the processes have different address spaces, the data itself is copied into the
+//! shared slot, so can only be plain old data (see `Pod`), such as offsets into a shared
+//! buffer.
+//!
+//! ```rust
+//! # use std::fs::OpenOptions;
+//! # use std::thread;
+//! # use swapper::process::{ProcessSwapper, Side};
+//! # let path = std::env::temp_dir().join(format!("swapper-doc-{}", std::process::id()));
+//! # let _ = std::fs::remove_file(&path);
+//! // In this example both halves are in the same process, but they could be in different ones.
+//! let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+//! let ours = ProcessSwapper::<u64>::open(&file, Side::A).unwrap();
+//! let theirs = ProcessSwapper::<u64>::open(&file, Side::B).unwrap();
+//! let helper = thread::spawn(move || {
+//!     let mut offset = 4096;
+//!     theirs.swap(&mut offset).unwrap();
+//!     assert_eq!(offset, 0);
+//! });
+//! let mut offset = 0;
+//! ours.swap(&mut offset).unwrap();
+//! assert_eq!(offset, 4096);
+//! # helper.join().unwrap();
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! A pair is disconnected when either half is dropped, but not if the process holding it
+//! exits without dropping it, in which case the other half may block forever.
+
+use std::cell::UnsafeCell;
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+use libc;
+
+use SwapError;
+
+/// Data which can be copied between processes.
+///
+/// # Safety
+///
+/// The data must be valid in any process, so must not contain pointers or references.
+pub unsafe trait Pod: Copy + 'static {}
+
+unsafe impl Pod for u8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for u64 {}
+unsafe impl Pod for u128 {}
+unsafe impl Pod for usize {}
+unsafe impl Pod for i8 {}
+unsafe impl Pod for i16 {}
+unsafe impl Pod for i32 {}
+unsafe impl Pod for i64 {}
+unsafe impl Pod for i128 {}
+unsafe impl Pod for isize {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for f64 {}
+unsafe impl Pod for bool {}
+unsafe impl Pod for char {}
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Which half of the pair a process is using.
+///
+/// The two processes sharing a file must use different sides.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    fn index(self) -> usize {
+        match self {
+            Side::A => 0,
+            Side::B => 1,
+        }
+    }
+}
+
+// The state word is a phase, together with a closed flag for each side.
+// It is only ever changed by compare-and-swap, so every change wakes any waiting futex.
+const EMPTY: u32 = 0;
+const OFFERED: [u32; 2] = [1, 2];
+const DONE: [u32; 2] = [3, 4];
+const PHASE: u32 = 0xF;
+const CLOSED: [u32; 2] = [0x10, 0x20];
+
+/// The layout of the shared memory.
+///
+/// A freshly created file is zeroed, which is an empty slot with neither side closed.
+#[repr(C)]
+struct Region<T> {
+    state: AtomicU32,
+    // Each side copies its data into its own cell when it offers to swap.
+    cells: [UnsafeCell<T>; 2],
+}
+
+/// One half of a swap pair, whose other half may be in a different process.
+pub struct ProcessSwapper<T: Pod> {
+    region: *mut Region<T>,
+    ours: usize,
+    theirs: usize,
+    marker: PhantomData<T>,
+}
+
+unsafe impl<T: Pod + Send> Send for ProcessSwapper<T> {}
+
+impl<T: Pod> ProcessSwapper<T> {
+    /// Open one half of a pair, whose slot is stored in `file`.
+    ///
+    /// The file should be empty when the first half is opened, and is extended if necessary.
+    pub fn open(file: &File, side: Side) -> io::Result<ProcessSwapper<T>> {
+        let size = mem::size_of::<Region<T>>();
+        if file.metadata()?.len() < size as u64 {
+            file.set_len(size as u64)?;
+        }
+        let region = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if region == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ProcessSwapper {
+            region: region.cast(),
+            ours: side.index(),
+            theirs: 1 - side.index(),
+            marker: PhantomData,
+        })
+    }
+
+    fn region(&self) -> &Region<T> {
+        unsafe { &*self.region }
+    }
+
+    /// Swap data.
+    ///
+    /// If the other half of the swap pair is blocked waiting to swap, then it swaps
+    /// the data, then unblocks the other half. Otherwise it blocks waiting to swap.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let region = self.region();
+        let our_cell = region.cells[self.ours].get();
+        let their_cell = region.cells[self.theirs].get();
+        loop {
+            let state = region.state.load(Ordering::Acquire);
+            if state & CLOSED[self.theirs] != 0 {
+                return Err(SwapError::Disconnected);
+            }
+            let phase = state & PHASE;
+            if phase == OFFERED[self.theirs] {
+                // The other half is blocked waiting to swap, so swap with its cell and unblock it.
+                unsafe { ptr::swap(our_ref, their_cell) };
+                self.transition(state, DONE[self.theirs]);
+                return Ok(());
+            } else if phase == EMPTY {
+                // Offer to swap, by copying our data into our cell.
+                unsafe { ptr::write(our_cell, *our_ref) };
+                if region.state.compare_exchange(state, state | OFFERED[self.ours], Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                    return self.wait(our_ref);
+                }
+            } else {
+                // The other half is still collecting the result of the previous swap.
+                futex_wait(&region.state, state);
+            }
+        }
+    }
+
+    // Wait for our offer to be taken.
+    fn wait(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let region = self.region();
+        loop {
+            let state = region.state.load(Ordering::Acquire);
+            if state & PHASE == DONE[self.ours] {
+                unsafe { *our_ref = ptr::read(region.cells[self.ours].get()) };
+                self.transition(state, EMPTY);
+                return Ok(());
+            } else if state & CLOSED[self.theirs] != 0 {
+                // Retract our offer, unless the other half took it before closing.
+                if region.state.compare_exchange(state, state & !PHASE, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                    return Err(SwapError::Disconnected);
+                }
+            } else {
+                futex_wait(&region.state, state);
+            }
+        }
+    }
+
+    // Change the phase, preserving the closed flags, and wake the other half.
+    fn transition(&self, mut state: u32, phase: u32) {
+        let region = self.region();
+        while let Err(current) = region.state.compare_exchange(state, (state & !PHASE) | phase, Ordering::AcqRel, Ordering::Acquire) {
+            state = current;
+        }
+        futex_wake(&region.state);
+    }
+}
+
+impl<T: Pod> Drop for ProcessSwapper<T> {
+    fn drop(&mut self) {
+        let region = self.region();
+        region.state.fetch_or(CLOSED[self.ours], Ordering::AcqRel);
+        futex_wake(&region.state);
+        unsafe { libc::munmap(self.region.cast(), mem::size_of::<Region<T>>()) };
+    }
+}
+
+// Block while the futex word has the given value. This may return spuriously.
+fn futex_wait(word: &AtomicU32, value: u32) {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            value,
+            ptr::null::<libc::timespec>(),
+        );
+    }
+}
+
+// Wake every process blocked on the futex word.
+fn futex_wake(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
@@ -0,0 +1,152 @@
+//! Swapping from threads which cannot block, such as GUI event loops.
+
+use std::fmt;
+use std::sync::Arc;
+use std::task;
+use std::task::Wake;
+
+use CollectError;
+use Swapper;
+use deposit::RawDeposit;
+use wake::TaskWaker;
+
+/// A half of a swap pair whose swaps are completed by polling.
+///
+/// The main thread of a GUI toolkit cannot block inside `swap`, so instead it offers
+/// a value, and periodically calls `pump` from its event loop to collect the value the
+/// other half traded in. The other half uses the normal blocking `swap`. Rather than
+/// polling on every iteration of the event loop, a callback can be registered with
+/// `on_ready`, to post an event to the loop once the swap can be collected.
+///
+/// ```rust
+/// # use std::sync::mpsc;
+/// # use std::thread;
+/// let (ab, ba) = swapper::swapper();
+/// let mut pump = swapper::SwapPump::new(ab);
+/// // Standing in for an event loop proxy.
+/// let (proxy, events) = mpsc::channel();
+/// pump.on_ready(move || proxy.send("swap ready").unwrap());
+/// pump.offer(String::from("hello")).unwrap();
+/// let helper = thread::spawn(move || {
+///     let mut world = String::from("world");
+///     ba.swap(&mut world).unwrap();
+///     assert_eq!(world, "hello");
+/// });
+/// assert_eq!(events.recv(), Ok("swap ready"));
+/// assert_eq!(pump.pump(), Some(Ok(String::from("world"))));
+/// # helper.join().unwrap();
+/// ```
+pub struct SwapPump<T> {
+    swapper: Swapper<T>,
+    deposit: Option<RawDeposit<T>>,
+    task: Arc<TaskWaker>,
+    ready: Option<task::Waker>,
+}
+
+// Calls the callback registered with `on_ready`.
+struct Callback<F>(F);
+
+impl<F: Fn() + Send + Sync + 'static> Wake for Callback<F> {
+    fn wake(self: Arc<Self>) {
+        (self.0)()
+    }
+}
+
+impl<T: Send> SwapPump<T> {
+    /// Use a half of a swap pair from a thread which cannot block.
+    pub fn new(swapper: Swapper<T>) -> SwapPump<T> {
+        SwapPump {
+            swapper,
+            deposit: None,
+            task: Arc::new(TaskWaker::new()),
+            ready: None,
+        }
+    }
+
+    /// Offer a value to swap, without blocking.
+    ///
+    /// If a swap is already in progress, this returns the value. If the other half is
+    /// already waiting, the swap completes immediately, and the next `pump` collects it
+    /// without the `on_ready` callback being called.
+    pub fn offer(&mut self, value: T) -> Result<(), T> {
+        if self.deposit.is_some() {
+            return Err(value);
+        }
+        self.register();
+        self.deposit = Some(RawDeposit::new(&self.swapper, value, Some(self.task.clone())));
+        Ok(())
+    }
+
+    /// Collect the value the other half traded in, if the swap has completed.
+    ///
+    /// Returns `None` if there is no swap in progress, or the other half has not swapped yet.
+    /// If the other half was dropped without swapping, this returns the offered value.
+    pub fn pump(&mut self) -> Option<Result<T, CollectError<T>>> {
+        let mut deposit = self.deposit.take()?;
+        // Register before checking, so a wake between the check and returning is not lost.
+        self.register();
+        match deposit.try_wait(&self.swapper) {
+            Some(result) => Some(deposit.free(result)),
+            None => {
+                self.deposit = Some(deposit);
+                None
+            }
+        }
+    }
+
+    /// Cancel the swap in progress, if any, without waiting for the other half.
+    ///
+    /// If the other half has not taken the offer, it is retracted, and this returns the
+    /// offered value with `SwapError::WouldBlock`. If the other half has taken it, this
+    /// waits for the swap to complete, and returns the value it traded in.
+    pub fn cancel(&mut self) -> Option<Result<T, CollectError<T>>> {
+        let deposit = self.deposit.take()?;
+        let result = deposit.retract(&self.swapper);
+        Some(deposit.free(result))
+    }
+}
+
+impl<T> SwapPump<T> {
+    /// Register a callback, which is called on the other half's thread when a swap
+    /// in progress can be collected by `pump`.
+    ///
+    /// The callback should not do the work itself, but post an event to the event loop,
+    /// for example using winit's `EventLoopProxy` or glib's `MainContext::invoke`.
+    pub fn on_ready<F: Fn() + Send + Sync + 'static>(&mut self, callback: F) {
+        self.ready = Some(task::Waker::from(Arc::new(Callback(callback))));
+        self.register();
+    }
+
+    /// Is a swap in progress?
+    pub fn is_pending(&self) -> bool {
+        self.deposit.is_some()
+    }
+
+    fn register(&self) {
+        if let Some(ref ready) = self.ready {
+            self.task.register(ready);
+        }
+    }
+}
+
+impl<T> Drop for SwapPump<T> {
+    fn drop(&mut self) {
+        // If the offer has been taken, wait for the swap to complete before freeing it.
+        if let Some(deposit) = self.deposit.take() {
+            let result = deposit.retract(&self.swapper);
+            let _ = deposit.free(result);
+        }
+    }
+}
+
+impl<T> fmt::Debug for SwapPump<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapPump")
+            .field("swapper", &self.swapper)
+            .field("pending", &self.is_pending())
+            .finish()
+    }
+}
+
+// Be explicit about implementing Send.
+unsafe impl<T: Send> Send for SwapPump<T> {}
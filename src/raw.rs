@@ -0,0 +1,160 @@
+//! The low-level offer slot at the heart of the swap protocol, for building other structures.
+//!
+//! A `Swapper` is a safe wrapper around the same slot, and a way of waking a blocked thread.
+//! Other structures, such as elimination arrays or custom schedulers, can be built from
+//! the slot directly, providing their own way of waiting. The slot only passes a pointer
+//! to an offer from one thread to another; what the offer contains, and how the offering
+//! thread learns that it has been taken, is up to the structure built on it.
+//!
+//! ```rust
+//! # use std::ptr::{self, NonNull};
+//! # use std::sync::atomic::{AtomicBool, Ordering};
+//! # use std::thread;
+//! # use swapper::raw::SwapSlot;
+//! struct Offer {
+//!     data: NonNull<String>,
+//!     // Set by the taker once it has finished with the offer.
+//!     done: AtomicBool,
+//! }
+//!
+//! // The data is only accessed by one thread at a time, as agreed through the slot.
+//! unsafe impl Send for Offer {}
+//!
+//! fn exchange(slot: &SwapSlot<Offer>, ours: &mut String) {
+//!     loop {
+//!         if let Some(theirs) = slot.take_offer() {
+//!             // We have exclusive access to the offer until we mark it done.
+//!             let theirs = unsafe { theirs.as_ref() };
+//!             unsafe { ptr::swap(ours, theirs.data.as_ptr()) };
+//!             theirs.done.store(true, Ordering::Release);
+//!             return;
+//!         }
+//!         let offer = Offer { data: NonNull::from(&mut *ours), done: AtomicBool::new(false) };
+//!         // We do not touch our data, or move or drop the offer, until it is done or retracted.
+//!         if unsafe { slot.offer(NonNull::from(&offer)) } {
+//!             for _ in 0..100 {
+//!                 thread::yield_now();
+//!             }
+//!             if slot.retract(NonNull::from(&offer)) {
+//!                 continue;
+//!             }
+//!             // The offer was taken, so wait for the taker to finish with it.
+//!             while !offer.done.load(Ordering::Acquire) {
+//!                 thread::yield_now();
+//!             }
+//!             return;
+//!         }
+//!     }
+//! }
+//!
+//! let slot = SwapSlot::new();
+//! thread::scope(|scope| {
+//!     scope.spawn(|| {
+//!         let mut hello = String::from("hello");
+//!         exchange(&slot, &mut hello);
+//!         assert_eq!(hello, "world");
+//!     });
+//!     let mut world = String::from("world");
+//!     exchange(&slot, &mut world);
+//!     assert_eq!(world, "hello");
+//! });
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use slot::Slot;
+
+/// A slot holding at most one offer, of type `T`, made by one thread for another to take.
+///
+/// The slot itself is a single atomic pointer. The protocol for using it is:
+///
+/// * A thread makes an offer with `offer`, which succeeds if the slot is empty.
+/// * Another thread takes the offer with `take_offer`, leaving the slot empty. It then has
+///   exclusive access to the offer, until it tells the offering thread it has finished,
+///   by some means outside the slot, such as a flag in the offer.
+/// * Until then, the offering thread can try to `retract` its offer. If that succeeds,
+///   the offer was not taken, and no other thread has access to it. If it fails, the offer
+///   has been taken, and the offering thread must wait for the taker to finish.
+///
+/// The slot never wakes the offering thread. A `Swapper` wakes it with its waiting backend, a
+/// `ScopedSlot` by unparking it, and a spinning structure need not wake it at all, so the
+/// wakeup is left to the structure built on the slot, which knows how its threads wait.
+///
+/// The slot is `Send` and `Sync` when the offers are `Send`, since each offer is handed from
+/// the offering thread to the taker's thread. Offers often contain raw pointers, so are not
+/// `Send` automatically; it is up to the type of the offer to implement it, if the data it
+/// points to can be accessed from the taker's thread.
+pub struct SwapSlot<T> {
+    slot: Slot,
+    marker: PhantomData<*mut T>,
+}
+
+impl<T> SwapSlot<T> {
+    /// Create an empty slot.
+    pub fn new() -> SwapSlot<T> {
+        SwapSlot {
+            slot: Slot::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Is there currently no offer in the slot?
+    ///
+    /// Another thread may make or take an offer at any time, so this is only a hint.
+    pub fn is_empty(&self) -> bool {
+        self.slot.is_empty()
+    }
+
+    /// Publish an offer, if the slot is empty, returning whether it was published.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, then until the offer has been successfully retracted, or
+    /// taken and finished with by the taker, the offering thread must not move or drop the
+    /// offer, nor access it in any way which conflicts with how takers access it. The offer
+    /// must be valid for whatever access takers of this slot make, which must be safe from
+    /// whichever thread takes it. The offer must not already be in the slot.
+    pub unsafe fn offer(&self, offer: NonNull<T>) -> bool {
+        self.slot.offer(offer)
+    }
+
+    /// Take the offer in the slot, if there is one, leaving the slot empty.
+    ///
+    /// The taker has exclusive access to the offer, as agreed with the offering thread,
+    /// and must tell the offering thread once it has finished with it, since until then the
+    /// offering thread cannot reuse it. Dereferencing the pointer is only safe if every
+    /// thread making offers to this slot upholds the contract of `offer`.
+    pub fn take_offer(&self) -> Option<NonNull<T>> {
+        self.slot.take()
+    }
+
+    /// Retract an offer, returning whether it was still in the slot.
+    ///
+    /// If this returns `true`, no other thread has access to the offer. If it returns `false`,
+    /// the offer has been taken, or was never in the slot, and an offering thread must wait
+    /// for the taker to finish with it.
+    pub fn retract(&self, offer: NonNull<T>) -> bool {
+        self.slot.retract(offer)
+    }
+}
+
+impl<T> Default for SwapSlot<T> {
+    fn default() -> SwapSlot<T> {
+        SwapSlot::new()
+    }
+}
+
+impl<T> fmt::Debug for SwapSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapSlot")
+            .field("empty", &self.is_empty())
+            .finish()
+    }
+}
+
+// The slot only holds a pointer to an offer, which the contract of `offer` makes safe to take,
+// and which is accessed by one thread at a time.
+unsafe impl<T: Send> Send for SwapSlot<T> {}
+unsafe impl<T: Send> Sync for SwapSlot<T> {}
@@ -0,0 +1,98 @@
+//! A global registry of swap pairs, for debugging.
+//!
+//! When a program has many swap pairs, a hang is hard to attribute to any one of them.
+//! Pairs built with `SwapperBuilder::register(true)` are recorded here, and can be
+//! listed along with their names and current states:
+//!
+//! ```rust
+//! # use swapper::SwapperBuilder;
+//! # use swapper::registry::{self, PairState};
+//! let (ab, ba) = SwapperBuilder::new().name("gpu-upload").register(true).build::<u8>();
+//! let info = registry::pairs().into_iter().find(|info| info.id == ab.id()).unwrap();
+//! assert_eq!(info.name.as_deref(), Some("gpu-upload"));
+//! assert_eq!(info.state, PairState::Empty);
+//! drop(ba);
+//! println!("{}", registry::dump());
+//! ```
+//!
+//! The registry only holds weak references, so registering a pair does not keep it alive.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use Shared;
+
+static REGISTRY: Mutex<Vec<Weak<Shared>>> = Mutex::new(Vec::new());
+
+/// The state of a registered swap pair.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PairState {
+    /// Neither half is waiting to swap.
+    Empty,
+    /// One half is blocked waiting for the other to swap.
+    Offered,
+    /// One of the halves has been dropped.
+    Disconnected,
+}
+
+/// A snapshot of a registered swap pair.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PairInfo {
+    /// The id of the pair, as returned by `Swapper::id`.
+    pub id: u64,
+    /// The name of the pair, as given to `SwapperBuilder::name`.
+    pub name: Option<String>,
+    /// The state of the pair when the snapshot was taken.
+    pub state: PairState,
+}
+
+impl fmt::Display for PairInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "#{} {:?}: {:?}", self.id, name, self.state),
+            None => write!(f, "#{}: {:?}", self.id, self.state),
+        }
+    }
+}
+
+pub(crate) fn register(shared: &Arc<Shared>) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|pair| pair.strong_count() > 0);
+    registry.push(Arc::downgrade(shared));
+}
+
+/// Snapshot every live registered pair, in order of creation.
+///
+/// Pairs where both halves have been dropped are removed from the registry.
+pub fn pairs() -> Vec<PairInfo> {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|pair| pair.strong_count() > 0);
+    registry
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|shared| info(&shared))
+        .collect()
+}
+
+/// Snapshot a pair, given a strong reference to its shared state.
+pub(crate) fn info(shared: &Arc<Shared>) -> PairInfo {
+    let state = if shared.is_disconnected() {
+        PairState::Disconnected
+    } else if shared.slot.is_empty() {
+        PairState::Empty
+    } else {
+        PairState::Offered
+    };
+    PairInfo {
+        id: shared.id,
+        name: shared.name.clone(),
+        state,
+    }
+}
+
+/// Format every live registered pair, one per line.
+pub fn dump() -> String {
+    pairs().iter().map(|info| format!("{}\n", info)).collect()
+}
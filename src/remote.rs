@@ -0,0 +1,138 @@
+//! Swapping between processes connected by a socket.
+//!
+//! Enabled by the `remote` feature. Each half of the pair holds one end of a stream, such as
+//! a `TcpStream` or `UnixStream`, and a swap sends our data down the stream, and receives the
+//! other half's data in its place. Since the data is copied, it must be serializable with
+//! [serde](https://serde.rs), and is encoded with `bincode`, but code which swaps through `swap(&mut T)` works the same whether the other
+//! half is in another thread, using a `Swapper`, or in another process, using a `RemoteSwapper`.
+//!
+//! ```rust
+//! # use std::os::unix::net::UnixStream;
+//! # use std::thread;
+//! # use swapper::remote::{RemoteSwapper, Side};
+//! // In this example both halves are in the same process, but they could be in different ones.
+//! let (a, b) = UnixStream::pair().unwrap();
+//! let ours = RemoteSwapper::new(a, Side::A);
+//! let theirs = RemoteSwapper::new(b, Side::B);
+//! let helper = thread::spawn(move || {
+//!     let mut token = String::from("world");
+//!     theirs.swap(&mut token).unwrap();
+//!     assert_eq!(token, "hello");
+//! });
+//! let mut token = String::from("hello");
+//! ours.swap(&mut token).unwrap();
+//! assert_eq!(token, "world");
+//! # helper.join().unwrap();
+//! ```
+//!
+//! Each swap is framed as a length followed by the encoded data. Side A sends first and
+//! side B receives first, so large data cannot fill both directions of the stream at once.
+//! If the stream is closed or fails, swaps return `SwapError::Disconnected`, and if the
+//! data received cannot be decoded, they return `SwapError::Mismatch`. In either case our
+//! data is left in place, but unlike a failed swap between threads, that does not mean the
+//! other half does not have it. On a mismatch, the other half has already received our data,
+//! and its swap succeeded, so the data has been duplicated, with both halves now holding it.
+//! On a disconnection, the other half may or may not have received our data, depending on
+//! when the stream failed. Data whose ownership must not be duplicated, such as a lease or
+//! a token, needs some way of detecting or reconciling this, such as a sequence number.
+
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use bincode;
+use bincode::Options;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use SwapError;
+
+/// Which half of the pair a process is using.
+///
+/// The two ends of a stream must use different sides.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Side {
+    A,
+    B,
+}
+
+/// One half of a swap pair, whose other half is at the other end of a stream.
+pub struct RemoteSwapper<T, S> {
+    stream: Mutex<S>,
+    side: Side,
+    marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T, S> RemoteSwapper<T, S> {
+    /// Create one half of a pair, from one end of a stream.
+    pub fn new(stream: S, side: Side) -> RemoteSwapper<T, S> {
+        RemoteSwapper {
+            stream: Mutex::new(stream),
+            side,
+            marker: PhantomData,
+        }
+    }
+
+    /// Which side of the pair this is.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Get back the stream.
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, S: Read + Write> RemoteSwapper<T, S> {
+    /// Swap data with the other half.
+    ///
+    /// This blocks until the other half swaps. If our data cannot be serialized, this returns
+    /// `SwapError::Mismatch` without sending anything, so the other half does not receive it.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let mut stream = self.stream.lock().unwrap_or_else(|err| err.into_inner());
+        let ours = options().serialize(our_ref).map_err(|_| SwapError::Mismatch)?;
+        let theirs = match self.side {
+            Side::A => send(&mut *stream, &ours).and_then(|()| receive(&mut *stream)),
+            Side::B => receive(&mut *stream).and_then(|theirs| send(&mut *stream, &ours).map(|()| theirs)),
+        };
+        let theirs = theirs.map_err(|_| SwapError::Disconnected)?;
+        // The other half has our data by now, so a mismatch duplicates it.
+        *our_ref = options().deserialize(&theirs).map_err(|_| SwapError::Mismatch)?;
+        Ok(())
+    }
+}
+
+// Both ends must agree on the options. Integers are variable-length encoded, so both ends
+// agree however wide their `usize` is, and each frame must be exactly one value.
+fn options() -> impl Options {
+    bincode::options().reject_trailing_bytes()
+}
+
+fn send<S: Write>(stream: &mut S, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    stream.write_all(bytes)?;
+    stream.flush()
+}
+
+fn receive<S: Read>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    stream.read_exact(&mut len)?;
+    let mut bytes = Vec::new();
+    stream.take(u64::from_le_bytes(len)).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != u64::from_le_bytes(len) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+impl<T, S> fmt::Debug for RemoteSwapper<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RemoteSwapper")
+            .field("side", &self.side)
+            .finish()
+    }
+}
@@ -0,0 +1,186 @@
+//! Recording the order in which pairs swap, and replaying it, for debugging.
+//!
+//! Enabled by the `replay` feature. While recording, each completed swap is appended to
+//! a global log, with the pair, its generation, the threads involved, and when it happened.
+//! Bugs which depend on the order of swaps between many pairs are hard to reproduce, but
+//! once a failing run has been recorded, a `Replayer` can re-execute that order against
+//! callbacks, single-threaded and deterministically, as many times as needed.
+//!
+//! ```rust
+//! # use std::cell::RefCell;
+//! # use std::thread;
+//! # use swapper::replay::{self, Replayer};
+//! let (ab, ba) = swapper::swapper();
+//! let (cd, dc) = swapper::swapper();
+//! replay::start_recording();
+//! let helper = thread::spawn(move || {
+//!     ba.swap(&mut 1).unwrap();
+//!     dc.swap(&mut 2).unwrap();
+//! });
+//! ab.swap(&mut 3).unwrap();
+//! cd.swap(&mut 4).unwrap();
+//! # helper.join().unwrap();
+//! let log = replay::stop_recording();
+//! # let log: Vec<_> = log.into_iter().filter(|event| event.pair == ab.id() || event.pair == cd.id()).collect();
+//! assert_eq!(log.len(), 2);
+//! let order = RefCell::new(Vec::new());
+//! Replayer::new(log)
+//!     .on_pair(ab.id(), |event| order.borrow_mut().push(("ab", event.generation)))
+//!     .on_pair(cd.id(), |event| order.borrow_mut().push(("cd", event.generation)))
+//!     .run();
+//! assert_eq!(order.into_inner(), [("ab", 0), ("cd", 0)]);
+//! ```
+//!
+//! Recording takes a global lock for each swap, so changes the timing of the program
+//! being recorded. Swaps which fail, and `swap_cloned` calls, are not recorded.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::ThreadId;
+use std::time::Duration;
+use std::time::Instant;
+
+use Shared;
+
+/// A swap, as recorded in the log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapEvent {
+    /// The unique id of the pair which swapped.
+    pub pair: u64,
+    /// The generation of the swap, which is the number of swaps the pair had completed before it.
+    pub generation: u64,
+    /// The half of the pair whose thread completed the swap, 0 or 1.
+    pub half: usize,
+    /// The thread which completed the swap, by taking the other half's offer.
+    pub taker: ThreadId,
+    /// The thread which made the offer, if it was blocked waiting, rather than left a deposit.
+    pub offerer: Option<ThreadId>,
+    /// When the swap completed, since recording started.
+    pub at: Duration,
+}
+
+/// Re-executes a recorded log of swaps, calling back for each one in order.
+pub struct Replayer<'a> {
+    events: Vec<SwapEvent>,
+    next: usize,
+    callbacks: Vec<(u64, Callback<'a>)>,
+}
+
+type Callback<'a> = Box<dyn FnMut(&SwapEvent) + 'a>;
+
+struct Log {
+    start: Instant,
+    events: Vec<SwapEvent>,
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Log>> {
+    LOG.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Start recording swaps, discarding any previous recording.
+pub fn start_recording() {
+    *lock() = Some(Log {
+        start: Instant::now(),
+        events: Vec::new(),
+    });
+    RECORDING.store(true, Ordering::SeqCst);
+}
+
+/// Stop recording swaps, and return the log, in the order the swaps completed.
+pub fn stop_recording() -> Vec<SwapEvent> {
+    RECORDING.store(false, Ordering::SeqCst);
+    lock().take().map(|log| log.events).unwrap_or_default()
+}
+
+/// Is a recording in progress?
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Acquire)
+}
+
+/// Record a swap which the current thread is completing, using the given half.
+///
+/// This is called before the swap is counted, and before the offering thread is unblocked,
+/// so swaps are recorded in the order they happen.
+pub(crate) fn record(shared: &Shared, half: usize, offerer: Option<ThreadId>) {
+    if !is_recording() {
+        return;
+    }
+    if let Some(ref mut log) = *lock() {
+        let event = SwapEvent {
+            pair: shared.id,
+            generation: shared.swaps.load(Ordering::Acquire),
+            half,
+            taker: thread::current().id(),
+            offerer,
+            at: log.start.elapsed(),
+        };
+        log.events.push(event);
+    }
+}
+
+impl<'a> Replayer<'a> {
+    /// Create a replayer for a log, with no callbacks.
+    pub fn new(events: Vec<SwapEvent>) -> Replayer<'a> {
+        Replayer {
+            events,
+            next: 0,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Call `callback` for each swap made by the pair with the given id.
+    ///
+    /// If there are several callbacks for the same pair, they are called in the order they were added.
+    pub fn on_pair<F: FnMut(&SwapEvent) + 'a>(mut self, pair: u64, callback: F) -> Replayer<'a> {
+        self.callbacks.push((pair, Box::new(callback)));
+        self
+    }
+
+    /// Replay the next swap, returning it, or `None` if the log has been replayed.
+    pub fn step(&mut self) -> Option<&SwapEvent> {
+        let event = self.events.get(self.next)?;
+        self.next += 1;
+        for (pair, callback) in &mut self.callbacks {
+            if *pair == event.pair {
+                callback(event);
+            }
+        }
+        Some(event)
+    }
+
+    /// Replay the rest of the log.
+    pub fn run(&mut self) {
+        while self.step().is_some() {}
+    }
+
+    /// The swaps which have not been replayed yet.
+    pub fn remaining(&self) -> &[SwapEvent] {
+        &self.events[self.next..]
+    }
+
+    /// Start again from the beginning of the log.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+
+    /// Get back the log.
+    pub fn into_inner(self) -> Vec<SwapEvent> {
+        self.events
+    }
+}
+
+impl<'a> fmt::Debug for Replayer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Replayer")
+            .field("replayed", &self.next)
+            .field("remaining", &self.remaining().len())
+            .finish()
+    }
+}
@@ -0,0 +1,91 @@
+//! Requests to swap, carried over an existing channel.
+
+use std::fmt;
+use std::sync::mpsc::Sender;
+
+use SwapError;
+use Swapper;
+use swapper;
+
+/// A request to swap, sent to another thread over a channel.
+///
+/// This packages up the pattern of creating a swap pair, sending one half to the other
+/// thread in a message, and swapping with the other half. The requester calls `request`,
+/// which blocks until the thread receiving the message calls `fulfill`.
+///
+/// ```rust
+/// # use std::sync::mpsc;
+/// # use std::thread;
+/// # use swapper::{SwapMessage, SwapRequest};
+/// struct Token(u32);
+/// enum Message {
+///     Swap(SwapRequest<Token>),
+///     Stop,
+/// }
+/// impl SwapMessage<Token> for Message {
+///     fn swap_request(request: SwapRequest<Token>) -> Message {
+///         Message::Swap(request)
+///     }
+/// }
+/// let (sender, receiver) = mpsc::channel();
+/// let helper = thread::spawn(move || {
+///     let mut token = Token(1);
+///     for message in receiver {
+///         match message {
+///             Message::Swap(request) => request.fulfill(&mut token).unwrap(),
+///             Message::Stop => break,
+///         }
+///     }
+///     assert_eq!(token.0, 0);
+/// });
+/// let mut token = Token(0);
+/// SwapRequest::request(&sender, &mut token).unwrap();
+/// assert_eq!(token.0, 1);
+/// sender.send(Message::Stop).unwrap();
+/// # helper.join().unwrap();
+/// ```
+pub struct SwapRequest<T>(Swapper<T>);
+
+/// A message type which can carry a `SwapRequest`, typically as a variant of an enum.
+pub trait SwapMessage<T>: Sized {
+    /// Embed the request in a message.
+    fn swap_request(request: SwapRequest<T>) -> Self;
+}
+
+impl<T> SwapMessage<T> for SwapRequest<T> {
+    fn swap_request(request: SwapRequest<T>) -> SwapRequest<T> {
+        request
+    }
+}
+
+impl<T: Send> SwapRequest<T> {
+    /// Send a request to swap, and block until the receiver fulfills it.
+    ///
+    /// If the receiver has hung up, or drops the request without fulfilling it,
+    /// this returns `SwapError::Disconnected`, and the data is unchanged.
+    pub fn request<M: SwapMessage<T>>(sender: &Sender<M>, our_ref: &mut T) -> Result<(), SwapError> {
+        let (ours, theirs) = swapper();
+        if sender.send(M::swap_request(SwapRequest(theirs))).is_err() {
+            return Err(SwapError::Disconnected);
+        }
+        ours.swap(our_ref)
+    }
+
+    /// Fulfill a request, swapping data with the requester.
+    pub fn fulfill(self, our_ref: &mut T) -> Result<(), SwapError> {
+        self.0.swap(our_ref)
+    }
+}
+
+impl<T> SwapRequest<T> {
+    /// The unique id of the swap pair carrying this request.
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+}
+
+impl<T> fmt::Debug for SwapRequest<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SwapRequest").field(&self.0).finish()
+    }
+}
@@ -0,0 +1,186 @@
+//! Swap pairs whose shared state lives on the stack, for use with scoped threads.
+
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::atomic;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::Thread;
+
+use SwapError;
+use slot::Slot;
+
+/// The shared state of a swap pair, provided by the caller.
+///
+/// A `Swapper` keeps its shared state in an `Arc`, and blocks using channels, so creating
+/// a pair allocates. For short-lived exchanges between scoped threads, the shared state
+/// can instead live on the stack, and be split into a pair of halves which borrow it.
+/// Waiting threads are parked, so no allocation is needed at all.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::ScopedSlot;
+/// let mut slot = ScopedSlot::new();
+/// let (ab, ba) = slot.split();
+/// thread::scope(|scope| {
+///     scope.spawn(move || {
+///         let mut hello = String::from("hello");
+///         ab.swap(&mut hello).unwrap();
+///         assert_eq!(hello, "world");
+///     });
+///     let mut world = String::from("world");
+///     ba.swap(&mut world).unwrap();
+///     assert_eq!(world, "hello");
+/// });
+/// ```
+///
+/// Once both halves have been dropped, the slot can be split again.
+pub struct ScopedSlot<T> {
+    slot: Slot,
+    halves: AtomicUsize,
+    marker: PhantomData<T>,
+}
+
+/// One half of a swap pair, borrowing its shared state from a `ScopedSlot`.
+pub struct ScopedSwapper<'a, T: 'a> {
+    shared: &'a ScopedSlot<T>,
+}
+
+/// An offer to swap, which lives on the stack of the offering thread while it is parked.
+struct Offer<T> {
+    data: NonNull<T>,
+    thread: Thread,
+    // Set by the thread that takes the offer, before it unparks the offering thread.
+    outcome: Cell<Result<(), SwapError>>,
+    done: AtomicBool,
+}
+
+impl<T> ScopedSlot<T> {
+    /// Create the shared state for a swap pair.
+    pub fn new() -> ScopedSlot<T> {
+        ScopedSlot {
+            slot: Slot::new(),
+            halves: AtomicUsize::new(0),
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the slot into a pair of halves, which can swap until either is dropped.
+    pub fn split(&mut self) -> (ScopedSwapper<'_, T>, ScopedSwapper<'_, T>) {
+        // We have exclusive access, so the halves from any previous split have been dropped.
+        self.halves.store(2, Ordering::SeqCst);
+        (ScopedSwapper { shared: self }, ScopedSwapper { shared: self })
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.halves.load(Ordering::SeqCst) < 2
+    }
+}
+
+impl<T> Default for ScopedSlot<T> {
+    fn default() -> ScopedSlot<T> {
+        ScopedSlot::new()
+    }
+}
+
+impl<'a, T: Send> ScopedSwapper<'a, T> {
+    /// Swap data.
+    ///
+    /// If the other half of the swap pair is parked waiting to swap, then it swaps ownership
+    /// of the data, then unparks the other thread. Otherwise it parks waiting to swap.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let shared = self.shared;
+        let our_offer = Offer {
+            data: NonNull::from(our_ref),
+            thread: thread::current(),
+            outcome: Cell::new(Ok(())),
+            done: AtomicBool::new(false),
+        };
+        loop {
+            if shared.is_disconnected() {
+                return Err(SwapError::Disconnected);
+            }
+            // Is the other thead parked waiting to swap? If so, swap and unpark it.
+            if let Some(their_offer) = shared.slot.take::<Offer<T>>() {
+                let their_offer = unsafe { their_offer.as_ref() };
+                if their_offer.thread.id() == our_offer.thread.id() {
+                    shared.slot.offer(NonNull::from(their_offer));
+                    return Err(SwapError::WouldDeadlock);
+                }
+                // The safety of this implementation depends on the other thread being parked
+                // while this swap happens.
+                unsafe { ptr::swap_nonoverlapping(our_offer.data.as_ptr(), their_offer.data.as_ptr(), 1) };
+                complete(their_offer, Ok(()));
+                return Ok(());
+            }
+            // Is the other thead not ready for a swap yet? If so, park waiting to swap.
+            if shared.slot.offer(NonNull::from(&our_offer)) {
+                return self.wait(&our_offer);
+            }
+        }
+    }
+
+    // Wait for our offer to be taken and completed.
+    fn wait(&self, our_offer: &Offer<T>) -> Result<(), SwapError> {
+        // Pairs with the fence in `drop`, so either we see the other half has been dropped,
+        // or it sees our offer.
+        atomic::fence(Ordering::SeqCst);
+        loop {
+            if our_offer.done.load(Ordering::Acquire) {
+                return our_offer.outcome.get();
+            }
+            if self.shared.is_disconnected() && self.shared.slot.retract(NonNull::from(our_offer)) {
+                return Err(SwapError::Disconnected);
+            }
+            thread::park();
+        }
+    }
+}
+
+// Complete an offer, and unpark the thread that made it.
+fn complete<T>(offer: &Offer<T>, outcome: Result<(), SwapError>) {
+    // The offer may be freed as soon as it is marked as done.
+    let thread = offer.thread.clone();
+    offer.outcome.set(outcome);
+    offer.done.store(true, Ordering::Release);
+    thread.unpark();
+}
+
+impl<'a, T> Drop for ScopedSwapper<'a, T> {
+    fn drop(&mut self) {
+        self.shared.halves.fetch_sub(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        // We cannot be swapping, so any offer is from the other half, which will never be taken.
+        if let Some(their_offer) = self.shared.slot.take::<Offer<T>>() {
+            complete(unsafe { their_offer.as_ref() }, Err(SwapError::Disconnected));
+        }
+    }
+}
+
+impl<T> fmt::Debug for ScopedSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScopedSlot")
+            .field("halves", &self.halves.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl<'a, T> fmt::Debug for ScopedSwapper<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScopedSwapper")
+            .field("disconnected", &self.shared.is_disconnected())
+            .finish()
+    }
+}
+
+// The slot is only accessed through the swap protocol, which hands the data between threads.
+unsafe impl<T: Send> Sync for ScopedSlot<T> {}
+
+// Be explicit about implementing Send.
+unsafe impl<T: Send> Send for ScopedSlot<T> {}
+unsafe impl<'a, T: Send> Send for ScopedSwapper<'a, T> {}
@@ -0,0 +1,527 @@
+//! Swapping between the members of a dynamic set of threads.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+
+use PartnerState;
+use SwapError;
+
+/// A set of threads which can swap with each other, and which can join or leave at any time.
+///
+/// Each thread registers to get a `SetMember`, which can swap with a specific member
+/// by id, or with any other member.
+///
+/// Members are matched in arrival order: a member which arrives to swap is paired with
+/// the member which has been waiting longest out of those it can swap with. So under
+/// sustained contention, no waiting member is starved by later arrivals.
+///
+/// Members can also be given priorities, using `set_priority`, in which case a member
+/// which arrives to swap is paired with the waiting member with the highest priority,
+/// out of those it can swap with. To stop low priority members being starved, a waiting
+/// member's priority rises by one for each `set_priority_aging` interval it has waited,
+/// 10ms by default.
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::SwapperSet;
+/// let set = SwapperSet::new();
+/// let (background, mut render, producer) = (set.register(), set.register(), set.register());
+/// let (render_id, producer_id) = (render.id(), producer.id());
+/// render.set_priority(1000);
+/// let background = thread::spawn(move || background.swap_with(producer_id, &mut 1));
+/// # while set.waiting().len() < 1 { thread::yield_now(); }
+/// let render = thread::spawn(move || render.swap_with(producer_id, &mut 2));
+/// # while set.waiting().len() < 2 { thread::yield_now(); }
+/// // The render thread arrived later, but has a higher priority, so is paired first.
+/// let mut buffer = 3;
+/// assert_eq!(producer.swap_any(&mut buffer), Ok(render_id));
+/// assert_eq!(buffer, 2);
+/// # drop(producer);
+/// # render.join().unwrap().unwrap();
+/// # background.join().unwrap().unwrap_err();
+/// ```
+///
+/// ```rust
+/// # use std::thread;
+/// # use swapper::SwapperSet;
+/// let set = SwapperSet::new();
+/// let a = set.register();
+/// let b = set.register();
+/// let a_id = a.id();
+/// let helper = thread::spawn(move || {
+///     let mut hello = String::from("hello");
+///     assert_eq!(b.swap_any(&mut hello), Ok(a_id));
+///     assert_eq!(hello, "world");
+/// });
+/// let mut world = String::from("world");
+/// a.swap_any(&mut world).unwrap();
+/// assert_eq!(world, "hello");
+/// # helper.join().unwrap();
+/// ```
+///
+/// On a machine with several NUMA nodes, swapping between threads on different nodes is
+/// slower, so members can register with the node they run on, using `register_on`.
+/// The set's `PairingPolicy` then decides which waiting members a member arriving to
+/// swap with any member can pair with, for example `PreferSameNode`:
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use swapper::{PreferSameNode, SwapperSet};
+/// let set = SwapperSet::with_policy(PreferSameNode::new(Duration::from_micros(50)));
+/// let member = set.register_on(1);
+/// # member.swap_any(&mut ()).unwrap_err();
+/// assert_eq!(member.node(), Some(1));
+/// ```
+pub struct SwapperSet<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// A member of a `SwapperSet`.
+///
+/// Dropping a member removes it from the set, and any swaps waiting specifically for it
+/// fail with `SwapError::Disconnected`, as do swaps waiting for any member if only
+/// the waiting member is left.
+pub struct SetMember<T> {
+    inner: Arc<Inner<T>>,
+    id: u64,
+    node: Option<usize>,
+    priority: u32,
+}
+
+/// Decides which members of a `SwapperSet` can pair up when swapping with any member.
+///
+/// Members swapping with a specific member are always paired, regardless of the policy.
+pub trait PairingPolicy: Send + Sync {
+    /// Can members on these nodes pair, once one of them has waited this long?
+    ///
+    /// A node is `None` for a member which registered without one.
+    fn accept(&self, ours: Option<usize>, theirs: Option<usize>, waited: Duration) -> bool;
+
+    /// How long a waiting member waits before checking again whether it can pair,
+    /// if acceptance depends on how long members have waited.
+    fn recheck(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The default pairing policy, which pairs any members, in arrival order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnyNode;
+
+impl PairingPolicy for AnyNode {
+    fn accept(&self, _: Option<usize>, _: Option<usize>, _: Duration) -> bool {
+        true
+    }
+}
+
+/// A pairing policy which prefers pairing members on the same node.
+///
+/// Members on different nodes only pair once one of them has waited for the fallback
+/// duration, so a waiting member is not starved if no one on its node arrives.
+/// Members without a node can pair with anyone.
+#[derive(Clone, Copy, Debug)]
+pub struct PreferSameNode {
+    fallback: Duration,
+}
+
+impl PreferSameNode {
+    /// Prefer members on the same node, falling back to any node after the given wait.
+    pub fn new(fallback: Duration) -> PreferSameNode {
+        PreferSameNode { fallback }
+    }
+}
+
+impl PairingPolicy for PreferSameNode {
+    fn accept(&self, ours: Option<usize>, theirs: Option<usize>, waited: Duration) -> bool {
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) => ours == theirs || waited >= self.fallback,
+            _ => true,
+        }
+    }
+
+    fn recheck(&self) -> Option<Duration> {
+        Some(self.fallback)
+    }
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    condvar: Condvar,
+    policy: Box<dyn PairingPolicy>,
+}
+
+struct State<T> {
+    next_id: u64,
+    next_ticket: u64,
+    // How long a member waits for its priority to rise by one, if priorities rise.
+    aging: Option<Duration>,
+    shutdown: bool,
+    members: Vec<u64>,
+    waiting: Vec<Waiting<T>>,
+}
+
+/// A member blocked waiting to swap.
+struct Waiting<T> {
+    ticket: u64,
+    member: u64,
+    node: Option<usize>,
+    since: Instant,
+    priority: u32,
+    // The member it is waiting to swap with, or `None` for any member.
+    partner: Option<u64>,
+    // The data lives on the stack of the waiting thread, which does not access it
+    // until the outcome is set.
+    data: NonNull<T>,
+    // Set by the thread that completes or cancels the swap, to the id of the partner.
+    outcome: Option<Result<u64, SwapError>>,
+}
+
+impl<T> Waiting<T> {
+    fn matches(&self, member: u64, partner: Option<u64>) -> bool {
+        self.outcome.is_none()
+            && self.member != member
+            && self.partner.is_none_or(|id| id == member)
+            && partner.is_none_or(|id| id == self.member)
+    }
+
+    // The priority, raised by one for each aging interval the member has waited.
+    fn effective_priority(&self, now: Instant, aging: Option<Duration>) -> u64 {
+        let aged = match aging {
+            Some(aging) if !aging.is_zero() => ((now - self.since).as_nanos() / aging.as_nanos()) as u64,
+            _ => 0,
+        };
+        u64::from(self.priority).saturating_add(aged)
+    }
+}
+
+impl<T> SwapperSet<T> {
+    /// Create a new set, with no members, which pairs any members.
+    pub fn new() -> SwapperSet<T> {
+        SwapperSet::with_policy(AnyNode)
+    }
+
+    /// Create a new set, with no members, which pairs members according to the policy.
+    pub fn with_policy<P: PairingPolicy + 'static>(policy: P) -> SwapperSet<T> {
+        SwapperSet {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    next_id: 0,
+                    next_ticket: 0,
+                    aging: Some(Duration::from_millis(10)),
+                    shutdown: false,
+                    members: Vec::new(),
+                    waiting: Vec::new(),
+                }),
+                condvar: Condvar::new(),
+                policy: Box::new(policy),
+            }),
+        }
+    }
+
+    /// Join the set, returning a new member.
+    pub fn register(&self) -> SetMember<T> {
+        self.join(None)
+    }
+
+    /// Join the set from the given NUMA node, returning a new member.
+    pub fn register_on(&self, node: usize) -> SetMember<T> {
+        self.join(Some(node))
+    }
+
+    fn join(&self, node: Option<usize>) -> SetMember<T> {
+        let mut state = self.inner.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.members.push(id);
+        SetMember {
+            inner: self.inner.clone(),
+            id,
+            node,
+            priority: 0,
+        }
+    }
+
+    /// The ids of the current members, in order of registration.
+    pub fn members(&self) -> Vec<u64> {
+        self.inner.lock().members.clone()
+    }
+
+    /// Set how long a waiting member waits for its priority to rise by one,
+    /// or `None` for priorities not to rise, so low priority members can be starved.
+    pub fn set_priority_aging(&self, aging: Option<Duration>) {
+        self.inner.lock().aging = aging;
+    }
+
+    /// Shut down the set, so every member waiting to swap, and every later swap,
+    /// fails with `SwapError::Shutdown`.
+    ///
+    /// This unblocks every member at once, so a pool of threads swapping using the set
+    /// can be torn down without dropping members in any particular order.
+    ///
+    /// ```rust
+    /// # use std::thread;
+    /// # use swapper::{SwapError, SwapperSet};
+    /// let set = SwapperSet::new();
+    /// let (a, b) = (set.register(), set.register());
+    /// let b_id = b.id();
+    /// let helper = thread::spawn(move || a.swap_with(b_id, &mut 1));
+    /// # while set.waiting().is_empty() { thread::yield_now(); }
+    /// set.shutdown();
+    /// assert_eq!(helper.join().unwrap(), Err(SwapError::Shutdown));
+    /// assert_eq!(b.swap_any(&mut 2), Err(SwapError::Shutdown));
+    /// ```
+    pub fn shutdown(&self) {
+        let mut state = self.inner.lock();
+        state.shutdown = true;
+        for waiting in &mut state.waiting {
+            if waiting.outcome.is_none() {
+                waiting.outcome = Some(Err(SwapError::Shutdown));
+            }
+        }
+        self.inner.condvar.notify_all();
+    }
+
+    /// Has the set been shut down?
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.lock().shutdown
+    }
+
+    /// The ids of the members blocked waiting to swap, in order of arrival.
+    pub fn waiting(&self) -> Vec<u64> {
+        let state = self.inner.lock();
+        state
+            .waiting
+            .iter()
+            .filter(|waiting| waiting.outcome.is_none())
+            .map(|waiting| waiting.member)
+            .collect()
+    }
+}
+
+impl<T> Default for SwapperSet<T> {
+    fn default() -> SwapperSet<T> {
+        SwapperSet::new()
+    }
+}
+
+impl<T> Clone for SwapperSet<T> {
+    fn clone(&self) -> SwapperSet<T> {
+        SwapperSet {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Inner<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl<T: Send> SetMember<T> {
+    /// Swap data with the member with the given id.
+    ///
+    /// This blocks until that member swaps with us, or with any member.
+    /// If it is not in the set, or leaves while we are waiting, this returns
+    /// `SwapError::Disconnected`, and if the set is shut down, `SwapError::Shutdown`.
+    pub fn swap_with(&self, partner: u64, our_ref: &mut T) -> Result<(), SwapError> {
+        self.exchange(Some(partner), our_ref, None).map(|_| ())
+    }
+
+    /// Swap data with any other member, returning the id of the member we swapped with.
+    ///
+    /// This blocks until another member swaps with us, or with any member.
+    /// If we are the only member, this returns `SwapError::Disconnected`.
+    pub fn swap_any(&self, our_ref: &mut T) -> Result<u64, SwapError> {
+        self.exchange(None, our_ref, None)
+    }
+
+    /// Swap data with any other member, giving up if none swaps with us within the timeout.
+    ///
+    /// On timeout we are no longer waiting, and the error reports `PartnerState::NeverArrived`.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use swapper::{PartnerState, SwapError, SwapperSet};
+    /// let set = SwapperSet::new();
+    /// let (a, _b) = (set.register(), set.register());
+    /// let partner = PartnerState::NeverArrived;
+    /// assert_eq!(a.swap_any_timeout(&mut 1, Duration::from_millis(10)), Err(SwapError::Timeout { partner }));
+    /// assert!(set.waiting().is_empty());
+    /// ```
+    pub fn swap_any_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<u64, SwapError> {
+        self.exchange(None, our_ref, Some(Instant::now() + timeout))
+    }
+
+    fn exchange(&self, partner: Option<u64>, our_ref: &mut T, deadline: Option<Instant>) -> Result<u64, SwapError> {
+        let mut state = self.inner.lock();
+        if state.shutdown {
+            return Err(SwapError::Shutdown);
+        }
+        if let Some(partner) = partner {
+            if partner == self.id {
+                return Err(SwapError::WouldDeadlock);
+            }
+            if !state.members.contains(&partner) {
+                return Err(SwapError::Disconnected);
+            }
+        } else if state.members.len() < 2 {
+            return Err(SwapError::Disconnected);
+        }
+        // Is a matching member blocked waiting to swap? If so, swap with the one with the
+        // highest priority, or which has waited longest, and unblock it.
+        let since = Instant::now();
+        if let Some(index) = self.find_partner(&state, partner, since) {
+            let their_id = self.complete(&mut state.waiting[index], NonNull::from(our_ref));
+            return Ok(their_id);
+        }
+        // Otherwise, block waiting for a matching member.
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push(Waiting {
+            ticket,
+            member: self.id,
+            node: self.node,
+            since,
+            priority: self.priority,
+            partner,
+            data: NonNull::from(our_ref),
+            outcome: None,
+        });
+        loop {
+            let index = state.waiting.iter().position(|waiting| waiting.ticket == ticket).unwrap();
+            if let Some(outcome) = state.waiting[index].outcome {
+                state.waiting.remove(index);
+                return outcome;
+            }
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                // No one has swapped with us, so we are still waiting.
+                state.waiting.remove(index);
+                return Err(SwapError::Timeout {
+                    partner: PartnerState::NeverArrived,
+                });
+            }
+            let recheck = self.inner.policy.recheck();
+            if recheck.is_some() {
+                // We may have waited long enough to pair with a member we could not before.
+                let ours = state.waiting.remove(index);
+                if let Some(theirs) = self.find_partner(&state, partner, ours.since) {
+                    let their_id = self.complete(&mut state.waiting[theirs], ours.data);
+                    return Ok(their_id);
+                }
+                state.waiting.insert(index, ours);
+            }
+            let timeout = match (recheck, deadline) {
+                (None, None) => None,
+                (Some(recheck), None) => Some(recheck),
+                (None, Some(deadline)) => Some(deadline - now),
+                (Some(recheck), Some(deadline)) => Some(recheck.min(deadline - now)),
+            };
+            state = match timeout {
+                None => self.inner.condvar.wait(state).unwrap(),
+                Some(timeout) => self.inner.condvar.wait_timeout(state, timeout).unwrap().0,
+            };
+        }
+    }
+
+    // The index of the member we can pair with which has the highest priority, or has
+    // waited longest, if any. Waiting members are kept in arrival order.
+    fn find_partner(&self, state: &State<T>, partner: Option<u64>, since: Instant) -> Option<usize> {
+        let now = Instant::now();
+        (state.waiting.iter().enumerate())
+            .filter(|&(_, waiting)| {
+                waiting.matches(self.id, partner)
+                    && (partner.is_some()
+                        || waiting.partner.is_some()
+                        || self.inner.policy.accept(self.node, waiting.node, now - since.min(waiting.since)))
+            })
+            .max_by_key(|&(index, waiting)| (waiting.effective_priority(now, state.aging), Reverse(index)))
+            .map(|(index, _)| index)
+    }
+
+    // Swap with a waiting member, and unblock it.
+    fn complete(&self, waiting: &mut Waiting<T>, our_ptr: NonNull<T>) -> u64 {
+        // The waiting thread does not access its data until its outcome is set.
+        unsafe { ptr::swap_nonoverlapping(our_ptr.as_ptr(), waiting.data.as_ptr(), 1) };
+        waiting.outcome = Some(Ok(self.id));
+        self.inner.condvar.notify_all();
+        waiting.member
+    }
+}
+
+impl<T> SetMember<T> {
+    /// The id of this member, which is unique within its set.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The NUMA node this member registered on, if any.
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+
+    /// The priority of this member's swaps, which is 0 unless set.
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// Set the priority of this member's swaps.
+    ///
+    /// When another member arrives to swap, it pairs with the waiting member with the highest
+    /// priority, out of those it can pair with. If several have the same priority, the one
+    /// which has waited longest is paired first.
+    pub fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    /// Leave the set. This is the same as dropping the member.
+    pub fn deregister(self) {}
+}
+
+impl<T> Drop for SetMember<T> {
+    fn drop(&mut self) {
+        let mut state = self.inner.lock();
+        state.members.retain(|&id| id != self.id);
+        // Cancel any swaps waiting specifically for us, or for anyone if no one else is left.
+        let alone = state.members.len() < 2;
+        for waiting in &mut state.waiting {
+            if waiting.outcome.is_none() && (waiting.partner == Some(self.id) || alone) {
+                waiting.outcome = Some(Err(SwapError::Disconnected));
+            }
+        }
+        self.inner.condvar.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for SwapperSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapperSet")
+            .field("members", &self.members())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for SetMember<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SetMember")
+            .field("id", &self.id)
+            .field("node", &self.node)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+// The raw pointers are only dereferenced while the lock is held, and the waiting thread is blocked.
+unsafe impl<T: Send> Send for SwapperSet<T> {}
+unsafe impl<T: Send> Sync for SwapperSet<T> {}
+unsafe impl<T: Send> Send for SetMember<T> {}
+unsafe impl<T: Send> Sync for SetMember<T> {}
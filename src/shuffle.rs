@@ -0,0 +1,194 @@
+//! Swapping between randomly chosen pairs of a group of threads.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+use SwapError;
+
+/// One participant in a shuffle exchange between a group of threads.
+///
+/// Each round, every participant calls `shuffle`, and once they have all arrived,
+/// they are paired up at random and each pair swaps. If there are an odd number
+/// of participants, one of them is left out of each round, and keeps its data.
+///
+/// ```rust
+/// # use std::thread;
+/// let helpers: Vec<_> = swapper::shuffle(4).into_iter().map(|mut participant| thread::spawn(move || {
+///     let mut queue = vec![participant.index()];
+///     let partner = participant.shuffle(&mut queue).unwrap().unwrap();
+///     assert_eq!(queue, [partner]);
+/// })).collect();
+/// # for helper in helpers { helper.join().unwrap(); }
+/// ```
+pub struct ShuffleExchange<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    condvar: Condvar,
+}
+
+struct State<T> {
+    round: u64,
+    // The data offered by each participant which has arrived in this round.
+    // It lives on the stack of the participant, which is blocked until the round completes.
+    arrived: Vec<Option<NonNull<T>>>,
+    count: usize,
+    // The partner of each participant in the last completed round.
+    partners: Vec<Option<usize>>,
+    rng: u64,
+    disconnected: bool,
+    shutdown: bool,
+}
+
+/// Create a shuffle exchange between the given number of participants, seeded at random.
+pub fn shuffle<T>(participants: usize) -> Vec<ShuffleExchange<T>> {
+    shuffle_seeded(participants, RandomState::new().build_hasher().finish())
+}
+
+/// Create a shuffle exchange between the given number of participants, with a fixed seed.
+///
+/// Exchanges with the same seed and number of participants pair them up in the same
+/// sequence of matchings, regardless of the order they arrive in.
+pub fn shuffle_seeded<T>(participants: usize, seed: u64) -> Vec<ShuffleExchange<T>> {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            round: 0,
+            arrived: vec![None; participants],
+            count: 0,
+            partners: vec![None; participants],
+            rng: seed,
+            disconnected: false,
+            shutdown: false,
+        }),
+        condvar: Condvar::new(),
+    });
+    (0..participants)
+        .map(|index| ShuffleExchange {
+            shared: shared.clone(),
+            index,
+        })
+        .collect()
+}
+
+impl<T> State<T> {
+    // The splitmix64 generator, which is small and good enough for choosing matchings.
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Every participant has arrived, so pair them up at random, and swap each pair.
+    fn complete(&mut self) {
+        let mut order: Vec<usize> = (0..self.arrived.len()).collect();
+        for i in (1..order.len()).rev() {
+            let j = (self.next_random() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+        self.partners.iter_mut().for_each(|partner| *partner = None);
+        for pair in order.chunks_exact(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let (a_ptr, b_ptr) = (self.arrived[a].unwrap(), self.arrived[b].unwrap());
+            unsafe { ptr::swap_nonoverlapping(a_ptr.as_ptr(), b_ptr.as_ptr(), 1) };
+            self.partners[a] = Some(b);
+            self.partners[b] = Some(a);
+        }
+        self.arrived.iter_mut().for_each(|arrived| *arrived = None);
+        self.count = 0;
+        self.round += 1;
+    }
+}
+
+impl<T: Send> ShuffleExchange<T> {
+    /// Take part in the current round, returning the index of the participant we swapped with.
+    ///
+    /// This blocks until every participant has arrived. If we are left out of the round,
+    /// this returns `None`, and our data is unchanged. If any participant has been dropped,
+    /// the round can never complete, so this returns `SwapError::Disconnected`, and if
+    /// the exchange has been shut down, this returns `SwapError::Shutdown`.
+    pub fn shuffle(&mut self, our_ref: &mut T) -> Result<Option<usize>, SwapError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.shutdown {
+            return Err(SwapError::Shutdown);
+        }
+        if state.disconnected {
+            return Err(SwapError::Disconnected);
+        }
+        let round = state.round;
+        state.arrived[self.index] = Some(NonNull::from(our_ref));
+        state.count += 1;
+        if state.count == state.arrived.len() {
+            state.complete();
+            self.shared.condvar.notify_all();
+            return Ok(state.partners[self.index]);
+        }
+        while state.round == round {
+            if state.shutdown || state.disconnected {
+                // Retract our data, since the round will never complete.
+                state.arrived[self.index] = None;
+                state.count -= 1;
+                if state.shutdown {
+                    return Err(SwapError::Shutdown);
+                }
+                return Err(SwapError::Disconnected);
+            }
+            state = self.shared.condvar.wait(state).unwrap();
+        }
+        Ok(state.partners[self.index])
+    }
+}
+
+impl<T> ShuffleExchange<T> {
+    /// The index of this participant, from 0 to the number of participants.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The number of participants.
+    pub fn participants(&self) -> usize {
+        self.shared.state.lock().unwrap().arrived.len()
+    }
+
+    /// Shut down the exchange for every participant, so every participant waiting for
+    /// the round to complete, and every later round, fails with `SwapError::Shutdown`.
+    pub fn shutdown(&self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.condvar.notify_all();
+    }
+
+    /// The number of rounds which have completed.
+    pub fn round(&self) -> u64 {
+        self.shared.state.lock().unwrap().round
+    }
+}
+
+impl<T> Drop for ShuffleExchange<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().disconnected = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for ShuffleExchange<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShuffleExchange")
+            .field("index", &self.index)
+            .field("round", &self.round())
+            .finish()
+    }
+}
+
+// The raw pointers are only dereferenced while the lock is held, and their owners are blocked.
+unsafe impl<T: Send> Send for ShuffleExchange<T> {}
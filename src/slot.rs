@@ -0,0 +1,63 @@
+//! The offer slot at the heart of the swap protocol, exposed with types as `raw::SwapSlot`.
+//!
+//! A thread offers to swap by publishing a pointer to its offer in the slot, then blocks.
+//! The other thread takes the offer, swaps the data it points to, and unblocks it.
+//! If the offer is not taken, the offering thread can retract it.
+//!
+//! Pointers are only ever derived from references and compared, never converted to or
+//! from integers, so the protocol is compatible with strict provenance.
+
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::atomic::Ordering;
+
+use sync::AtomicPtr;
+
+/// A slot holding at most one offer.
+///
+/// The slot is type-erased, so that it can be shared by code that does not know the type
+/// of the data being swapped. It is up to the owner of the slot to ensure that offers
+/// are only ever taken at the type they were made at.
+pub(crate) struct Slot {
+    offer: AtomicPtr<()>,
+}
+
+impl Slot {
+    pub(crate) fn new() -> Slot {
+        Slot {
+            offer: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Is there currently no offer in the slot?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.offer.load(Ordering::Acquire).is_null()
+    }
+
+    /// Publish an offer, if the slot is empty.
+    ///
+    /// Once the offer is published, the offering thread must not access the data
+    /// until the offer has been taken and completed, or successfully retracted.
+    pub(crate) fn offer<T>(&self, ptr: NonNull<T>) -> bool {
+        self.offer
+            .compare_exchange(ptr::null_mut(), ptr.as_ptr().cast(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Take the offer in the slot, if there is one, leaving the slot empty.
+    ///
+    /// The taker has exclusive access to the data until it completes the offer.
+    pub(crate) fn take<T>(&self) -> Option<NonNull<T>> {
+        NonNull::new(self.offer.swap(ptr::null_mut(), Ordering::AcqRel).cast())
+    }
+
+    /// Retract an offer, returning whether it was still in the slot.
+    ///
+    /// If this returns `false`, the offer has been taken, and the offering thread
+    /// must wait for it to be completed.
+    pub(crate) fn retract<T>(&self, ptr: NonNull<T>) -> bool {
+        self.offer
+            .compare_exchange(ptr.as_ptr().cast(), ptr::null_mut(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
@@ -0,0 +1,15 @@
+//! The synchronization primitives used by the swap protocol.
+//!
+//! In `cfg(loom)` builds these are replaced by loom's models, so that the interleavings
+//! of the protocol can be checked exhaustively. Only the primitives the protocol relies on
+//! for its correctness are modelled; reference counting and the debug registry always use `std`.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::AtomicPtr;
+#[cfg(loom)]
+pub(crate) use loom::sync::mpsc;
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::AtomicPtr;
+#[cfg(not(any(loom, feature = "parking-lot", all(target_arch = "wasm32", target_feature = "atomics"))))]
+pub(crate) use std::sync::mpsc;
@@ -0,0 +1,251 @@
+//! A deterministic, single-threaded stand-in for a swap pair, for testing code which swaps.
+//!
+//! Testing a protocol built on `Swapper` normally means spawning a thread for the other half,
+//! and the outcome then depends on how the threads are scheduled. Instead, the code under
+//! test can be given a `MockSwapper`, with the same swapping methods as `Swapper`, while the
+//! test plays the other half using the matching `MockPartner`. The test schedules what the
+//! other half does, and each swap completes immediately, against the next scheduled event.
+//!
+//! Timeouts use virtual time, which only moves when a swap waits for a scheduled event,
+//! or when the test calls `advance` or `step`, so tests of timeouts do not sleep.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swapper::{PartnerState, SwapError};
+//! # use swapper::testing::{MockSwapper, mock_swapper};
+//! // The code under test, which swaps in its state, retrying if the other half is slow.
+//! fn exchange(half: &MockSwapper<u32>, state: &mut u32) -> Result<(), SwapError> {
+//!     let timeout = Duration::from_millis(10);
+//!     half.swap_timeout(state, timeout).or_else(|_| half.swap_timeout(state, timeout))
+//! }
+//! let (ours, partner) = mock_swapper();
+//! partner.complete_swap_after(Duration::from_millis(15), 2);
+//! let mut state = 1;
+//! exchange(&ours, &mut state).unwrap();
+//! assert_eq!(state, 2);
+//! assert_eq!(partner.received(), Some(1));
+//! assert_eq!(partner.now(), Duration::from_millis(15));
+//! // Nothing else is scheduled, so both attempts time out.
+//! let partner_state = PartnerState::NeverArrived;
+//! assert_eq!(exchange(&ours, &mut state), Err(SwapError::Timeout { partner: partner_state }));
+//! assert_eq!(partner.now(), Duration::from_millis(35));
+//! ```
+//!
+//! The mocks are not `Send`, since everything happens on the test's thread.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use Exchange;
+use PartnerState;
+use SwapError;
+
+/// The half of a mock swap pair which is given to the code under test.
+pub struct MockSwapper<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+/// The half of a mock swap pair which is controlled by the test.
+pub struct MockPartner<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+struct State<T> {
+    // The virtual time since the pair was created.
+    now: Duration,
+    // What the other half does next, and when.
+    scheduled: VecDeque<(Duration, Event<T>)>,
+    // The values the code under test traded in, oldest first.
+    received: VecDeque<T>,
+    generation: u64,
+    disconnected: bool,
+}
+
+enum Event<T> {
+    Swap(T),
+    Fail(SwapError),
+}
+
+/// Create a new mock swap pair, at virtual time zero.
+pub fn mock_swapper<T>() -> (MockSwapper<T>, MockPartner<T>) {
+    let state = Rc::new(RefCell::new(State {
+        now: Duration::from_secs(0),
+        scheduled: VecDeque::new(),
+        received: VecDeque::new(),
+        generation: 0,
+        disconnected: false,
+    }));
+    (MockSwapper { state: state.clone() }, MockPartner { state })
+}
+
+impl<T> MockSwapper<T> {
+    /// Swap data with the next scheduled swap, as `Swapper::swap` does.
+    ///
+    /// If the next swap is scheduled in the future, virtual time advances to it.
+    /// If the partner has disconnected with nothing scheduled, this returns
+    /// `SwapError::Disconnected`.
+    ///
+    /// # Panics
+    ///
+    /// If nothing is scheduled, and the partner has not disconnected, since a real swap
+    /// would block forever.
+    pub fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        let mut state = self.state.borrow_mut();
+        if state.scheduled.is_empty() && !state.disconnected {
+            panic!("MockSwapper::swap would block forever, since no swap is scheduled");
+        }
+        state.rendezvous(our_ref, None)
+    }
+
+    /// Swap data with the next scheduled swap, giving up after a timeout in virtual time,
+    /// as `Swapper::swap_timeout` does.
+    ///
+    /// On timeout, virtual time advances by the timeout. A timeout which would overflow
+    /// virtual time, such as `Duration::MAX`, never expires, so this behaves as `swap`.
+    pub fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        let deadline = self.state.borrow().now.checked_add(timeout);
+        match deadline {
+            None => self.swap(our_ref),
+            Some(deadline) => self.state.borrow_mut().rendezvous(our_ref, Some(deadline)),
+        }
+    }
+
+    /// The number of swaps this pair has completed, as `Swapper::current_gen` reports.
+    pub fn current_gen(&self) -> u64 {
+        self.state.borrow().generation
+    }
+}
+
+impl<T> Exchange<T> for MockSwapper<T> {
+    fn swap(&self, our_ref: &mut T) -> Result<(), SwapError> {
+        MockSwapper::swap(self, our_ref)
+    }
+
+    fn swap_timeout(&self, our_ref: &mut T, timeout: Duration) -> Result<(), SwapError> {
+        MockSwapper::swap_timeout(self, our_ref, timeout)
+    }
+}
+
+impl<T> MockPartner<T> {
+    /// Schedule a swap, which trades in `value` for the next swap by the code under test.
+    pub fn complete_swap(&self, value: T) {
+        self.complete_swap_after(Duration::from_secs(0), value)
+    }
+
+    /// Schedule a swap, which only arrives once `delay` has passed in virtual time.
+    ///
+    /// The delay is measured from the later of now, and the previously scheduled event.
+    pub fn complete_swap_after(&self, delay: Duration, value: T) {
+        self.state.borrow_mut().schedule(delay, Event::Swap(value))
+    }
+
+    /// Schedule the next swap by the code under test to fail with the error.
+    pub fn fail_swap(&self, err: SwapError) {
+        self.state.borrow_mut().schedule(Duration::from_secs(0), Event::Fail(err))
+    }
+
+    /// Disconnect, as if the other half had been dropped.
+    ///
+    /// Swaps which were already scheduled still complete, after which swaps
+    /// return `SwapError::Disconnected`.
+    pub fn disconnect(&self) {
+        self.state.borrow_mut().disconnected = true;
+    }
+
+    /// Take the oldest value traded in by the code under test, if any.
+    pub fn received(&self) -> Option<T> {
+        self.state.borrow_mut().received.pop_front()
+    }
+
+    /// The number of scheduled events which have not happened yet.
+    pub fn scheduled(&self) -> usize {
+        self.state.borrow().scheduled.len()
+    }
+
+    /// The virtual time since the pair was created.
+    pub fn now(&self) -> Duration {
+        self.state.borrow().now
+    }
+
+    /// Advance virtual time.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        state.now = state.now.saturating_add(duration);
+    }
+
+    /// Advance virtual time to the next scheduled event, if there is one.
+    ///
+    /// Returns whether there was a scheduled event.
+    pub fn step(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        match state.scheduled.front() {
+            Some(&(at, _)) => {
+                state.now = state.now.max(at);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> State<T> {
+    fn schedule(&mut self, delay: Duration, event: Event<T>) {
+        let after = self.scheduled.back().map_or(self.now, |&(at, _)| at.max(self.now));
+        // A delay past the end of virtual time is as good as forever.
+        self.scheduled.push_back((after.saturating_add(delay), event));
+    }
+
+    fn rendezvous(&mut self, our_ref: &mut T, deadline: Option<Duration>) -> Result<(), SwapError> {
+        let at = match self.scheduled.front() {
+            Some(&(at, _)) => at,
+            None if self.disconnected => return Err(SwapError::Disconnected),
+            None => Duration::MAX,
+        };
+        if let Some(deadline) = deadline {
+            if deadline < at {
+                self.now = self.now.max(deadline);
+                let partner = if self.disconnected {
+                    PartnerState::Disconnected
+                } else {
+                    PartnerState::NeverArrived
+                };
+                return Err(SwapError::Timeout { partner });
+            }
+        }
+        self.now = self.now.max(at);
+        match self.scheduled.pop_front().map(|(_, event)| event) {
+            Some(Event::Swap(value)) => {
+                self.received.push_back(mem::replace(our_ref, value));
+                self.generation += 1;
+                Ok(())
+            }
+            Some(Event::Fail(err)) => Err(err),
+            None => Err(SwapError::Disconnected),
+        }
+    }
+}
+
+impl<T> fmt::Debug for MockSwapper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("MockSwapper")
+            .field("now", &state.now)
+            .field("generation", &state.generation)
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for MockPartner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("MockPartner")
+            .field("now", &state.now)
+            .field("scheduled", &state.scheduled.len())
+            .field("disconnected", &state.disconnected)
+            .finish()
+    }
+}
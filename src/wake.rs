@@ -0,0 +1,305 @@
+//! Blocking a thread until it is woken by the other half of its pair.
+//!
+//! Each half of a pair has a `Waiter`, and the other half has the matching `Waker`.
+//! Wakes are counted, so a wake that arrives before the wait is not lost.
+//! If either end is dropped, the other end reports `SwapError::Disconnected`.
+//! A waiter can also poll for a wake without blocking, using `try_wait`,
+//! or block until a deadline, using `wait_until`.
+//!
+//! By default this is implemented using a channel. Otherwise, it is implemented with a
+//! counter word shared by the two ends, on which the waiter blocks:
+//!
+//! * On `wasm32` with the `atomics` feature, using `memory.atomic.wait32` and
+//!   `memory.atomic.notify`. Since the main thread of a web page cannot block,
+//!   it can opt out of blocking using `set_blocking_allowed(false)`, in which case it
+//!   polls instead.
+//! * With the `parking-lot` feature, using `parking_lot_core::park` keyed on the word's
+//!   address, which avoids allocating a channel per pair.
+//!
+//! An async task waiting for a swap cannot block, so it also registers a `TaskWaker`,
+//! which is woken after the other half has woken its `Waiter`.
+//!
+//! Loom does not implement timed waits, so under `cfg(loom)` a wait with a deadline
+//! polls the channel instead.
+//!
+//! A backend is an `imp` module providing `channel`, `Waker::wake`, and `Waiter::wait`,
+//! `try_wait` and `wait_until`, with wakes counted and dropped ends reported as above.
+//! The counter word backend only needs a `sys` module which blocks while the word is
+//! unchanged, and wakes the threads blocked on it, so can be ported to other primitives.
+
+use std::sync::Mutex;
+use std::task;
+
+pub(crate) use self::imp::channel;
+pub(crate) use self::imp::Waiter;
+pub(crate) use self::imp::Waker;
+
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+pub use self::imp::set_blocking_allowed;
+
+/// The most recently registered waker of an async task.
+pub(crate) struct TaskWaker(Mutex<Option<task::Waker>>);
+
+impl TaskWaker {
+    pub(crate) fn new() -> TaskWaker {
+        TaskWaker(Mutex::new(None))
+    }
+
+    /// Register the waker of the task, which must then check its `Waiter` before suspending.
+    pub(crate) fn register(&self, waker: &task::Waker) {
+        let mut registered = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        match *registered {
+            Some(ref old) if old.will_wake(waker) => (),
+            _ => *registered = Some(waker.clone()),
+        }
+    }
+
+    pub(crate) fn wake(&self) {
+        let registered = self.0.lock().unwrap_or_else(|err| err.into_inner()).take();
+        if let Some(waker) = registered {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(target_arch = "wasm32", target_feature = "atomics"),
+    all(feature = "parking-lot", not(loom))
+)))]
+mod imp {
+    #[cfg(not(loom))]
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::sync::mpsc::TryRecvError;
+    use std::time::Instant;
+
+    use SwapError;
+    use sync::mpsc;
+    use sync::mpsc::Receiver;
+    use sync::mpsc::Sender;
+
+    pub(crate) struct Waker(Sender<()>);
+
+    pub(crate) struct Waiter(Receiver<()>);
+
+    pub(crate) fn channel() -> (Waker, Waiter) {
+        let (sender, receiver) = mpsc::channel();
+        (Waker(sender), Waiter(receiver))
+    }
+
+    impl Waker {
+        pub(crate) fn wake(&self) -> Result<(), SwapError> {
+            self.0.send(()).map_err(SwapError::from)
+        }
+    }
+
+    impl Waiter {
+        pub(crate) fn wait(&self) -> Result<(), SwapError> {
+            self.0.recv().map_err(SwapError::from)
+        }
+
+        pub(crate) fn try_wait(&self) -> Result<bool, SwapError> {
+            match self.0.try_recv() {
+                Ok(()) => Ok(true),
+                Err(TryRecvError::Empty) => Ok(false),
+                Err(TryRecvError::Disconnected) => Err(SwapError::Disconnected),
+            }
+        }
+
+        #[cfg(not(loom))]
+        pub(crate) fn wait_until(&self, deadline: Instant) -> Result<bool, SwapError> {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.0.recv_timeout(timeout) {
+                Ok(()) => Ok(true),
+                Err(RecvTimeoutError::Timeout) => Ok(false),
+                Err(RecvTimeoutError::Disconnected) => Err(SwapError::Disconnected),
+            }
+        }
+
+        /// Loom does not model time, and a deadline may pass at any point, so under loom
+        /// it passes once the other threads have had a chance to run, which is deterministic,
+        /// and lets the model check a wake racing with the retraction of a timed-out offer.
+        #[cfg(loom)]
+        pub(crate) fn wait_until(&self, _deadline: Instant) -> Result<bool, SwapError> {
+            if self.try_wait()? {
+                return Ok(true);
+            }
+            loom::thread::yield_now();
+            self.try_wait()
+        }
+    }
+}
+
+#[cfg(any(
+    all(target_arch = "wasm32", target_feature = "atomics"),
+    all(feature = "parking-lot", not(loom))
+))]
+mod imp {
+    use std::cell::Cell;
+    use std::hint;
+    use std::marker::PhantomData;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+    use std::time::Instant;
+
+    use SwapError;
+    use padded::CachePadded;
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+    pub use self::sys::set_blocking_allowed;
+
+    // The low bits of the word count the pending wakes, and the high bits record dropped ends.
+    const WAKER_DROPPED: u32 = 1 << 31;
+    const WAITER_DROPPED: u32 = 1 << 30;
+    const PENDING: u32 = WAITER_DROPPED - 1;
+
+    // The word is waited on by one thread while the other spins or wakes it, so is kept on its own cache line.
+    pub(crate) struct Waker(Arc<CachePadded<AtomicU32>>);
+
+    // Only one thread may wait at a time, so like a channel's receiver, a waiter is not `Sync`.
+    pub(crate) struct Waiter(Arc<CachePadded<AtomicU32>>, PhantomData<Cell<()>>);
+
+    pub(crate) fn channel() -> (Waker, Waiter) {
+        let word = Arc::new(CachePadded::new(AtomicU32::new(0)));
+        (Waker(word.clone()), Waiter(word, PhantomData))
+    }
+
+    impl Waker {
+        pub(crate) fn wake(&self) -> Result<(), SwapError> {
+            if self.0.fetch_add(1, Ordering::AcqRel) & WAITER_DROPPED != 0 {
+                return Err(SwapError::Disconnected);
+            }
+            sys::notify(&self.0);
+            Ok(())
+        }
+    }
+
+    impl Drop for Waker {
+        fn drop(&mut self) {
+            self.0.fetch_or(WAKER_DROPPED, Ordering::AcqRel);
+            sys::notify(&self.0);
+        }
+    }
+
+    impl Waiter {
+        pub(crate) fn wait(&self) -> Result<(), SwapError> {
+            loop {
+                let word = self.0.load(Ordering::Acquire);
+                if word & PENDING != 0 {
+                    if self.0.compare_exchange(word, word - 1, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                        return Ok(());
+                    }
+                } else if word & WAKER_DROPPED != 0 {
+                    return Err(SwapError::Disconnected);
+                } else if sys::blocking_allowed() {
+                    sys::wait(&self.0, word, None);
+                } else {
+                    hint::spin_loop();
+                }
+            }
+        }
+
+        pub(crate) fn try_wait(&self) -> Result<bool, SwapError> {
+            let word = self.0.load(Ordering::Acquire);
+            if word & PENDING != 0 {
+                Ok(self.0.compare_exchange(word, word - 1, Ordering::AcqRel, Ordering::Acquire).is_ok())
+            } else if word & WAKER_DROPPED != 0 {
+                Err(SwapError::Disconnected)
+            } else {
+                Ok(false)
+            }
+        }
+
+        pub(crate) fn wait_until(&self, deadline: Instant) -> Result<bool, SwapError> {
+            loop {
+                if self.try_wait()? {
+                    return Ok(true);
+                }
+                let word = self.0.load(Ordering::Acquire);
+                if Instant::now() >= deadline {
+                    return Ok(false);
+                } else if word & PENDING != 0 {
+                    continue;
+                } else if sys::blocking_allowed() {
+                    sys::wait(&self.0, word, Some(deadline));
+                } else {
+                    hint::spin_loop();
+                }
+            }
+        }
+    }
+
+    impl Drop for Waiter {
+        fn drop(&mut self) {
+            self.0.fetch_or(WAITER_DROPPED, Ordering::AcqRel);
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+    mod sys {
+        use std::arch::wasm32;
+        use std::cell::Cell;
+        use std::sync::atomic::AtomicU32;
+        use std::time::Instant;
+
+        thread_local! {
+            static BLOCKING_ALLOWED: Cell<bool> = Cell::new(true);
+        }
+
+        /// Set whether the current thread is allowed to block using `memory.atomic.wait32`.
+        ///
+        /// The main thread of a web page is not allowed to block, so should call
+        /// `set_blocking_allowed(false)` before swapping, in which case it polls while waiting.
+        pub fn set_blocking_allowed(allowed: bool) {
+            BLOCKING_ALLOWED.with(|cell| cell.set(allowed));
+        }
+
+        pub(super) fn blocking_allowed() -> bool {
+            BLOCKING_ALLOWED.with(Cell::get)
+        }
+
+        /// Block while the word is `expected`, until notified or the deadline passes.
+        pub(super) fn wait(word: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+            let timeout = match deadline {
+                None => -1,
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()).as_nanos().min(i64::MAX as u128) as i64,
+            };
+            unsafe { wasm32::memory_atomic_wait32(word.as_ptr().cast(), expected as i32, timeout) };
+        }
+
+        pub(super) fn notify(word: &AtomicU32) {
+            unsafe { wasm32::memory_atomic_notify(word.as_ptr().cast(), u32::MAX) };
+        }
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "atomics")))]
+    mod sys {
+        use std::sync::atomic::AtomicU32;
+        use std::sync::atomic::Ordering;
+        use std::time::Instant;
+
+        use parking_lot_core;
+        use parking_lot_core::DEFAULT_PARK_TOKEN;
+        use parking_lot_core::DEFAULT_UNPARK_TOKEN;
+
+        pub(super) fn blocking_allowed() -> bool {
+            true
+        }
+
+        /// Block while the word is `expected`, until notified or the deadline passes.
+        pub(super) fn wait(word: &AtomicU32, expected: u32, deadline: Option<Instant>) {
+            // The word is checked with the parking queue locked, and `notify` locks it after
+            // the word changes, so either we see the change or `notify` sees us parked.
+            let validate = || word.load(Ordering::Acquire) == expected;
+            unsafe { parking_lot_core::park(key(word), validate, || (), |_, _| (), DEFAULT_PARK_TOKEN, deadline) };
+        }
+
+        pub(super) fn notify(word: &AtomicU32) {
+            unsafe { parking_lot_core::unpark_all(key(word), DEFAULT_UNPARK_TOKEN) };
+        }
+
+        fn key(word: &AtomicU32) -> usize {
+            word as *const AtomicU32 as usize
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Watchdogs, which report swaps that have been blocked for too long.
+//!
+//! Every watched swap is recorded in a global list, which a single timer thread checks.
+//! The thread is started when the first swap is watched, and sleeps until the earliest
+//! threshold, so pairs without a watchdog cost nothing, and pairs with one only cost
+//! a lock when a swap blocks.
+
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use Shared;
+
+/// A swap which has been blocked for longer than its pair's watchdog threshold.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockedSwap {
+    /// The unique id of the pair.
+    pub id: u64,
+    /// The name of the pair, if it has one.
+    pub name: Option<String>,
+    /// Which half of the pair is blocked, 0 or 1.
+    pub half: usize,
+    /// How long the swap had been blocked when the callback was called.
+    pub blocked_for: Duration,
+}
+
+/// The callback for swaps blocked longer than a threshold.
+#[derive(Clone)]
+pub(crate) struct Watchdog {
+    threshold: Duration,
+    callback: Arc<dyn Fn(&BlockedSwap) + Send + Sync>,
+}
+
+struct Watched {
+    next: u64,
+    // Whether the timer thread has been started.
+    running: bool,
+    waits: Vec<Wait>,
+}
+
+struct Wait {
+    key: u64,
+    since: Instant,
+    // Whether the callback has been called for this wait, so it is only called once.
+    fired: bool,
+    shared: Arc<Shared>,
+    half: usize,
+    watchdog: Watchdog,
+}
+
+static WATCHED: Mutex<Watched> = Mutex::new(Watched {
+    next: 0,
+    running: false,
+    waits: Vec::new(),
+});
+static WATCHED_CONDVAR: Condvar = Condvar::new();
+
+/// A record that a swap is blocked, which is removed when dropped.
+pub(crate) struct Guard(u64);
+
+impl Watchdog {
+    pub(crate) fn new<F: Fn(&BlockedSwap) + Send + Sync + 'static>(threshold: Duration, callback: F) -> Watchdog {
+        Watchdog {
+            threshold,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+/// Record that a half of a pair is blocked, until the returned guard is dropped.
+pub(crate) fn watch(shared: &Arc<Shared>, half: usize, watchdog: &Watchdog) -> Guard {
+    let mut watched = lock();
+    let key = watched.next;
+    watched.next += 1;
+    watched.waits.push(Wait {
+        key,
+        since: Instant::now(),
+        fired: false,
+        shared: shared.clone(),
+        half,
+        watchdog: watchdog.clone(),
+    });
+    if !watched.running {
+        watched.running = true;
+        thread::Builder::new()
+            .name(String::from("swapper-watchdog"))
+            .spawn(run)
+            .expect("Failed to start the watchdog thread");
+    }
+    WATCHED_CONDVAR.notify_one();
+    Guard(key)
+}
+
+fn lock() -> MutexGuard<'static, Watched> {
+    WATCHED.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+// The timer thread, which calls the callbacks of waits which have passed their thresholds.
+fn run() {
+    let mut watched = lock();
+    loop {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        let mut earliest: Option<Instant> = None;
+        for wait in watched.waits.iter_mut().filter(|wait| !wait.fired) {
+            let deadline = wait.since + wait.watchdog.threshold;
+            if deadline <= now {
+                wait.fired = true;
+                let blocked = BlockedSwap {
+                    id: wait.shared.id,
+                    name: wait.shared.name.clone(),
+                    half: wait.half,
+                    blocked_for: now - wait.since,
+                };
+                due.push((wait.watchdog.callback.clone(), blocked));
+            } else {
+                earliest = Some(earliest.map_or(deadline, |earliest| earliest.min(deadline)));
+            }
+        }
+        if !due.is_empty() {
+            // Callbacks are called without the lock, so they can swap, or block, themselves.
+            drop(watched);
+            for (callback, blocked) in due {
+                // A panicking callback would otherwise kill the timer thread, and with it
+                // every watchdog. The panic has already been reported by the panic hook.
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(&blocked)));
+            }
+            watched = lock();
+            continue;
+        }
+        watched = match earliest {
+            Some(earliest) => WATCHED_CONDVAR.wait_timeout(watched, earliest - now).unwrap_or_else(|err| err.into_inner()).0,
+            None => WATCHED_CONDVAR.wait(watched).unwrap_or_else(|err| err.into_inner()),
+        };
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut watched = lock();
+        if let Some(index) = watched.waits.iter().position(|wait| wait.key == self.0) {
+            watched.waits.swap_remove(index);
+        }
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
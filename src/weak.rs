@@ -0,0 +1,119 @@
+//! Weak halves of swap pairs, which do not keep the pair alive.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::atomic::Ordering;
+
+use Shared;
+use Swapper;
+use WaitStrategy;
+use release;
+use wake::Waiter;
+use wake::Waker;
+
+/// A half of a swap pair which does not keep the pair's shared state alive.
+///
+/// A weak half is created by `Swapper::downgrade`, and can be upgraded back to a swapper
+/// while the other half is alive. Once the other half has been dropped, the pair's shared
+/// state is freed, and upgrading fails. This allows a registry of many halves to hold
+/// them weakly, and prune the dead ones with `is_alive`.
+///
+/// While the weak half exists, the other half still sees the pair as connected,
+/// and can block waiting for the weak half to be upgraded and swap.
+///
+/// ```rust
+/// let (ab, ba) = swapper::swapper::<u8>();
+/// let weak = ab.downgrade();
+/// assert!(weak.is_alive());
+/// let ab = weak.upgrade().unwrap();
+/// let weak = ab.downgrade();
+/// drop(ba);
+/// assert!(!weak.is_alive());
+/// assert!(weak.upgrade().is_none());
+/// ```
+pub struct SwapperWeak<T: ?Sized> {
+    shared: Weak<Shared>,
+    wait: Waiter,
+    notify: Waker,
+    strategy: WaitStrategy,
+    half: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: ?Sized> Swapper<T> {
+    /// Convert this half into a weak half, which does not keep the pair alive.
+    pub fn downgrade(self) -> SwapperWeak<T> {
+        // Move the fields out without running `Drop`, since this half is still counted.
+        let this = ManuallyDrop::new(self);
+        let shared = unsafe { ptr::read(&this.shared) };
+        SwapperWeak {
+            shared: Arc::downgrade(&shared),
+            wait: unsafe { ptr::read(&this.wait) },
+            notify: unsafe { ptr::read(&this.notify) },
+            strategy: this.strategy,
+            half: this.half,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> SwapperWeak<T> {
+    /// Convert this back into a swapper, if the other half is still alive.
+    pub fn upgrade(self) -> Option<Swapper<T>> {
+        let this = ManuallyDrop::new(self);
+        let weak = unsafe { ptr::read(&this.shared) };
+        let wait = unsafe { ptr::read(&this.wait) };
+        let notify = unsafe { ptr::read(&this.notify) };
+        let shared = weak.upgrade()?;
+        if shared.is_disconnected() {
+            // The other half has been dropped, but the shared state has not yet been freed.
+            shared.halves.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(Swapper {
+            shared,
+            wait,
+            notify,
+            strategy: this.strategy,
+            half: this.half,
+            marker: PhantomData,
+        })
+    }
+
+    /// Is the other half still alive?
+    pub fn is_alive(&self) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => !shared.is_disconnected(),
+            None => false,
+        }
+    }
+
+    /// The unique id of this swap pair, if its shared state has not been freed.
+    pub fn id(&self) -> Option<u64> {
+        self.shared.upgrade().map(|shared| shared.id)
+    }
+}
+
+impl<T: ?Sized> Drop for SwapperWeak<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            release::<T>(&shared, &self.notify);
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for SwapperWeak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SwapperWeak")
+            .field("id", &self.id())
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+// Be explicit about implementing Send.
+unsafe impl<T: ?Sized + Send> Send for SwapperWeak<T> {}
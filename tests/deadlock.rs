@@ -0,0 +1,57 @@
+#![cfg(feature = "deadlock-detection")]
+
+extern crate swapper;
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use swapper::PartnerState;
+use swapper::SwapError;
+use swapper::deadlock;
+use swapper::swapper;
+
+static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn report(cycle: &str) {
+    REPORTS.lock().unwrap().push(String::from(cycle));
+}
+
+#[test]
+fn test_deadlock_detection() {
+    deadlock::set_reporter(report);
+    let (a, b) = swapper();
+    let (c, d) = swapper();
+    let (e, f) = swapper();
+    let timeout = Duration::from_millis(200);
+    let one = thread::spawn(move || {
+        let mut value = 1;
+        e.swap(&mut value).unwrap();
+        a.swap(&mut value).unwrap();
+        d.swap(&mut value).unwrap();
+        // Both threads now wait for a pair whose other half is used by the other thread.
+        a.swap_timeout(&mut value, timeout)
+    });
+    let two = thread::spawn(move || {
+        let mut value = 2;
+        b.swap(&mut value).unwrap();
+        c.swap(&mut value).unwrap();
+        c.swap_timeout(&mut value, timeout)
+    });
+    f.swap(&mut 0).unwrap();
+    // Once the cycle has formed, wait for a pair whose other half is used by a thread in it,
+    // which does not close another cycle.
+    thread::sleep(Duration::from_millis(50));
+    let partner = PartnerState::NeverArrived;
+    assert_eq!(f.swap_timeout(&mut 0, Duration::from_millis(10)), Err(SwapError::Timeout { partner }));
+    // Whichever thread waits second reports the cycle, and both keep waiting until one times out,
+    // dropping its halves, which disconnects the other, unless it has timed out too.
+    for result in [one.join().unwrap(), two.join().unwrap()] {
+        match result {
+            Err(SwapError::Timeout { .. }) | Err(SwapError::Disconnected) => (),
+            result => panic!("Expected a timeout, got {:?}", result),
+        }
+    }
+    let reports = REPORTS.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].contains("waits for pair"));
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "ffi")]
+
+extern crate swapper;
+
+use std::os::raw::c_void;
+use std::ptr;
+use std::thread;
+use swapper::ffi::*;
+
+#[test]
+fn test_ffi() {
+    let mut hello = 37;
+    let mut world = 5;
+    let (mut us, mut them) = (ptr::null_mut(), ptr::null_mut());
+    unsafe {
+        assert_eq!(swapper_new(&mut us, &mut them), SWAPPER_OK);
+        let them = ForeignPtr(them.cast());
+        let hello = ForeignPtr((&mut hello as *mut i32).cast());
+        let helper = thread::spawn(move || {
+            let mut value = hello.0;
+            assert_eq!(swapper_swap(them.0.cast(), &mut value), SWAPPER_OK);
+            swapper_free(them.0.cast());
+            ForeignPtr(value)
+        });
+        let mut value: *mut c_void = (&mut world as *mut i32).cast();
+        assert_eq!(swapper_swap(us, &mut value), SWAPPER_OK);
+        assert_eq!(*value.cast::<i32>(), 37);
+        assert_eq!(*helper.join().unwrap().0.cast::<i32>(), 5);
+        assert_eq!(swapper_swap(us, &mut value), SWAPPER_DISCONNECTED);
+        swapper_free(us);
+    }
+}
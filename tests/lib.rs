@@ -1,7 +1,62 @@
 extern crate swapper;
 
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::panic;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Wake;
+use std::task::Waker;
 use std::thread;
+use std::time::Duration;
+use swapper::CancelToken;
+use swapper::CollectError;
+use swapper::EpochError;
+use swapper::Exchange;
+use swapper::GiveError;
+use swapper::IsrSwapper;
+use swapper::KeyedExchange;
+use swapper::PartnerState;
+use swapper::Pipeline;
+use swapper::PipelineError;
+use swapper::PreferSameNode;
+use swapper::ScopedSlot;
+use swapper::SwapBox;
+use swapper::SwapEpoch;
+use swapper::SwapMessage;
+use swapper::SwapObserver;
+use swapper::SwapPump;
+use swapper::SwapRequest;
+use swapper::SwapperPool;
+use swapper::SwapperSet;
+use swapper::SwapError;
+use swapper::any_swapper;
+use swapper::big_swapper;
+use swapper::broadcast;
+use swapper::buffered_swapper;
+use swapper::config_swap;
+use swapper::gather;
+use swapper::SwapState;
+use swapper::SwapperBuilder;
+use swapper::WaitStrategy;
+use swapper::halo_exchange;
+use swapper::handoff;
+use swapper::neighbours;
+use swapper::oneshot_swapper;
+use swapper::reusable_swapper;
+use swapper::raw;
+use swapper::registry::{self, PairState};
+use swapper::shuffle_seeded;
+use swapper::swap_lock;
 use swapper::swapper;
+use swapper::work_swapping;
 
 #[test]
 fn test() {
@@ -16,3 +71,1545 @@ fn test() {
     assert_eq!(world, "hello");
     helper.join().unwrap();
 }
+
+#[test]
+fn test_registry() {
+    let (us, them) = SwapperBuilder::new().name("test-registry").register(true).build::<u8>();
+    let state = || {
+        registry::pairs()
+            .into_iter()
+            .find(|info| info.id == us.id())
+            .map(|info| info.state)
+    };
+    assert_eq!(us.name(), Some("test-registry"));
+    assert_eq!(state(), Some(PairState::Empty));
+    let helper = thread::spawn(move || them.swap(&mut 37).unwrap());
+    while state() == Some(PairState::Empty) {
+        thread::yield_now();
+    }
+    assert_eq!(state(), Some(PairState::Offered));
+    us.swap(&mut 5).unwrap();
+    helper.join().unwrap();
+    assert_eq!(state(), Some(PairState::Disconnected));
+    assert!(registry::dump().contains("\"test-registry\": Disconnected"));
+}
+
+#[test]
+fn test_state() {
+    let (us, them) = swapper();
+    assert_eq!(us.state(), SwapState::Idle);
+    let helper = thread::spawn(move || {
+        them.swap(&mut 37).unwrap();
+        them
+    });
+    while us.state() == SwapState::Idle {
+        thread::yield_now();
+    }
+    assert_eq!(us.state(), SwapState::PartnerWaiting);
+    assert!(format!("{:?}", us).contains("PartnerWaiting"));
+    us.swap(&mut 5).unwrap();
+    let them = helper.join().unwrap();
+    assert_eq!(them.state(), SwapState::Idle);
+    drop(us);
+    assert_eq!(them.state(), SwapState::Disconnected);
+}
+
+#[test]
+fn test_take_offer() {
+    // The helper offers first, and we take its offer.
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        let mut hello = vec![String::from("hello")];
+        them.swap(&mut hello).unwrap();
+        assert_eq!(hello, ["world"]);
+    });
+    while us.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    let mut world = vec![String::from("world")];
+    us.swap(&mut world).unwrap();
+    assert_eq!(world, ["hello"]);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_retract_offer() {
+    // We offer first, and the helper drops its swapper without taking our offer.
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        while them.state() != SwapState::PartnerWaiting {
+            thread::yield_now();
+        }
+    });
+    let mut hello = vec![String::from("hello")];
+    assert_eq!(us.swap(&mut hello), Err(SwapError::Disconnected));
+    assert_eq!(hello, ["hello"]);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swap_unsized() {
+    let mut ours = vec![0u8; 8];
+    let mut theirs = vec![1u8; 8];
+    let (us, them) = swapper::<[u8]>();
+    thread::scope(|scope| {
+        let theirs = &mut theirs;
+        scope.spawn(move || {
+            assert_eq!(them.swap_unsized(&mut theirs[..2]), Err(SwapError::Mismatch));
+            them.swap_unsized(&mut theirs[2..6]).unwrap();
+        });
+        assert_eq!(us.swap_unsized(&mut ours[..3]), Err(SwapError::Mismatch));
+        us.swap_unsized(&mut ours[4..]).unwrap();
+    });
+    assert_eq!(ours, [0, 0, 0, 0, 1, 1, 1, 1]);
+    assert_eq!(theirs, [1, 1, 0, 0, 0, 0, 1, 1]);
+}
+
+#[test]
+fn test_any_swapper() {
+    let (us, them) = any_swapper();
+    let helper = thread::spawn(move || {
+        let mut hello = String::from("hello");
+        them.swap_as(&mut hello).unwrap();
+        assert_eq!(hello, "world");
+        match them.swap_as(&mut hello) {
+            Err(SwapError::TypeMismatch { ours, theirs }) => {
+                assert!(ours.ends_with("String"));
+                assert_eq!(theirs, "u32");
+            }
+            result => panic!("Unexpected {:?}", result),
+        }
+    });
+    let mut world = String::from("world");
+    us.swap_as(&mut world).unwrap();
+    assert_eq!(world, "hello");
+    assert!(us.swap_as(&mut 37u32).is_err());
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_deposit() {
+    let (mut us, them) = swapper();
+    let pending = us.deposit(String::from("hello"));
+    let helper = thread::spawn(move || {
+        let mut world = String::from("world");
+        them.swap(&mut world).unwrap();
+        assert_eq!(world, "hello");
+    });
+    assert_eq!(pending.collect().unwrap(), "world");
+    helper.join().unwrap();
+    let pending = us.deposit(String::from("hello"));
+    assert_eq!(pending.collect(), Err(CollectError(String::from("hello"), SwapError::Disconnected)));
+}
+
+#[test]
+fn test_deposit_dropped() {
+    let (mut us, them) = swapper();
+    drop(us.deposit(String::from("hello")));
+    assert_eq!(us.state(), SwapState::Idle);
+    let pending = us.deposit(String::from("hello"));
+    let mut world = String::from("world");
+    them.swap(&mut world).unwrap();
+    drop(pending);
+    assert_eq!(world, "hello");
+}
+
+#[test]
+fn test_broadcast() {
+    let (mut leader, mut workers) = broadcast(3);
+    drop(workers.remove(1));
+    let helpers: Vec<_> = workers
+        .into_iter()
+        .enumerate()
+        .map(|(index, worker)| {
+            thread::spawn(move || {
+                let mut config = index;
+                worker.swap(&mut config).unwrap();
+                assert_eq!(config, 37);
+            })
+        })
+        .collect();
+    let error = leader.swap_each(vec![37; 3]).unwrap_err();
+    assert_eq!(error.values, [0, 37, 1]);
+    assert_eq!(error.failures, [(1, SwapError::Disconnected)]);
+    for helper in helpers {
+        helper.join().unwrap();
+    }
+}
+
+#[test]
+fn test_epoch() {
+    fn timeout(result: Result<u64, EpochError>) -> swapper::EpochTimeout {
+        match result {
+            Err(EpochError::Timeout(error)) => error,
+            result => panic!("Expected a timeout, got {:?}", result),
+        }
+    }
+    let epoch = SwapEpoch::new();
+    let (us, them) = SwapperBuilder::new().name("test-epoch").build();
+    epoch.register(&us);
+    let error = timeout(epoch.wait_timeout(Duration::from_millis(10)));
+    assert_eq!(error.generation, 0);
+    assert_eq!(error.pending[0].name.as_deref(), Some("test-epoch"));
+    let helper = thread::spawn(move || them.swap(&mut 37).unwrap());
+    us.swap(&mut 5).unwrap();
+    assert_eq!(epoch.wait_timeout(Duration::from_secs(10)), Ok(1));
+    helper.join().unwrap();
+    let error = timeout(epoch.wait_timeout(Duration::from_millis(10)));
+    assert_eq!(error.generation, 1);
+    assert_eq!(error.pending[0].state, PairState::Disconnected);
+    // The state is not locked while waiting, so the generation can be read meanwhile.
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| epoch.wait_timeout(Duration::from_millis(100)));
+        assert_eq!(epoch.generation(), 1);
+        timeout(waiter.join().unwrap());
+    });
+    // Once both halves are dropped, the pair can never swap, so no longer counts.
+    drop(us);
+    assert_eq!(epoch.wait(), Ok(2));
+    assert_eq!(epoch.wait_timeout(Duration::from_secs(10)), Ok(3));
+}
+
+#[test]
+fn test_swapper_set() {
+    let set = SwapperSet::new();
+    let a = set.register();
+    let b = set.register();
+    let c = set.register();
+    let (a_id, b_id, c_id) = (a.id(), b.id(), c.id());
+    assert_eq!(set.members(), [a_id, b_id, c_id]);
+    assert_eq!(a.swap_with(a_id, &mut 0), Err(SwapError::WouldDeadlock));
+    let helper = thread::spawn(move || {
+        // Wait for b, who leaves without swapping.
+        assert_eq!(c.swap_with(b_id, &mut 0), Err(SwapError::Disconnected));
+        let mut value = 3;
+        assert_eq!(c.swap_any(&mut value), Ok(a_id));
+        assert_eq!(value, 1);
+    });
+    b.deregister();
+    let mut value = 1;
+    a.swap_with(c_id, &mut value).unwrap();
+    assert_eq!(value, 3);
+    helper.join().unwrap();
+    assert_eq!(set.members(), [a_id]);
+    assert_eq!(a.swap_any(&mut value), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_shuffle() {
+    let run = |seed| {
+        let helpers: Vec<_> = shuffle_seeded(5, seed)
+            .into_iter()
+            .map(|mut participant| {
+                thread::spawn(move || {
+                    (0..3)
+                        .map(|_| {
+                            let mut value = participant.index();
+                            let partner = participant.shuffle(&mut value).unwrap();
+                            assert_eq!(value, partner.unwrap_or(participant.index()));
+                            partner
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        helpers.into_iter().map(|helper| helper.join().unwrap()).collect::<Vec<_>>()
+    };
+    let partners = run(37);
+    for round in 0..3 {
+        assert_eq!(partners.iter().filter(|partners| partners[round].is_none()).count(), 1);
+    }
+    assert_eq!(run(37), partners);
+    let mut participants = shuffle_seeded::<u8>(2, 0);
+    drop(participants.pop());
+    assert_eq!(participants[0].shuffle(&mut 0), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_swap_seq() {
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        let mut value = 37;
+        them.swap_seq(&mut value, 0).unwrap();
+        assert_eq!(value, 5);
+        // The helper skips a generation.
+        assert_eq!(them.swap_seq(&mut value, 2), Err(SwapError::Sequence { ours: 2, theirs: 1 }));
+        // The helper swaps with a stale generation, after we have offered.
+        while them.state() != SwapState::PartnerWaiting {
+            thread::yield_now();
+        }
+        them.swap(&mut value).unwrap();
+    });
+    let mut value = 5;
+    us.swap_seq(&mut value, 0).unwrap();
+    assert_eq!(value, 37);
+    assert_eq!(us.current_gen(), 1);
+    us.swap_seq(&mut value, 1).unwrap();
+    assert_eq!(value, 5);
+    assert_eq!(us.current_gen(), 2);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swapper_set_fifo() {
+    let set = SwapperSet::new();
+    let us = set.register();
+    let us_id = us.id();
+    let helpers: Vec<_> = (0..3)
+        .map(|_| {
+            let member = set.register();
+            let id = member.id();
+            let helper = thread::spawn(move || member.swap_with(us_id, &mut 0).unwrap());
+            // Wait for this member to arrive before the next one.
+            while set.waiting().last() != Some(&id) {
+                thread::yield_now();
+            }
+            (id, helper)
+        })
+        .collect();
+    for (id, helper) in helpers {
+        assert_eq!(us.swap_any(&mut 0), Ok(id));
+        helper.join().unwrap();
+    }
+}
+
+#[test]
+fn test_swapper_set_stress() {
+    let set = SwapperSet::new();
+    let total = Arc::new(AtomicUsize::new(0));
+    let helpers: Vec<_> = (0..6)
+        .map(|_| {
+            let member = set.register();
+            let total = total.clone();
+            thread::spawn(move || {
+                let mut swaps = 0;
+                while total.load(Ordering::Relaxed) < 10_000 {
+                    match member.swap_any(&mut member.id()) {
+                        Ok(_) => total.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => break,
+                    };
+                    swaps += 1;
+                }
+                swaps
+            })
+        })
+        .collect();
+    for helper in helpers {
+        assert!(helper.join().unwrap() > 0);
+    }
+}
+
+#[test]
+fn test_swap_lock() {
+    let (us, them) = swap_lock(vec![1], vec![2]);
+    let helper = thread::spawn(move || {
+        for round in 0..3 {
+            let mut guard = them.lock();
+            guard.push(round);
+            guard.swap_with_partner().unwrap();
+        }
+        them
+    });
+    for _ in 0..3 {
+        us.lock().swap_with_partner().unwrap();
+    }
+    let them = helper.join().unwrap();
+    assert_eq!(us.into_inner(), [2, 0, 2]);
+    assert_eq!(them.into_inner(), [1, 1]);
+    let (us, them) = swap_lock(1, 2);
+    drop(them);
+    assert_eq!(us.lock().swap_with_partner(), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_buffered_swapper() {
+    let (us, them) = buffered_swapper(0);
+    // We can swap with the initial value without waiting.
+    let mut value = 1;
+    us.swap(&mut value).unwrap();
+    assert_eq!(value, 0);
+    assert!(!us.is_ready());
+    assert!(them.is_ready());
+    let helper = thread::spawn(move || {
+        let mut value = 2;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 1);
+    });
+    // We block until the helper has swapped.
+    us.swap(&mut value).unwrap();
+    assert_eq!(value, 2);
+    helper.join().unwrap();
+    assert_eq!(us.swap(&mut value), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_swap_cloned() {
+    // The helper offers first, and we copy its offer.
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        let mut hello = String::from("hello");
+        them.swap(&mut hello).unwrap();
+        assert_eq!(hello, "hello");
+        them
+    });
+    while us.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    assert_eq!(us.swap_cloned().unwrap(), "hello");
+    let mut them = helper.join().unwrap();
+    assert_eq!(us.current_gen(), 0);
+    // We offer first, and the helper's deposit is copied.
+    let helper = thread::spawn(move || us.swap_cloned());
+    while them.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    assert_eq!(them.deposit(String::from("world")).collect().unwrap(), "world");
+    assert_eq!(helper.join().unwrap().unwrap(), "world");
+    assert_eq!(them.swap_cloned(), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_big_swapper() {
+    let (us, them) = big_swapper::<[u8]>();
+    let theirs = SwapBox::from(vec![1; 1 << 20].into_boxed_slice());
+    let their_ptr = theirs.as_ptr();
+    let helper = thread::spawn(move || {
+        let mut theirs = theirs;
+        them.swap(&mut theirs).unwrap();
+        assert_eq!(theirs.len(), 3);
+    });
+    let mut ours = SwapBox::from(vec![2; 3].into_boxed_slice());
+    us.swap(&mut ours).unwrap();
+    // The data was not copied, only the pointer to it.
+    assert_eq!(ours.as_ptr(), their_ptr);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_wait_strategies() {
+    let (mut us, them) = SwapperBuilder::new()
+        .wait_strategies(WaitStrategy::Spin(100), WaitStrategy::SpinThenPark(100))
+        .build();
+    assert_eq!(us.wait_strategy(), WaitStrategy::Spin(100));
+    let mut value = 5;
+    assert_eq!(us.swap(&mut value), Err(SwapError::WouldBlock));
+    assert_eq!(us.state(), SwapState::Idle);
+    let helper = thread::spawn(move || {
+        let mut value = 37;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 5);
+    });
+    us.set_wait_strategy(WaitStrategy::Park);
+    us.swap(&mut value).unwrap();
+    assert_eq!(value, 37);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swapper_weak() {
+    let (us, them) = swapper();
+    let weak = us.downgrade();
+    assert!(weak.is_alive());
+    // The other half still sees the pair as connected.
+    assert_eq!(them.state(), SwapState::Idle);
+    let helper = thread::spawn(move || {
+        let mut value = 37;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 5);
+    });
+    let us = weak.upgrade().unwrap();
+    us.swap(&mut 5).unwrap();
+    helper.join().unwrap();
+    let weak = us.downgrade();
+    assert!(!weak.is_alive());
+    assert_eq!(weak.id(), None);
+    assert!(weak.upgrade().is_none());
+    // Dropping a weak half disconnects the other half.
+    let (us, them) = swapper::<u8>();
+    drop(us.downgrade());
+    assert_eq!(them.state(), SwapState::Disconnected);
+}
+
+#[test]
+fn test_neighbours() {
+    // A 1-dimensional blur, where each task owns 3 cells, plus a halo cell either side.
+    let mut cells: Vec<u32> = (0..12).map(|x| x * x).collect();
+    let expected: Vec<u32> = (0..12)
+        .map(|x| cells[x.max(1) - 1] + cells[x] + cells[(x + 1).min(11)])
+        .collect();
+    thread::scope(|scope| {
+        for (chunk, neighbours) in cells.chunks_mut(3).zip(neighbours(4)) {
+            scope.spawn(move || {
+                let (mut left, mut right) = (chunk[0], chunk[2]);
+                neighbours.exchange(&mut left, &mut right).unwrap();
+                // At the edges, the halo is a copy of the edge cell.
+                let mut padded = vec![left];
+                padded.extend_from_slice(chunk);
+                padded.push(right);
+                for (cell, window) in chunk.iter_mut().zip(padded.windows(3)) {
+                    *cell = window.iter().sum();
+                }
+            });
+        }
+    });
+    assert_eq!(cells, expected);
+}
+
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_swap_async() {
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+    let (mut us, them) = swapper();
+    // The other half swaps while the future is suspended, and wakes it.
+    let mut swap = us.swap_async(String::from("hello"));
+    assert!(Pin::new(&mut swap).poll(&mut cx).is_pending());
+    let helper = thread::spawn(move || {
+        let mut world = String::from("world");
+        them.swap(&mut world).unwrap();
+        assert_eq!(world, "hello");
+        them
+    });
+    let them = helper.join().unwrap();
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+    assert_eq!(Pin::new(&mut swap).poll(&mut cx), Poll::Ready(Ok(String::from("world"))));
+    drop(swap);
+    // Dropping a suspended future retracts its offer.
+    let mut swap = us.swap_async(String::from("hello"));
+    assert!(Pin::new(&mut swap).poll(&mut cx).is_pending());
+    drop(swap);
+    assert_eq!(us.state(), SwapState::Idle);
+    // Cancelling a suspended future returns its value.
+    let mut swap = us.swap_async(String::from("hello"));
+    assert!(Pin::new(&mut swap).poll(&mut cx).is_pending());
+    assert_eq!(swap.cancel(), Err(CollectError(String::from("hello"), SwapError::WouldBlock)));
+    // If the other half is already waiting, the future completes on its first poll.
+    let helper = thread::spawn(move || {
+        let mut world = String::from("world");
+        them.swap(&mut world).unwrap();
+        assert_eq!(world, "hello");
+    });
+    while us.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    let mut swap = us.swap_async(String::from("hello"));
+    assert_eq!(Pin::new(&mut swap).poll(&mut cx), Poll::Ready(Ok(String::from("world"))));
+    drop(swap);
+    helper.join().unwrap();
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+    // Once the other half is dropped, the future completes with the deposited value.
+    let mut swap = us.swap_async(String::from("hello"));
+    assert_eq!(
+        Pin::new(&mut swap).poll(&mut cx),
+        Poll::Ready(Err(CollectError(String::from("hello"), SwapError::Disconnected)))
+    );
+    drop(swap);
+    // A suspended future can be sent to another thread, for example by a multi-threaded executor.
+    fn assert_send<T: Send>(_: &T) {}
+    let (mut us, them) = swapper();
+    let mut swap = us.swap_async(String::from("hello"));
+    assert_send(&swap);
+    assert!(Pin::new(&mut swap).poll(&mut cx).is_pending());
+    thread::scope(|scope| {
+        let helper = scope.spawn(move || {
+            let mut cx = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(result) = Pin::new(&mut swap).poll(&mut cx) {
+                    return result;
+                }
+                thread::yield_now();
+            }
+        });
+        them.swap(&mut String::from("world")).unwrap();
+        assert_eq!(helper.join().unwrap(), Ok(String::from("world")));
+    });
+}
+
+#[test]
+fn test_swap_timeout() {
+    let (us, them) = swapper();
+    let timeout = Duration::from_millis(10);
+    let partner = PartnerState::NeverArrived;
+    assert_eq!(us.swap_timeout(&mut 1, timeout), Err(SwapError::Timeout { partner }));
+    assert_eq!(us.state(), SwapState::Idle);
+    let helper = thread::spawn(move || {
+        thread::sleep(timeout);
+        let mut value = 2;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 1);
+    });
+    let mut value = 1;
+    us.swap_timeout(&mut value, Duration::from_secs(10)).unwrap();
+    assert_eq!(value, 2);
+    helper.join().unwrap();
+    assert_eq!(us.swap_timeout(&mut value, timeout), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_swap_slot() {
+    let mut slot = ScopedSlot::new();
+    for _ in 0..2 {
+        // The slot can be split again once both halves have been dropped.
+        let (us, them) = slot.split();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut value = 1;
+                for round in 0..100 {
+                    them.swap(&mut value).unwrap();
+                    assert_eq!(value, round * 2);
+                    value = round * 2 + 3;
+                }
+            });
+            let mut value = 0;
+            for round in 0..100 {
+                us.swap(&mut value).unwrap();
+                assert_eq!(value, round * 2 + 1);
+                value = round * 2 + 2;
+            }
+            // The other half is dropped once it has finished, which disconnects us.
+            assert_eq!(us.swap(&mut value), Err(SwapError::Disconnected));
+        });
+    }
+}
+
+#[test]
+fn test_oneshot_swapper() {
+    let (us, them) = oneshot_swapper();
+    let helper = thread::spawn(move || {
+        let mut value = 1;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 2);
+    });
+    let mut value = 2;
+    us.swap(&mut value).unwrap();
+    assert_eq!(value, 1);
+    helper.join().unwrap();
+    let (us, them) = oneshot_swapper::<u8>();
+    drop(them);
+    assert_eq!(us.swap(&mut 1), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_reusable_swapper() {
+    let (us, them) = reusable_swapper();
+    let helper = thread::spawn(move || {
+        // The nth swap of each half exchanges data with the nth swap of the other.
+        for round in 0..100 {
+            let mut value = (1, round);
+            them.swap(&mut value).unwrap();
+            assert_eq!(value, (0, round));
+        }
+    });
+    for round in 0..100 {
+        let mut value = (0, round);
+        us.swap(&mut value).unwrap();
+        assert_eq!(value, (1, round));
+    }
+    helper.join().unwrap();
+    assert_eq!(us.current_gen(), 100);
+    assert_eq!(us.swap(&mut (0, 100)), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_prepare() {
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        for round in 0..3 {
+            // Prepare first, so this half negotiates.
+            let mut value = 1;
+            let negotiation = them.prepare(&mut value).unwrap().wait().unwrap().unwrap();
+            assert_eq!((*negotiation.ours(), *negotiation.theirs()), (1, 0));
+            match round {
+                0 => negotiation.commit().unwrap(),
+                1 => negotiation.abort().unwrap(),
+                _ => drop(negotiation),
+            }
+            assert_eq!(value, if round == 0 { 0 } else { 1 });
+        }
+        them
+    });
+    for round in 0..3 {
+        while us.state() != SwapState::PartnerWaiting {
+            thread::yield_now();
+        }
+        let mut value = 0;
+        let result = us.prepare(&mut value).unwrap().wait().map(|negotiation| negotiation.is_none());
+        if round == 0 {
+            assert_eq!(result, Ok(true));
+            assert_eq!(value, 1);
+        } else {
+            assert!(matches!(result, Err(SwapError::Aborted)));
+            assert_eq!(value, 0);
+        }
+    }
+    let them = helper.join().unwrap();
+    assert_eq!(us.current_gen(), 1);
+    // A half which swaps joins a prepared swap, and the preparing half negotiates.
+    let mut value = 0;
+    let pending = us.prepare(&mut value).unwrap();
+    let helper = thread::spawn(move || {
+        let mut value = 1;
+        them.swap(&mut value).unwrap();
+        assert_eq!(value, 0);
+        them
+    });
+    pending.wait().unwrap().unwrap().commit().unwrap();
+    assert_eq!(value, 1);
+    let them = helper.join().unwrap();
+    // Both halves can prepare on the same thread, since preparing does not block.
+    let (mut ours, mut theirs) = (0, 1);
+    let first = us.prepare(&mut ours).unwrap();
+    let second = them.prepare(&mut theirs).unwrap();
+    first.wait().unwrap().unwrap().commit().unwrap();
+    assert!(second.wait().unwrap().is_none());
+    assert_eq!((ours, theirs), (1, 0));
+    // Dropping a prepared swap retracts it.
+    drop(us.prepare(&mut ours).unwrap());
+    assert_eq!(us.state(), SwapState::Idle);
+    // A prepared swap cannot be joined by the same thread swapping, or by a deposit.
+    let pending = us.prepare(&mut ours).unwrap();
+    assert_eq!(them.swap(&mut theirs), Err(SwapError::WouldDeadlock));
+    let mut them = them;
+    assert_eq!(them.deposit(2).collect(), Err(CollectError(2, SwapError::Mismatch)));
+    assert!(matches!(pending.wait(), Err(SwapError::Mismatch)));
+    assert_eq!(us.current_gen(), 3);
+}
+
+#[test]
+fn test_handoff() {
+    let (giver, taker) = handoff();
+    let helper = thread::spawn(move || {
+        for job in 0..100 {
+            giver.give(job).unwrap();
+        }
+        giver
+    });
+    for job in 0..100 {
+        assert_eq!(taker.take(), Ok(job));
+    }
+    let giver = helper.join().unwrap();
+    drop(taker);
+    assert_eq!(giver.give(100), Err(GiveError(100, SwapError::Disconnected)));
+}
+
+#[test]
+fn test_swap_init() {
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        let mut value = MaybeUninit::uninit();
+        assert!(unsafe { them.swap_init(&mut value, false) }.unwrap());
+        assert_eq!(unsafe { value.assume_init_ref() }, "hello");
+        assert!(unsafe { them.swap_init(&mut value, true) }.unwrap());
+        assert_eq!(unsafe { value.assume_init_ref() }, "world");
+        assert!(!unsafe { them.swap_init(&mut value, true) }.unwrap());
+        them.swap(&mut String::from("mismatch"))
+    });
+    let mut value = MaybeUninit::new(String::from("hello"));
+    assert!(!unsafe { us.swap_init(&mut value, true) }.unwrap());
+    value.write(String::from("world"));
+    assert!(unsafe { us.swap_init(&mut value, true) }.unwrap());
+    assert_eq!(unsafe { value.assume_init_ref() }, "hello");
+    assert!(unsafe { us.swap_init(&mut value, false) }.unwrap());
+    assert_eq!(unsafe { value.assume_init_read() }, "world");
+    // Possibly uninitialized data cannot be swapped with a plain swap.
+    assert_eq!(unsafe { us.swap_init(&mut value, false) }, Err(SwapError::Mismatch));
+    assert_eq!(helper.join().unwrap(), Err(SwapError::Mismatch));
+}
+
+#[test]
+fn test_isr_swapper() {
+    let buffers = IsrSwapper::new();
+    let mut filled = vec![0];
+    // With no offer from the main loop, the interrupt handler keeps its buffer.
+    assert_eq!(buffers.try_swap_from_isr(&mut filled), Err(SwapError::WouldBlock));
+    assert_eq!(filled, [0]);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for round in 1..100 {
+                filled.push(round);
+                while buffers.try_swap_from_isr(&mut filled).is_err() {
+                    thread::yield_now();
+                }
+                assert!(filled.is_empty());
+            }
+        });
+        let mut empty = Vec::new();
+        for round in 1..100 {
+            buffers.swap_from_main(&mut empty, thread::yield_now);
+            assert_eq!(empty.last(), Some(&round));
+            empty.clear();
+        }
+    });
+    assert!(!buffers.is_offered());
+}
+
+#[test]
+fn test_swap_pump() {
+    let (us, them) = swapper();
+    let mut pump = SwapPump::new(us);
+    let ready = Arc::new(AtomicUsize::new(0));
+    let counter = ready.clone();
+    pump.on_ready(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(pump.pump(), None);
+    pump.offer(String::from("hello")).unwrap();
+    assert_eq!(pump.offer(String::from("again")), Err(String::from("again")));
+    assert_eq!(pump.pump(), None);
+    let helper = thread::spawn(move || {
+        let mut world = String::from("world");
+        them.swap(&mut world).unwrap();
+        assert_eq!(world, "hello");
+        them
+    });
+    let them = helper.join().unwrap();
+    assert_eq!(ready.load(Ordering::SeqCst), 1);
+    assert!(pump.is_pending());
+    assert_eq!(pump.pump(), Some(Ok(String::from("world"))));
+    assert!(!pump.is_pending());
+    // A swap in progress can be cancelled.
+    pump.offer(String::from("hello")).unwrap();
+    assert_eq!(pump.cancel(), Some(Err(CollectError(String::from("hello"), SwapError::WouldBlock))));
+    // Once the other half is dropped, the callback is called, and pumping returns the offered value.
+    pump.offer(String::from("hello")).unwrap();
+    drop(them);
+    assert_eq!(ready.load(Ordering::SeqCst), 2);
+    assert_eq!(pump.pump(), Some(Err(CollectError(String::from("hello"), SwapError::Disconnected))));
+}
+
+#[test]
+fn test_swap_with_cancel() {
+    let (us, them) = swapper();
+    let token = CancelToken::new();
+    assert!(!token.is_cancelled());
+    let shutdown = token.clone();
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        assert_eq!(them.swap_with_cancel(&mut data, &token), Err(SwapError::Cancelled));
+        assert_eq!(data, 1);
+        // Once cancelled, swaps are cancelled immediately.
+        assert_eq!(them.swap_with_cancel(&mut data, &token), Err(SwapError::Cancelled));
+        them
+    });
+    while us.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    shutdown.cancel();
+    assert!(shutdown.is_cancelled());
+    let them = helper.join().unwrap();
+    assert_eq!(us.state(), SwapState::Idle);
+    // A token which is not cancelled does not affect the swap.
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        them.swap_with_cancel(&mut data, &CancelToken::new()).unwrap();
+        assert_eq!(data, 2);
+        // A swap blocked waiting for a partner which is dropped returns, without being cancelled.
+        assert_eq!(them.swap_with_cancel(&mut data, &CancelToken::new()), Err(SwapError::Disconnected));
+    });
+    let mut data = 2;
+    us.swap(&mut data).unwrap();
+    assert_eq!(data, 1);
+    while us.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    drop(us);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swap_request() {
+    enum Message {
+        Swap(SwapRequest<u32>),
+    }
+    impl SwapMessage<u32> for Message {
+        fn swap_request(request: SwapRequest<u32>) -> Message {
+            Message::Swap(request)
+        }
+    }
+    let (sender, receiver) = mpsc::channel();
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        let Message::Swap(request) = receiver.recv().unwrap();
+        request.fulfill(&mut data).unwrap();
+        assert_eq!(data, 0);
+        // Dropping a request without fulfilling it disconnects the requester.
+        drop(receiver.recv().unwrap());
+    });
+    let mut data = 0;
+    SwapRequest::request(&sender, &mut data).unwrap();
+    assert_eq!(data, 1);
+    assert_eq!(SwapRequest::request(&sender, &mut data), Err(SwapError::Disconnected));
+    helper.join().unwrap();
+    // Once the receiver has hung up, requests fail immediately.
+    assert_eq!(SwapRequest::request(&sender, &mut data), Err(SwapError::Disconnected));
+    assert_eq!(data, 1);
+}
+
+#[test]
+fn test_swapper_set_affinity() {
+    let set = SwapperSet::with_policy(PreferSameNode::new(Duration::from_secs(60)));
+    let a = set.register_on(0);
+    let b = set.register_on(1);
+    let c = set.register_on(0);
+    let d = set.register();
+    let (a_id, b_id) = (a.id(), b.id());
+    let b_helper = thread::spawn(move || b.swap_any(&mut 1).unwrap());
+    while set.waiting() != [b_id] {
+        thread::yield_now();
+    }
+    // A member on another node does not pair with the waiting member.
+    let a_helper = thread::spawn(move || a.swap_any(&mut 0).unwrap());
+    while set.waiting() != [b_id, a_id] {
+        thread::yield_now();
+    }
+    // A member on the same node pairs with a member which arrived later.
+    assert_eq!(c.swap_any(&mut 0), Ok(a_id));
+    assert_eq!(a_helper.join().unwrap(), c.id());
+    // A member without a node pairs with anyone.
+    assert_eq!(d.swap_any(&mut 0), Ok(b_id));
+    assert_eq!(b_helper.join().unwrap(), d.id());
+    // Members on different nodes pair once they have waited for the fallback.
+    let set = SwapperSet::with_policy(PreferSameNode::new(Duration::from_millis(10)));
+    let a = set.register_on(0);
+    let b = set.register_on(1);
+    let b_id = b.id();
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        b.swap_any(&mut data).unwrap();
+        data
+    });
+    let mut data = 0;
+    assert_eq!(a.swap_any(&mut data), Ok(b_id));
+    assert_eq!(data, 1);
+    assert_eq!(helper.join().unwrap(), 0);
+}
+
+#[test]
+fn test_pipeline() {
+    let pipeline = Pipeline::builder()
+        .stage(|input: &mut u32, output: &mut u32| {
+            *output = *input * 10;
+            Ok(())
+        })
+        .stage(|input: &mut u32, output: &mut u32| {
+            if *input > 100 {
+                return Err(*input);
+            }
+            *output = *input + 1;
+            Ok(())
+        })
+        .build();
+    assert_eq!(pipeline.stages(), 2);
+    // Fill the pipeline before pulling.
+    pipeline.push(&mut 1).unwrap();
+    pipeline.push(&mut 2).unwrap();
+    for value in 3..=5 {
+        let mut result = 0;
+        pipeline.pull(&mut result).unwrap();
+        assert_eq!(result, (value - 2) * 10 + 1);
+        let mut buffer = value;
+        pipeline.push(&mut buffer).unwrap();
+    }
+    for value in 4..=5 {
+        let mut result = 0;
+        pipeline.pull(&mut result).unwrap();
+        assert_eq!(result, value * 10 + 1);
+    }
+    // An error in a stage stops the pipeline, and is reported by finish.
+    pipeline.push(&mut 20).unwrap();
+    assert_eq!(pipeline.pull(&mut 0), Err(SwapError::Disconnected));
+    assert_eq!(pipeline.finish(), Err(PipelineError { stage: 1, error: 200 }));
+}
+
+#[test]
+fn test_swapper_pool() {
+    let pool = SwapperPool::with_capacity(2);
+    assert_eq!(pool.free(), 2);
+    let (ab, ba) = pool.acquire();
+    let id = ab.id();
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        ba.swap(&mut data).unwrap();
+        assert_eq!(data, 0);
+    });
+    let mut data = 0;
+    ab.swap(&mut data).unwrap();
+    assert_eq!(data, 1);
+    assert_eq!(ab.current_gen(), 1);
+    helper.join().unwrap();
+    // Returning one half disconnects the other.
+    assert_eq!(ab.swap(&mut data), Err(SwapError::Disconnected));
+    assert_eq!(pool.free(), 1);
+    drop(ab);
+    assert_eq!(pool.free(), 2);
+    // A recycled pair starts afresh.
+    let (ab, ba) = pool.acquire();
+    assert_eq!(ab.id(), id);
+    assert_eq!(ab.current_gen(), 0);
+    assert_eq!(ab.state(), SwapState::Idle);
+    // A swap blocked when the other half is returned is disconnected.
+    let helper = thread::spawn(move || ab.swap(&mut 0));
+    while ba.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    drop(ba);
+    assert_eq!(helper.join().unwrap(), Err(SwapError::Disconnected));
+    // Pairs beyond the capacity are freed when returned.
+    let pairs: Vec<_> = (0..3).map(|_| pool.acquire()).collect();
+    assert_eq!(pool.free(), 0);
+    drop(pairs);
+    assert_eq!(pool.free(), 2);
+    // A pair registered with an epoch is freed rather than recycled, which deregisters it.
+    let epoch = SwapEpoch::new();
+    let (ab, ba) = pool.acquire();
+    epoch.register(&ab);
+    drop((ab, ba));
+    assert_eq!(pool.free(), 2);
+    assert_eq!(epoch.wait_timeout(Duration::from_secs(1)), Ok(1));
+}
+
+#[test]
+fn test_lease() {
+    let (us, them) = swapper();
+    let helper = thread::spawn(move || {
+        let mut buffer = vec![0];
+        // Give the data back early.
+        let mut loan = them.borrow(&mut buffer).unwrap();
+        assert!(!loan.is_expired());
+        loan.get_mut().unwrap().push(4);
+        loan.give_back().unwrap();
+        assert_eq!(buffer, [0]);
+        // Keep the data until the lease expires.
+        let mut loan = them.borrow(&mut buffer).unwrap();
+        while let Some(data) = loan.get_mut() {
+            data.push(5);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(loan.is_expired());
+        drop(loan);
+        assert_eq!(buffer, [0]);
+        // Dropping the loan gives the data back.
+        let loan = them.borrow(&mut buffer).unwrap();
+        drop(loan);
+        assert_eq!(buffer, [0]);
+        // A lease can only be borrowed.
+        assert_eq!(them.swap(&mut buffer), Err(SwapError::Mismatch));
+    });
+    let mut buffer = vec![1, 2, 3];
+    us.lease(&mut buffer, Duration::from_secs(60)).unwrap();
+    assert_eq!(buffer, [1, 2, 3, 4]);
+    us.lease(&mut buffer, Duration::from_millis(10)).unwrap();
+    assert!(buffer[4..].iter().all(|&x| x == 5));
+    let length = buffer.len();
+    us.lease(&mut buffer, Duration::from_secs(60)).unwrap();
+    assert_eq!(buffer.len(), length);
+    assert_eq!(us.lease(&mut buffer, Duration::from_secs(60)), Err(SwapError::Mismatch));
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_watchdog() {
+    let (sender, receiver) = mpsc::channel();
+    let (ab, ba) = SwapperBuilder::new()
+        .name("stuck")
+        .on_blocked_longer_than(Duration::from_millis(5), move |blocked| {
+            let _ = sender.send(blocked.clone());
+        })
+        .build();
+    let id = ab.id();
+    let helper = thread::spawn(move || ab.swap(&mut 1).unwrap());
+    let blocked = receiver.recv().unwrap();
+    assert_eq!(blocked.id, id);
+    assert_eq!(blocked.name.as_deref(), Some("stuck"));
+    assert_eq!(blocked.half, 0);
+    assert!(blocked.blocked_for >= Duration::from_millis(5));
+    let mut x = 2;
+    ba.swap(&mut x).unwrap();
+    assert_eq!(x, 1);
+    helper.join().unwrap();
+    // The callback is only called once per blocked swap, and not for swaps which don't block.
+    assert!(receiver.recv_timeout(Duration::from_millis(20)).is_err());
+    // A panicking callback does not stop the watchdog.
+    let (sender, receiver) = mpsc::channel();
+    let (ab, ba) = SwapperBuilder::new()
+        .on_blocked_longer_than(Duration::from_millis(5), move |blocked| {
+            let _ = sender.send(blocked.half);
+            panic!("Callback panicked");
+        })
+        .build();
+    let helper = thread::spawn(move || {
+        ab.swap(&mut 1).unwrap();
+        ab.swap(&mut 1).unwrap();
+    });
+    for _ in 0..2 {
+        assert_eq!(receiver.recv().unwrap(), 0);
+        ba.swap(&mut 2).unwrap();
+    }
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swap_or_default() {
+    let (ab, ba) = swapper();
+    let helper = thread::spawn(move || ba.swap_or_else(String::from("theirs"), |_| unreachable!()));
+    assert_eq!(ab.swap_or_default(String::from("ours")), "theirs");
+    assert_eq!(helper.join().unwrap(), "ours");
+    assert_eq!(ab.swap_or_default(String::from("ours")), "");
+    assert_eq!(ab.swap_or_else(String::from("ours"), |err| format!("{:?}", err)), "Disconnected");
+}
+
+#[test]
+fn test_gather() {
+    let (mut coordinator, mut workers) = gather(3);
+    assert_eq!(coordinator.len(), 3);
+    let last = workers.pop().unwrap();
+    let helpers: Vec<_> = workers
+        .into_iter()
+        .map(|worker| {
+            thread::spawn(move || {
+                let mut token = worker.index() * 10;
+                worker.swap(&mut token).unwrap();
+                assert_eq!(token, worker.index());
+            })
+        })
+        .collect();
+    // The coordinator waits for the last worker to arrive, even though the others already have.
+    let slow = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        let mut token = 20;
+        last.swap(&mut token).unwrap();
+        assert_eq!(token, 2);
+        last
+    });
+    assert_eq!(coordinator.gather(vec![0, 1, 2]).unwrap(), [0, 10, 20]);
+    for helper in helpers {
+        helper.join().unwrap();
+    }
+    drop(slow.join().unwrap());
+    let err = coordinator.gather(vec![3, 4, 5]).unwrap_err();
+    assert_eq!(err.values, [3, 4, 5]);
+    assert_eq!(err.failures, [(0, SwapError::Disconnected), (1, SwapError::Disconnected), (2, SwapError::Disconnected)]);
+}
+
+#[test]
+fn test_history() {
+    let (mut ab, ba) = SwapperBuilder::new().history(2).build();
+    let helper = thread::spawn(move || {
+        for i in 0..3 {
+            ba.swap(&mut i.clone()).unwrap();
+        }
+        thread::current().id()
+    });
+    for i in 0..3 {
+        assert_eq!(ab.deposit(i).collect().unwrap(), i);
+    }
+    let helper = helper.join().unwrap();
+    let history = ab.history();
+    // Only the two most recent swaps are kept.
+    assert_eq!(history.iter().map(|record| record.generation).collect::<Vec<_>>(), [1, 2]);
+    for record in &history {
+        assert_eq!(record.threads[1], Some(helper));
+        assert!(record.threads[0].is_none() || record.threads[0] == Some(thread::current().id()));
+    }
+    assert!(history[0].at <= history[1].at);
+    // Pairs which are not tracking have no history.
+    let (cd, dc) = swapper();
+    let helper = thread::spawn(move || dc.swap(&mut 1).unwrap());
+    cd.swap(&mut 2).unwrap();
+    helper.join().unwrap();
+    assert!(cd.history().is_empty());
+}
+
+#[test]
+fn test_halo_exchange() {
+    let mut cells: Vec<u32> = (0..10).collect();
+    let halos = halo_exchange(3, 2);
+    assert_eq!(halos[1].width(), 2);
+    let seen: Vec<_> = thread::scope(|scope| {
+        let (first, rest) = cells.split_at_mut(4);
+        let (second, third) = rest.split_at_mut(1);
+        let workers: Vec<_> = vec![first, second, third]
+            .into_iter()
+            .zip(halos)
+            .map(|(chunk, mut halos)| {
+                scope.spawn(move || {
+                    let mut seen = Vec::new();
+                    for _ in 0..2 {
+                        halos.exchange_halos(chunk).unwrap();
+                        seen.push((halos.left_halo().to_vec(), halos.right_halo().to_vec()));
+                        for cell in chunk.iter_mut() {
+                            *cell += 10;
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+    // The middle chunk is narrower than the halos, so sends its only cell both ways.
+    assert_eq!(seen[0], [(vec![], vec![4]), (vec![], vec![14])]);
+    assert_eq!(seen[1], [(vec![2, 3], vec![5, 6]), (vec![12, 13], vec![15, 16])]);
+    assert_eq!(seen[2], [(vec![4], vec![]), (vec![14], vec![])]);
+}
+
+#[test]
+fn test_inspect() {
+    let (mut ab, ba) = swapper();
+    // The inspecting half arrives first, and waits for the other half's data.
+    let helper = thread::spawn(move || {
+        let lens: Vec<_> = (0..3).map(|_| ba.inspect(|token: &String| token.len())).collect();
+        let panicked = panic::catch_unwind(panic::AssertUnwindSafe(|| ba.inspect(|_| panic!("oops"))));
+        assert!(panicked.is_err());
+        (ba, lens)
+    });
+    thread::sleep(Duration::from_millis(10));
+    let mut token = String::from("hello");
+    ab.swap(&mut token).unwrap();
+    assert_eq!(token, "hello");
+    token.push('!');
+    ab.swap(&mut token).unwrap();
+    assert_eq!(ab.deposit(String::from("deposit")).collect().unwrap(), "deposit");
+    assert_eq!(ab.swap(&mut token), Err(SwapError::Aborted));
+    let (ba, lens) = helper.join().unwrap();
+    assert_eq!(lens, [Ok(5), Ok(6), Ok(7)]);
+    // Inspecting does not count as a swap.
+    assert_eq!(ab.current_gen(), 0);
+    // Both halves inspecting is a mismatch.
+    let helper = thread::spawn(move || ab.inspect(|token| token.len()));
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(ba.inspect(|token| token.len()), Err(SwapError::Mismatch));
+    drop(ba);
+    assert_eq!(helper.join().unwrap(), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_swapper_set_priority() {
+    let set = SwapperSet::new();
+    let producer = set.register();
+    let producer_id = producer.id();
+    let (mut low, mut high) = (set.register(), set.register());
+    let (low_id, high_id) = (low.id(), high.id());
+    low.set_priority(1);
+    high.set_priority(5);
+    assert_eq!(high.priority(), 5);
+    let wait_for = |count| {
+        while set.waiting().len() < count {
+            thread::yield_now();
+        }
+    };
+    // Without aging, the higher priority member is always paired first.
+    set.set_priority_aging(None);
+    let low = thread::spawn(move || {
+        let results = [low.swap_with(producer_id, &mut 1), low.swap_with(producer_id, &mut 1)];
+        (low, results)
+    });
+    wait_for(1);
+    let high = thread::spawn(move || (high.swap_with(producer_id, &mut 2), high));
+    wait_for(2);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(producer.swap_any(&mut 0), Ok(high_id));
+    let (result, high) = high.join().unwrap();
+    assert_eq!(result, Ok(()));
+    assert_eq!(producer.swap_any(&mut 0), Ok(low_id));
+    // With aging, a low priority member which has waited long enough is paired first.
+    set.set_priority_aging(Some(Duration::from_millis(1)));
+    wait_for(1);
+    thread::sleep(Duration::from_millis(20));
+    let high = thread::spawn(move || (high.swap_with(producer_id, &mut 2), high));
+    wait_for(2);
+    assert_eq!(producer.swap_any(&mut 0), Ok(low_id));
+    assert_eq!(producer.swap_any(&mut 0), Ok(high_id));
+    let (_, results) = low.join().unwrap();
+    assert_eq!(results, [Ok(()), Ok(())]);
+    assert_eq!(high.join().unwrap().0, Ok(()));
+}
+
+#[test]
+fn test_split_off() {
+    let (ab, ba) = swapper::<String>();
+    let helper = thread::spawn(move || {
+        let lane = ba.split_off::<u32>().unwrap();
+        assert_eq!(ba.split_off::<u32>().unwrap_err(), SwapError::Mismatch);
+        assert_eq!(ba.split_off::<u64>().unwrap_err(), SwapError::TypeMismatch { ours: "u64", theirs: "u32" });
+        let mut token = String::from("theirs");
+        ba.swap(&mut token).unwrap();
+        assert_eq!(token, "ours");
+        lane
+    });
+    let lane = ab.split_off::<u32>().unwrap();
+    assert_eq!(ab.swap(&mut String::new()), Err(SwapError::Mismatch));
+    assert_eq!(ab.split_off::<u32>().unwrap_err(), SwapError::TypeMismatch { ours: "u32", theirs: "u64" });
+    let mut token = String::from("ours");
+    ab.swap(&mut token).unwrap();
+    assert_eq!(token, "theirs");
+    // Splitting off lanes does not count as swapping.
+    assert_eq!(ab.current_gen(), 1);
+    let their_lane = helper.join().unwrap();
+    let helper = thread::spawn(move || their_lane.swap(&mut 1).unwrap());
+    let mut x = 2;
+    lane.swap(&mut x).unwrap();
+    assert_eq!(x, 1);
+    helper.join().unwrap();
+}
+
+#[test]
+fn test_swap_observer() {
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(&'static str, u64, usize)>>);
+    impl Recorder {
+        fn record(&self, step: &'static str, pair: u64, half: usize) {
+            self.0.lock().unwrap().push((step, pair, half));
+        }
+        fn steps(&self, pair: u64) -> Vec<(&'static str, usize)> {
+            let steps = self.0.lock().unwrap();
+            steps.iter().filter(|step| step.1 == pair).map(|step| (step.0, step.2)).collect()
+        }
+    }
+    impl SwapObserver for Recorder {
+        fn on_offer(&self, pair: u64, half: usize) {
+            self.record("offer", pair, half);
+        }
+        fn on_park(&self, pair: u64, half: usize) {
+            self.record("park", pair, half);
+        }
+        fn on_wake(&self, pair: u64, half: usize, parked: Duration) {
+            assert!(parked >= Duration::from_millis(10));
+            self.record("wake", pair, half);
+        }
+        fn on_swap(&self, pair: u64, half: usize, generation: u64) {
+            assert_eq!(generation, 0);
+            self.record("swap", pair, half);
+        }
+    }
+    let ours = Arc::new(Recorder::default());
+    let (ab, ba) = SwapperBuilder::new().observer(ours.clone()).build();
+    let id = ab.id();
+    let helper = thread::spawn(move || ab.swap(&mut 1).unwrap());
+    while ba.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    thread::sleep(Duration::from_millis(20));
+    ba.swap(&mut 2).unwrap();
+    helper.join().unwrap();
+    assert_eq!(ours.steps(id), [("offer", 0), ("park", 0), ("swap", 1), ("wake", 0)]);
+    // A panicking observer does not disturb the swap.
+    struct Panicker;
+    impl SwapObserver for Panicker {
+        fn on_park(&self, _pair: u64, _half: usize) {
+            panic!("Observer panicked");
+        }
+        fn on_swap(&self, _pair: u64, _half: usize, _generation: u64) {
+            panic!("Observer panicked");
+        }
+    }
+    let (ab, ba) = SwapperBuilder::new().observer(Arc::new(Panicker)).build();
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        ab.swap(&mut data).unwrap();
+        data
+    });
+    let mut data = 2;
+    ba.swap(&mut data).unwrap();
+    assert_eq!(data, 1);
+    assert_eq!(helper.join().unwrap(), 2);
+}
+
+#[test]
+fn test_config_swap() {
+    let (mut writer, mut readers) = config_swap(3, vec![1]);
+    assert_eq!(writer.len(), 3);
+    drop(readers.remove(1));
+    assert!(!readers[0].checkpoint());
+    let helpers: Vec<_> = readers
+        .into_iter()
+        .map(|mut reader| {
+            thread::spawn(move || {
+                assert_eq!(*reader, [1]);
+                while !reader.checkpoint() {
+                    thread::yield_now();
+                }
+                assert_eq!(*reader, [2]);
+                assert_eq!(reader.version(), 1);
+                assert!(!reader.checkpoint());
+            })
+        })
+        .collect();
+    let error = writer.publish(vec![2]).unwrap_err();
+    assert_eq!(error.values, [vec![1], vec![2], vec![1]]);
+    assert_eq!(error.failures, [(1, SwapError::Disconnected)]);
+    assert_eq!(writer.version(), 1);
+    for helper in helpers {
+        helper.join().unwrap();
+    }
+}
+
+#[test]
+fn test_keyed_exchange() {
+    let exchange = KeyedExchange::new();
+    let timeout = Duration::from_millis(10);
+    let partner = PartnerState::NeverArrived;
+    assert_eq!(exchange.exchange_timeout(1, &mut 10, timeout), Err(SwapError::Timeout { partner }));
+    assert_eq!(exchange.waiting(), 0);
+    let helpers: Vec<_> = (0..2)
+        .map(|key| {
+            let exchange = exchange.clone();
+            thread::spawn(move || {
+                let mut token = key * 10;
+                exchange.exchange(key, &mut token).unwrap();
+                assert_eq!(token, key * 10 + 1);
+            })
+        })
+        .collect();
+    while exchange.waiting() < 2 {
+        thread::yield_now();
+    }
+    // Each thread swaps with the one waiting with the same key.
+    let mut token = 11;
+    exchange.exchange(1, &mut token).unwrap();
+    assert_eq!(token, 10);
+    let mut token = 1;
+    exchange.exchange_timeout(0, &mut token, Duration::from_secs(10)).unwrap();
+    assert_eq!(token, 0);
+    for helper in helpers {
+        helper.join().unwrap();
+    }
+}
+
+#[test]
+fn test_group_shutdown() {
+    let set = SwapperSet::new();
+    let (a, b) = (set.register(), set.register());
+    let keyed = KeyedExchange::new();
+    let mut participants = shuffle_seeded(3, 0);
+    let last = participants.pop().unwrap();
+    let (leader, workers) = broadcast(1);
+    let (_coordinator, mut gatherers) = gather(2);
+    let epoch = Arc::new(SwapEpoch::new());
+    let (ab, _ba) = swapper::<u32>();
+    epoch.register(&ab);
+    let mut helpers = Vec::new();
+    for worker in workers {
+        helpers.push(thread::spawn(move || worker.swap(&mut 6)));
+    }
+    let gatherer = gatherers.pop().unwrap();
+    helpers.push(thread::spawn(move || gatherer.swap(&mut 7)));
+    let coordinator = epoch.clone();
+    helpers.push(thread::spawn(move || coordinator.wait().map(|_| ())));
+    helpers.push(thread::spawn(move || a.swap_any(&mut 1).map(|_| ())));
+    let exchange = keyed.clone();
+    helpers.push(thread::spawn(move || exchange.exchange("key", &mut 2)));
+    for mut participant in participants {
+        helpers.push(thread::spawn(move || participant.shuffle(&mut 3).map(|_| ())));
+    }
+    while set.waiting().is_empty() || keyed.waiting() == 0 {
+        thread::yield_now();
+    }
+    // Every parked thread is woken, whichever group it is waiting in.
+    set.shutdown();
+    keyed.shutdown();
+    last.shutdown();
+    leader.shutdown();
+    gatherers[0].shutdown();
+    epoch.shutdown();
+    for helper in helpers {
+        assert_eq!(helper.join().unwrap(), Err(SwapError::Shutdown));
+    }
+    assert!(set.is_shutdown() && keyed.is_shutdown() && epoch.is_shutdown());
+    assert_eq!(b.swap_any(&mut 4), Err(SwapError::Shutdown));
+    assert_eq!(keyed.exchange("key", &mut 5), Err(SwapError::Shutdown));
+    assert_eq!(gatherers[0].swap(&mut 8), Err(SwapError::Shutdown));
+    assert_eq!(epoch.wait(), Err(SwapError::Shutdown));
+}
+
+#[test]
+fn test_work_swapping() {
+    let ran = Arc::new(Mutex::new(vec![0; 3]));
+    let mut workers = work_swapping(3);
+    for job in 0..30 {
+        let ran = ran.clone();
+        workers[0].token().push(move |_: &mut _| {
+            thread::sleep(Duration::from_millis(1));
+            ran.lock().unwrap()[job % 3] += 1;
+        });
+    }
+    assert_eq!(workers[0].token().len(), 30);
+    let helpers: Vec<_> = workers
+        .into_iter()
+        .map(|mut worker| thread::spawn(move || (worker.index(), worker.run())))
+        .collect();
+    let counts: Vec<_> = helpers.into_iter().map(|helper| helper.join().unwrap()).collect();
+    assert_eq!(*ran.lock().unwrap(), [10, 10, 10]);
+    assert_eq!(counts.iter().map(|&(_, count)| count).sum::<usize>(), 30);
+    // The idle workers were given some of the seeded worker's jobs.
+    assert!(counts.iter().any(|&(index, count)| index != 0 && count > 0));
+}
+
+#[test]
+fn test_exchange() {
+    fn try_exchange<E: Exchange<u32>>(half: &E, data: &mut u32) -> Result<(), SwapError> {
+        half.try_swap(data)
+    }
+    let (ab, ba) = swapper();
+    assert_eq!(try_exchange(&ab, &mut 1), Err(SwapError::WouldBlock));
+    let helper = thread::spawn(move || {
+        let mut data = 2;
+        Exchange::swap(&ba, &mut data).unwrap();
+        data
+    });
+    let mut data = 1;
+    while try_exchange(&ab, &mut data).is_err() {
+        thread::yield_now();
+    }
+    assert_eq!(data, 2);
+    assert_eq!(helper.join().unwrap(), 1);
+    let (ab, _ba) = buffered_swapper(0);
+    try_exchange(&ab, &mut data).unwrap();
+    assert_eq!(data, 0);
+    assert_eq!(try_exchange(&ab, &mut data), Err(SwapError::WouldBlock));
+    let partner = PartnerState::NeverArrived;
+    assert_eq!(Exchange::swap_timeout(&ab, &mut data, Duration::from_millis(10)), Err(SwapError::Timeout { partner }));
+    // A member of a set swaps with any other member.
+    let set = SwapperSet::new();
+    let (a, b) = (set.register(), set.register());
+    assert_eq!(try_exchange(&a, &mut data), Err(SwapError::WouldBlock));
+    assert!(set.waiting().is_empty());
+    let helper = thread::spawn(move || {
+        let mut data = 3;
+        Exchange::swap(&b, &mut data).unwrap();
+        data
+    });
+    while try_exchange(&a, &mut data).is_err() {
+        thread::yield_now();
+    }
+    assert_eq!(data, 3);
+    assert_eq!(helper.join().unwrap(), 0);
+}
+
+#[test]
+fn test_raw_offer_slot() {
+    let slot = raw::SwapSlot::new();
+    let (mut first, mut second) = (1, 2);
+    let (first_ptr, second_ptr) = (NonNull::from(&mut first), NonNull::from(&mut second));
+    assert!(slot.is_empty());
+    assert!(unsafe { slot.offer(first_ptr) });
+    // Only one offer can be in the slot at a time.
+    assert!(!unsafe { slot.offer(second_ptr) });
+    assert!(!slot.retract(second_ptr));
+    assert_eq!(slot.take_offer(), Some(first_ptr));
+    assert!(slot.is_empty());
+    assert!(!slot.retract(first_ptr));
+    assert_eq!(slot.take_offer(), None);
+    assert!(unsafe { slot.offer(second_ptr) });
+    assert!(slot.retract(second_ptr));
+    assert!(slot.is_empty());
+    assert_eq!((first, second), (1, 2));
+}
@@ -0,0 +1,103 @@
+//! Model checking of the swap protocol, run with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+
+#![cfg(loom)]
+
+extern crate loom;
+extern crate swapper;
+
+use std::time::Duration;
+
+use loom::thread;
+use swapper::SwapError;
+use swapper::swapper;
+
+#[test]
+fn loom_swap() {
+    loom::model(|| {
+        let (us, them) = swapper();
+        let helper = thread::spawn(move || {
+            let mut hello = String::from("hello");
+            them.swap(&mut hello).unwrap();
+            assert_eq!(hello, "world");
+        });
+        let mut world = String::from("world");
+        us.swap(&mut world).unwrap();
+        assert_eq!(world, "hello");
+        helper.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_swap_twice() {
+    // The second round races the notification of the first round with the next offer.
+    loom::model(|| {
+        let (us, them) = swapper();
+        let helper = thread::spawn(move || {
+            let mut value = 1;
+            them.swap(&mut value).unwrap();
+            assert_eq!(value, 2);
+            them.swap(&mut value).unwrap();
+            assert_eq!(value, 3);
+        });
+        let mut value = 2;
+        us.swap(&mut value).unwrap();
+        assert_eq!(value, 1);
+        value = 3;
+        us.swap(&mut value).unwrap();
+        assert_eq!(value, 2);
+        helper.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_swap_dropped() {
+    // The drop of the other half races our offer, which must be retracted.
+    loom::model(|| {
+        let (us, them) = swapper::<i32>();
+        let helper = thread::spawn(move || drop(them));
+        assert_eq!(us.swap(&mut 1), Err(SwapError::Disconnected));
+        helper.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_swap_timeout() {
+    // The other half taking our offer races our retraction of it once the deadline passes.
+    loom::model(|| {
+        let (us, them) = swapper();
+        let helper = thread::spawn(move || {
+            let mut value = 1;
+            them.swap(&mut value).unwrap();
+            value
+        });
+        let mut value = 2;
+        match us.swap_timeout(&mut value, Duration::from_millis(1)) {
+            Ok(()) => (),
+            Err(SwapError::Timeout { .. }) => {
+                assert_eq!(value, 2);
+                us.swap(&mut value).unwrap();
+            }
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
+        assert_eq!(value, 1);
+        assert_eq!(helper.join().unwrap(), 2);
+    });
+}
+
+#[test]
+fn loom_swap_timeout_dropped() {
+    // The drop of the other half races the retraction of a timed-out offer.
+    loom::model(|| {
+        let (us, them) = swapper::<i32>();
+        let helper = thread::spawn(move || drop(them));
+        match us.swap_timeout(&mut 1, Duration::from_millis(1)) {
+            Err(SwapError::Disconnected) | Err(SwapError::Timeout { .. }) => (),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        helper.join().unwrap();
+    });
+}
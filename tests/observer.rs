@@ -0,0 +1,60 @@
+// The global observer sees every pair in the process, so is tested in its own binary.
+
+extern crate swapper;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use swapper::SwapObserver;
+use swapper::SwapState;
+
+#[test]
+fn test_global_observer() {
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(&'static str, u64, usize)>>);
+    impl Recorder {
+        fn record(&self, step: &'static str, pair: u64, half: usize) {
+            self.0.lock().unwrap().push((step, pair, half));
+        }
+        fn steps(&self, pair: u64) -> Vec<(&'static str, usize)> {
+            let steps = self.0.lock().unwrap();
+            steps.iter().filter(|step| step.1 == pair).map(|step| (step.0, step.2)).collect()
+        }
+    }
+    impl SwapObserver for Recorder {
+        fn on_offer(&self, pair: u64, half: usize) {
+            self.record("offer", pair, half);
+        }
+        fn on_park(&self, pair: u64, half: usize) {
+            self.record("park", pair, half);
+        }
+        fn on_wake(&self, pair: u64, half: usize, _parked: Duration) {
+            self.record("wake", pair, half);
+        }
+        fn on_swap(&self, pair: u64, half: usize, _generation: u64) {
+            self.record("swap", pair, half);
+        }
+    }
+    let global = Arc::new(Recorder::default());
+    swapper::set_global_observer(Some(global.clone()));
+    let (ab, ba) = swapper::swapper();
+    let id = ab.id();
+    let helper = thread::spawn(move || ab.swap(&mut 1).unwrap());
+    while ba.state() != SwapState::PartnerWaiting {
+        thread::yield_now();
+    }
+    // Give the other half time to park.
+    thread::sleep(Duration::from_millis(10));
+    ba.swap(&mut 2).unwrap();
+    helper.join().unwrap();
+    swapper::set_global_observer(None);
+    assert_eq!(global.steps(id), [("offer", 0), ("park", 0), ("swap", 1), ("wake", 0)]);
+    // Once removed, the global observer sees no more steps.
+    let (ab, ba) = swapper::swapper();
+    let id = ab.id();
+    let helper = thread::spawn(move || ab.swap(&mut 1).unwrap());
+    ba.swap(&mut 2).unwrap();
+    helper.join().unwrap();
+    assert_eq!(global.steps(id), []);
+}
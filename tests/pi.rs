@@ -0,0 +1,50 @@
+#![cfg(all(feature = "pi", target_os = "linux"))]
+
+extern crate swapper;
+
+use std::thread;
+use swapper::SwapError;
+use swapper::pi::pi_swapper;
+
+#[test]
+fn test_pi_swapper() {
+    let (us, them) = pi_swapper();
+    let helper = thread::spawn(move || {
+        let them = them.bind();
+        let mut value = 1;
+        for round in 0..100 {
+            them.swap(&mut value).unwrap();
+            assert_eq!(value, round * 2);
+            value = round * 2 + 3;
+        }
+    });
+    let mut value = 0;
+    for round in 0..100 {
+        us.swap(&mut value).unwrap();
+        assert_eq!(value, round * 2 + 1);
+        value = round * 2 + 2;
+    }
+    helper.join().unwrap();
+    assert_eq!(us.swap(&mut value), Err(SwapError::Disconnected));
+}
+
+#[test]
+fn test_pi_swapper_unbound() {
+    // The helper never binds its half before we wait, so we poll until it swaps.
+    let (us, them) = pi_swapper();
+    assert!(!them.is_bound());
+    let helper = thread::spawn(move || {
+        thread::yield_now();
+        them.swap(&mut 37).unwrap();
+        // The half was only bound for the swap.
+        assert!(!them.is_bound());
+        let binding = them.bind();
+        assert!(them.is_bound());
+        drop(binding);
+        assert!(!them.is_bound());
+    });
+    let mut value = 5;
+    us.swap(&mut value).unwrap();
+    assert_eq!(value, 37);
+    helper.join().unwrap();
+}
@@ -0,0 +1,42 @@
+#![cfg(all(feature = "process", target_os = "linux"))]
+
+extern crate swapper;
+
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::thread;
+use swapper::SwapError;
+use swapper::process::{ProcessSwapper, Side};
+
+fn temp_file(name: &str) -> (PathBuf, File) {
+    let path = std::env::temp_dir().join(format!("swapper-{}-{}", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+    (path, file)
+}
+
+#[test]
+fn test_process_swapper() {
+    let (path, file) = temp_file("swap");
+    let us = ProcessSwapper::<[u32; 4]>::open(&file, Side::A).unwrap();
+    let them = ProcessSwapper::<[u32; 4]>::open(&file, Side::B).unwrap();
+    let helper = thread::spawn(move || {
+        let mut value = [1; 4];
+        for round in 0..100 {
+            them.swap(&mut value).unwrap();
+            assert_eq!(value, [round * 2; 4]);
+            value = [round * 2 + 3; 4];
+        }
+    });
+    let mut value = [0; 4];
+    for round in 0..100 {
+        us.swap(&mut value).unwrap();
+        assert_eq!(value, [round * 2 + 1; 4]);
+        value = [round * 2 + 2; 4];
+    }
+    helper.join().unwrap();
+    assert_eq!(us.swap(&mut value), Err(SwapError::Disconnected));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,42 @@
+#![cfg(all(feature = "remote", unix))]
+
+extern crate swapper;
+
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use swapper::SwapError;
+use swapper::remote::{RemoteSwapper, Side};
+
+#[test]
+fn test_remote_swapper() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let ours = RemoteSwapper::new(a, Side::A);
+    let theirs = RemoteSwapper::new(b, Side::B);
+    let helper = thread::spawn(move || {
+        // Large enough to fill the socket buffer in both directions.
+        let mut data = (vec![2u8; 1 << 20], Some(String::from("theirs")));
+        theirs.swap(&mut data).unwrap();
+        assert_eq!(data, (vec![1u8; 1 << 20], None));
+        theirs.into_inner()
+    });
+    let mut data = (vec![1u8; 1 << 20], None);
+    ours.swap(&mut data).unwrap();
+    assert_eq!(data, (vec![2u8; 1 << 20], Some(String::from("theirs"))));
+    // Data which cannot be decoded is a mismatch.
+    let mut stream = helper.join().unwrap();
+    data.0.clear();
+    stream.write_all(&1u64.to_le_bytes()).unwrap();
+    stream.write_all(&[7]).unwrap();
+    assert_eq!(ours.swap(&mut data), Err(SwapError::Mismatch));
+    assert_eq!(data, (vec![], Some(String::from("theirs"))));
+    // The other end has still received our data, so it has been duplicated.
+    let mut len = [0; 8];
+    stream.read_exact(&mut len).unwrap();
+    assert!(u64::from_le_bytes(len) > 0);
+    // Closing the stream disconnects.
+    stream.shutdown(Shutdown::Both).unwrap();
+    assert_eq!(ours.swap(&mut data), Err(SwapError::Disconnected));
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "replay")]
+
+extern crate swapper;
+
+use std::thread;
+use swapper::replay::{self, Replayer};
+
+#[test]
+fn test_replay() {
+    let (ab, ba) = swapper::swapper();
+    let (mut cd, dc) = swapper::swapper();
+    assert!(!replay::is_recording());
+    replay::start_recording();
+    assert!(replay::is_recording());
+    let helper = thread::spawn(move || {
+        for i in 0..3 {
+            ba.swap(&mut i.clone()).unwrap();
+            dc.swap(&mut i.clone()).unwrap();
+        }
+        thread::current().id()
+    });
+    for i in 0..3 {
+        ab.swap(&mut i.clone()).unwrap();
+        assert_eq!(cd.deposit(i).collect().unwrap(), i);
+    }
+    let other = helper.join().unwrap();
+    let log = replay::stop_recording();
+    assert!(!replay::is_recording());
+    assert_eq!(log.len(), 6);
+    for (i, event) in log.iter().enumerate() {
+        assert_eq!(event.pair, if i % 2 == 0 { ab.id() } else { cd.id() });
+        assert_eq!(event.generation, i as u64 / 2);
+        assert!(event.taker == other || event.offerer == Some(other));
+    }
+    assert!(log.windows(2).all(|events| events[0].at <= events[1].at));
+    let mut swaps = Vec::new();
+    let mut replayer = Replayer::new(log).on_pair(cd.id(), |event| swaps.push(event.generation));
+    assert_eq!(replayer.step().map(|event| event.pair), Some(ab.id()));
+    replayer.run();
+    assert!(replayer.remaining().is_empty());
+    replayer.rewind();
+    assert_eq!(replayer.remaining().len(), 6);
+    drop(replayer);
+    assert_eq!(swaps, [0, 1, 2]);
+}
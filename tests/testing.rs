@@ -0,0 +1,68 @@
+#![cfg(feature = "testing")]
+
+extern crate swapper;
+
+use std::time::Duration;
+use swapper::Exchange;
+use swapper::PartnerState;
+use swapper::SwapError;
+use swapper::testing::mock_swapper;
+
+#[test]
+fn test_mock_swapper() {
+    let (ours, partner) = mock_swapper();
+    let mut data = 1;
+    partner.complete_swap(2);
+    partner.complete_swap_after(Duration::from_secs(5), 3);
+    partner.fail_swap(SwapError::Aborted);
+    assert_eq!(partner.scheduled(), 3);
+    ours.swap(&mut data).unwrap();
+    assert_eq!(data, 2);
+    assert_eq!(partner.received(), Some(1));
+    assert_eq!(partner.received(), None);
+    // The next swap is not due yet, so a short timeout expires in virtual time.
+    let timeout = Duration::from_secs(2);
+    let partner_state = PartnerState::NeverArrived;
+    assert_eq!(ours.swap_timeout(&mut data, timeout), Err(SwapError::Timeout { partner: partner_state }));
+    assert_eq!(partner.now(), Duration::from_secs(2));
+    assert!(partner.step());
+    assert_eq!(partner.now(), Duration::from_secs(5));
+    ours.swap_timeout(&mut data, timeout).unwrap();
+    assert_eq!(data, 3);
+    assert_eq!(ours.current_gen(), 2);
+    assert_eq!(ours.swap(&mut data), Err(SwapError::Aborted));
+    assert!(!partner.step());
+    partner.advance(Duration::from_secs(1));
+    assert_eq!(partner.now(), Duration::from_secs(6));
+    // A timeout past the end of virtual time never expires.
+    partner.complete_swap_after(Duration::from_secs(1), 4);
+    ours.swap_timeout(&mut data, Duration::MAX).unwrap();
+    assert_eq!(data, 4);
+    assert_eq!(partner.now(), Duration::from_secs(7));
+    partner.disconnect();
+    assert_eq!(ours.swap(&mut data), Err(SwapError::Disconnected));
+    assert_eq!(data, 4);
+    assert_eq!(partner.received(), Some(2));
+}
+
+#[test]
+#[should_panic(expected = "would block forever")]
+fn test_mock_swapper_blocks_forever() {
+    let (ours, _partner) = mock_swapper();
+    let _ = ours.swap(&mut 1);
+}
+
+#[test]
+fn test_mock_swapper_exchange() {
+    // Code generic over the swap provider can be tested deterministically with the mock.
+    fn try_twice<E: Exchange<u32>>(half: &E, data: &mut u32) -> Result<(), SwapError> {
+        half.try_swap(data).or_else(|_| half.try_swap(data))
+    }
+    let (ours, partner) = mock_swapper();
+    let mut data = 1;
+    assert_eq!(try_twice(&ours, &mut data), Err(SwapError::WouldBlock));
+    partner.complete_swap(2);
+    try_twice(&ours, &mut data).unwrap();
+    assert_eq!(data, 2);
+    assert_eq!(partner.now(), Duration::from_secs(0));
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "tokio-util")]
+
+extern crate swapper;
+extern crate tokio_util;
+
+use std::thread;
+use std::time::Duration;
+use swapper::CancelToken;
+use swapper::SwapError;
+use swapper::swapper;
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn test_cancellation_token() {
+    let (ab, ba) = swapper();
+    let shutdown = CancellationToken::new();
+    let token = CancelToken::from(shutdown.clone());
+    assert!(!token.is_cancelled());
+    // The token can still be used to swap until it is cancelled.
+    let helper = thread::spawn(move || {
+        let mut data = 1;
+        ab.swap_with_cancel(&mut data, &token).unwrap();
+        assert_eq!(data, 2);
+        let result = ab.swap_with_cancel(&mut data, &token);
+        assert_eq!(data, 2);
+        (result, token)
+    });
+    ba.swap(&mut 2).unwrap();
+    thread::sleep(Duration::from_millis(10));
+    shutdown.cancel();
+    let (result, token) = helper.join().unwrap();
+    assert_eq!(result, Err(SwapError::Cancelled));
+    assert!(token.is_cancelled());
+    // Cancelling the token does not cancel the tokio token.
+    let shutdown = CancellationToken::new();
+    let token = CancelToken::from(shutdown.clone());
+    token.cancel();
+    assert!(!shutdown.is_cancelled());
+}